@@ -11,8 +11,11 @@ pub struct BusAsyncHandle {
 
 impl AsyncContext for BusAsyncHandle {
     fn new(socket: NngSocket) -> Result<Self> {
-        let push = PushAsyncHandle::new(socket.clone())?;
-        let pull = PullAsyncHandle::new(socket)?;
+        Self::with_metrics(socket, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, metrics: AsyncMetrics) -> Result<Self> {
+        let push = PushAsyncHandle::with_metrics(socket.clone(), metrics.clone())?;
+        let pull = PullAsyncHandle::with_metrics(socket, metrics)?;
         let ctx = Self { push, pull };
         Ok(ctx)
     }
@@ -0,0 +1,300 @@
+//! Instrumentation of asynchronous handles.
+
+use super::*;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Counters and latency histogram shared by asynchronous handles.
+///
+/// Handles are instrumented by creating them with `create_async_with_metrics()` or
+/// `create_async_stream_with_metrics()`.  Clones record into the same metrics, so one
+/// `AsyncMetrics` can cover several handles.  `AsyncMetrics::disabled()` (the default) records
+/// nothing.
+///
+/// # Examples
+/// ```
+/// use runng::{asyncio::*, factory::latest::ProtocolFactory, *};
+///
+/// fn test() -> runng::Result<()> {
+///     let factory = ProtocolFactory::default();
+///     let metrics = AsyncMetrics::new();
+///     let mut req = factory
+///         .requester_open()?
+///         .dial("tcp://127.0.0.1:5555")?
+///         .create_async_with_metrics(metrics.clone())?;
+///     let snapshot = metrics.snapshot();
+///     println!("Sent {} requests, mean latency {:?}", snapshot.sends, snapshot.latency.mean());
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AsyncMetrics {
+    inner: Option<Arc<Inner>>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    sends: AtomicU64,
+    receives: AtomicU64,
+    dropped: AtomicU64,
+    queue_depth: AtomicUsize,
+    max_queue_depth: AtomicUsize,
+    errors: Mutex<HashMap<NngErrno, u64>>,
+    other_errors: AtomicU64,
+    latency: Mutex<LatencyHistogram>,
+}
+
+impl AsyncMetrics {
+    /// Metrics with `LatencyHistogram::DEFAULT_BOUNDS` latency buckets.
+    pub fn new() -> Self {
+        Self::with_latency_bounds(LatencyHistogram::DEFAULT_BOUNDS)
+    }
+
+    /// Metrics with latency buckets having upper bounds `bounds` (in increasing order).
+    pub fn with_latency_bounds(bounds: &[Duration]) -> Self {
+        let inner = Inner {
+            sends: AtomicU64::new(0),
+            receives: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            queue_depth: AtomicUsize::new(0),
+            max_queue_depth: AtomicUsize::new(0),
+            errors: Mutex::new(HashMap::new()),
+            other_errors: AtomicU64::new(0),
+            latency: Mutex::new(LatencyHistogram::new(bounds)),
+        };
+        Self {
+            inner: Some(Arc::new(inner)),
+        }
+    }
+
+    /// Metrics that record nothing.
+    pub fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Copy of current values.  Empty if disabled.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let inner = match &self.inner {
+            Some(inner) => inner,
+            None => return MetricsSnapshot::default(),
+        };
+        MetricsSnapshot {
+            sends: inner.sends.load(Ordering::Relaxed),
+            receives: inner.receives.load(Ordering::Relaxed),
+            dropped: inner.dropped.load(Ordering::Relaxed),
+            queue_depth: inner.queue_depth.load(Ordering::Relaxed),
+            max_queue_depth: inner.max_queue_depth.load(Ordering::Relaxed),
+            errors: inner.errors.lock().unwrap().clone(),
+            other_errors: inner.other_errors.load(Ordering::Relaxed),
+            latency: inner.latency.lock().unwrap().clone(),
+        }
+    }
+
+    /// Message sent
+    pub(crate) fn sent(&self) {
+        if let Some(inner) = &self.inner {
+            inner.sends.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Message received
+    pub(crate) fn received(&self) {
+        if let Some(inner) = &self.inner {
+            inner.receives.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Operation failed
+    pub(crate) fn error(&self, err: &Error) {
+        if let Some(inner) = &self.inner {
+            match err.errno() {
+                Some(errno) => *inner.errors.lock().unwrap().entry(errno).or_default() += 1,
+                None => {
+                    inner.other_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Record outcome of an operation that sent (`sent` is true) or received a message
+    pub(crate) fn result<T>(&self, res: &Result<T>, sent: bool) {
+        match res {
+            Ok(_) if sent => self.sent(),
+            Ok(_) => self.received(),
+            Err(err) => self.error(err),
+        }
+    }
+
+    /// Result couldn't be delivered because nothing was waiting for it or a buffer was full
+    pub(crate) fn dropped(&self) {
+        if let Some(inner) = &self.inner {
+            inner.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Results waiting to be taken
+    pub(crate) fn queue_depth(&self, depth: usize) {
+        if let Some(inner) = &self.inner {
+            inner.queue_depth.store(depth, Ordering::Relaxed);
+            inner.max_queue_depth.fetch_max(depth, Ordering::Relaxed);
+        }
+    }
+
+    /// Time from request to reply
+    pub(crate) fn latency(&self, latency: Duration) {
+        if let Some(inner) = &self.inner {
+            inner.latency.lock().unwrap().record(latency);
+        }
+    }
+
+    /// Start timing an operation.  `None` if disabled.
+    pub(crate) fn start(&self) -> Option<Instant> {
+        self.inner.as_ref().map(|_| Instant::now())
+    }
+}
+
+/// Values of `AsyncMetrics` at one point in time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Messages sent
+    pub sends: u64,
+    /// Messages received
+    pub receives: u64,
+    /// Results discarded because nothing was waiting for them or a stream's buffer was full
+    pub dropped: u64,
+    /// Received messages waiting for `receive()`
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+    /// Failed operations by error number
+    pub errors: HashMap<NngErrno, u64>,
+    /// Failed operations with errors other than `Error::Errno`
+    pub other_errors: u64,
+    /// Time between sending a request and receiving its reply
+    pub latency: LatencyHistogram,
+}
+
+impl MetricsSnapshot {
+    /// Total failed operations.
+    pub fn error_count(&self) -> u64 {
+        self.errors.values().sum::<u64>() + self.other_errors
+    }
+}
+
+/// Distribution of durations in buckets with fixed upper bounds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencyHistogram {
+    bounds: Vec<Duration>,
+    /// Count per bucket, last one has no upper bound
+    counts: Vec<u64>,
+    sum: Duration,
+}
+
+impl LatencyHistogram {
+    /// 100µs to 10s
+    pub const DEFAULT_BOUNDS: &'static [Duration] = &[
+        Duration::from_micros(100),
+        Duration::from_micros(250),
+        Duration::from_micros(500),
+        Duration::from_millis(1),
+        Duration::from_micros(2500),
+        Duration::from_millis(5),
+        Duration::from_millis(10),
+        Duration::from_millis(25),
+        Duration::from_millis(50),
+        Duration::from_millis(100),
+        Duration::from_millis(250),
+        Duration::from_millis(500),
+        Duration::from_secs(1),
+        Duration::from_millis(2500),
+        Duration::from_secs(5),
+        Duration::from_secs(10),
+    ];
+
+    /// Empty histogram with buckets having upper bounds `bounds` (in increasing order).
+    pub fn new(bounds: &[Duration]) -> Self {
+        assert!(
+            bounds.windows(2).all(|pair| pair[0] < pair[1]),
+            "Bounds must be increasing"
+        );
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: Duration::from_millis(0),
+        }
+    }
+
+    pub fn record(&mut self, value: Duration) {
+        let index = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[index] += 1;
+        self.sum += value;
+    }
+
+    /// Number of values recorded.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Sum of values recorded.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(
+                (self.sum.as_nanos() / u128::from(count)) as u64,
+            ))
+        }
+    }
+
+    /// Upper bound of each bucket (`None` for the last, unbounded one) and the number of values
+    /// less than or equal to it.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        let mut cumulative = 0;
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(index, count)| {
+                cumulative += count;
+                (self.bounds.get(index).copied(), cumulative)
+            })
+            .collect()
+    }
+
+    /// Upper bound of bucket containing quantile `q` (0 to 1).  `None` if empty or in the
+    /// unbounded bucket.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((q * count as f64).ceil() as u64).max(1);
+        self.buckets()
+            .into_iter()
+            .find(|(_, cumulative)| *cumulative >= rank)
+            .and_then(|(bound, _)| bound)
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_BOUNDS)
+    }
+}
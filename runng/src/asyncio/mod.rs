@@ -2,8 +2,10 @@
 
 pub mod aio;
 pub mod bus;
+pub mod metrics;
 pub mod pair;
 pub mod pair_stream;
+pub mod pooled;
 pub mod pull;
 pub mod pull_stream;
 pub mod push;
@@ -11,12 +13,16 @@ pub mod reply;
 pub mod reply_stream;
 pub mod request;
 pub mod simple;
+mod span;
 pub mod stream;
+pub mod topic_router;
 
 pub use self::aio::*;
 pub use self::bus::*;
+pub use self::metrics::*;
 pub use self::pair::*;
 pub use self::pair_stream::*;
+pub use self::pooled::*;
 pub use self::pull::*;
 pub use self::pull_stream::*;
 pub use self::push::*;
@@ -25,8 +31,12 @@ pub use self::reply_stream::*;
 pub use self::request::*;
 pub use self::simple::*;
 pub use self::stream::*;
+pub use self::topic_router::*;
 
-use crate::{msg::NngMsg, *};
+use crate::{
+    msg::{Envelope, NngMsg},
+    *,
+};
 use futures::{
     channel::{mpsc, oneshot},
     future,
@@ -36,17 +46,32 @@ use futures::{
 use futures_util::future::FutureExt;
 use log::debug;
 use runng_sys::*;
+use span::{Op, OpSpan};
 use std::collections::VecDeque;
 
 /// Context for asynchrounous I/O.
 pub trait AsyncContext: Sized {
     /// Create a new asynchronous context using specified socket.
     fn new(socket: NngSocket) -> Result<Self>;
+    /// Create a new asynchronous context that records its operations in `metrics`.
+    ///
+    /// Contexts that don't support metrics ignore them.
+    fn with_metrics(socket: NngSocket, metrics: AsyncMetrics) -> Result<Self> {
+        let _ = metrics;
+        Self::new(socket)
+    }
 }
 
 pub trait AsyncStreamContext: Sized {
     /// Create a new asynchronous context using specified socket.
     fn new(socket: NngSocket, buffer: usize) -> Result<Self>;
+    /// Create a new asynchronous context that records its operations in `metrics`.
+    ///
+    /// Contexts that don't support metrics ignore them.
+    fn with_metrics(socket: NngSocket, buffer: usize, metrics: AsyncMetrics) -> Result<Self> {
+        let _ = metrics;
+        Self::new(socket, buffer)
+    }
     //fn new_unbounded(socket: NngSocket) -> Result<Self>;
 }
 
@@ -76,6 +101,12 @@ pub trait AsyncSocket: Socket {
         let ctx = Self::ContextType::new(socket)?;
         Ok(ctx)
     }
+
+    /// Turns the `Socket` into an asynchronous context that records its operations in `metrics`
+    fn create_async_with_metrics(&self, metrics: AsyncMetrics) -> Result<Self::ContextType> {
+        let socket = self.socket().clone();
+        Self::ContextType::with_metrics(socket, metrics)
+    }
 }
 
 pub trait AsyncStream: Socket {
@@ -88,11 +119,26 @@ pub trait AsyncStream: Socket {
         let ctx = Self::ContextType::new(socket, buffer)?;
         Ok(ctx)
     }
+
+    /// Turns the `Socket` into an asynchronous context that records its operations in `metrics`
+    fn create_async_stream_with_metrics(
+        &self,
+        buffer: usize,
+        metrics: AsyncMetrics,
+    ) -> Result<Self::ContextType> {
+        let socket = self.socket().clone();
+        Self::ContextType::with_metrics(socket, buffer, metrics)
+    }
 }
 
-fn try_signal_complete(sender: &mut mpsc::Sender<Result<NngMsg>>, message: Result<NngMsg>) {
+fn try_signal_complete(
+    sender: &mut mpsc::Sender<Result<NngMsg>>,
+    message: Result<NngMsg>,
+    metrics: &AsyncMetrics,
+) {
     let res = sender.try_send(message);
     if let Err(err) = res {
+        metrics.dropped();
         if err.is_disconnected() {
             let message = err.into_inner();
             debug!("mpsc::disconnected {:?}", message);
@@ -106,27 +152,41 @@ fn try_signal_complete(sender: &mut mpsc::Sender<Result<NngMsg>>, message: Resul
 
 pub type AsyncMsg = future::BoxFuture<'static, Result<NngMsg>>;
 pub type AsyncUnit = future::BoxFuture<'static, Result<()>>;
+/// Asynchronous message split into its `Envelope` (see `msg::Envelope::open()`) and the rest.
+pub type AsyncEnvelopeMsg = future::BoxFuture<'static, Result<(Envelope, NngMsg)>>;
 
 #[derive(Debug, Default)]
 struct WorkQueue {
     waiting: VecDeque<oneshot::Sender<Result<NngMsg>>>,
     ready: VecDeque<Result<NngMsg>>,
+    metrics: AsyncMetrics,
 }
 
 impl WorkQueue {
+    fn new(metrics: AsyncMetrics) -> Self {
+        Self {
+            metrics,
+            ..Default::default()
+        }
+    }
+
     fn push_back(&mut self, message: Result<NngMsg>) {
         if let Some(sender) = self.waiting.pop_front() {
-            sender
-                .send(message)
-                .unwrap_or_else(|err| debug!("Dropping message: {:?}", err));
+            let metrics = &self.metrics;
+            sender.send(message).unwrap_or_else(|err| {
+                metrics.dropped();
+                debug!("Dropping message: {:?}", err)
+            });
         } else {
             self.ready.push_back(message);
+            self.metrics.queue_depth(self.ready.len());
         }
     }
 
     fn pop_front(&mut self) -> AsyncMsg {
         // If a value is ready return it immediately.  Otherwise
         if let Some(item) = self.ready.pop_front() {
+            self.metrics.queue_depth(self.ready.len());
             Box::pin(future::ready(item))
         } else {
             let (sender, receiver) = oneshot::channel();
@@ -140,11 +200,23 @@ impl WorkQueue {
 trait NngSink: Sink<Result<NngMsg>, Error = mpsc::SendError> {}
 impl<T: Sink<Result<NngMsg>, Error = mpsc::SendError>> NngSink for T {}
 
+/// Kind of asynchronous I/O operation.  Lets a queue run reads and writes independently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AioLane {
+    Read,
+    Write,
+    Other,
+}
+
 /// Represents asynchronous I/O operation performed by NngAio handle.
 /// All methods will be called from native threads, impls must be thread-safe.
 pub trait AioWork {
     fn begin(&self, aio: &NngAio);
     fn finish(&mut self, aio: &NngAio);
+    /// Lane the operation runs in.
+    fn lane(&self) -> AioLane {
+        AioLane::Other
+    }
 }
 
 /// Trait object asynchronous I/O operation
@@ -11,8 +11,11 @@ pub struct PairStreamHandle {
 
 impl AsyncStreamContext for PairStreamHandle {
     fn new(socket: NngSocket, buffer: usize) -> Result<Self> {
-        let push = PushAsyncHandle::new(socket.clone())?;
-        let pull = PullAsyncStream::new(socket, buffer)?;
+        Self::with_metrics(socket, buffer, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, buffer: usize, metrics: AsyncMetrics) -> Result<Self> {
+        let push = PushAsyncHandle::with_metrics(socket.clone(), metrics.clone())?;
+        let pull = PullAsyncStream::with_metrics(socket, buffer, metrics)?;
         let ctx = Self { push, pull };
         Ok(ctx)
     }
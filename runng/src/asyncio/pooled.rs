@@ -0,0 +1,175 @@
+//! Queue of asynchronous I/O work with several operations in flight.
+
+use super::*;
+use log::debug;
+use std::{
+    collections::VecDeque,
+    marker::PhantomPinned,
+    sync::{Arc, Mutex},
+};
+
+/// Pool of nng asynchronous I/O handles with a queue of work items per [`AioLane`](enum.AioLane.html).
+///
+/// Each lane has its own handles, so a pending read doesn't hold up a write (e.g. full-duplex
+/// stream I/O) and several accepts or dials can be in flight at once.  With more than one handle
+/// per lane, operations of a lane may complete out of order.
+///
+/// # Examples
+/// ```
+/// use runng::asyncio::*;
+///
+/// fn test() -> runng::Result<()> {
+///     let mut listener = StreamListener::alloc("tcp://127.0.0.1:0")?;
+///     listener.listen()?;
+///     let mut queue = PooledAioWorkQueue::new(2)?;
+///     // Both accepts are pending at the same time
+///     let first = listener.accept(&mut queue);
+///     let second = listener.accept(&mut queue);
+///     Ok(())
+/// }
+/// ```
+pub struct PooledAioWorkQueue {
+    lanes: Vec<Arc<Mutex<Lane>>>,
+    workers: Vec<AioArg<PooledWorker>>,
+}
+
+impl PooledAioWorkQueue {
+    /// Create queue with `aios_per_lane` nng_aio handles for each lane.
+    ///
+    /// Fails with `EINVAL` if `aios_per_lane` is zero.
+    pub fn new(aios_per_lane: usize) -> Result<Self> {
+        if aios_per_lane == 0 {
+            return Err(Error::Errno(NngErrno::EINVAL));
+        }
+        // One for each `AioLane`
+        let lanes: Vec<Arc<Mutex<Lane>>> = (0..3).map(|_| Arc::default()).collect();
+        let mut workers = Vec::new();
+        for lane in lanes.iter() {
+            for _ in 0..aios_per_lane {
+                let worker =
+                    NngAio::create(|aio| PooledWorker::new(aio, lane.clone()), native_callback)?;
+                lane.lock()
+                    .unwrap()
+                    .idle
+                    .push(WorkerPtr(&*worker as *const PooledWorker));
+                workers.push(worker);
+            }
+        }
+        Ok(Self { lanes, workers })
+    }
+
+    fn lane(&self, lane: AioLane) -> &Mutex<Lane> {
+        let index = match lane {
+            AioLane::Read => 0,
+            AioLane::Write => 1,
+            AioLane::Other => 2,
+        };
+        &self.lanes[index]
+    }
+}
+
+impl std::fmt::Debug for PooledAioWorkQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let pending: Vec<usize> = self
+            .lanes
+            .iter()
+            .map(|lane| lane.lock().unwrap().pending.len())
+            .collect();
+        f.debug_struct("PooledAioWorkQueue")
+            .field("aios", &self.workers.len())
+            .field("pending", &pending)
+            .finish()
+    }
+}
+
+impl AioWorkQueue for PooledAioWorkQueue {
+    fn push_back(&mut self, obj: AioWorkRequest) {
+        let mut lane = self.lane(obj.lane()).lock().unwrap();
+        match lane.idle.pop() {
+            Some(worker) => unsafe { (*worker.0).start(obj) },
+            None => lane.pending.push_back(obj),
+        }
+    }
+}
+
+impl Drop for PooledAioWorkQueue {
+    fn drop(&mut self) {
+        // Stop workers from starting queued work while their aio are freed
+        for lane in self.lanes.iter() {
+            let mut lane = lane.lock().unwrap();
+            lane.closed = true;
+            lane.pending.clear();
+        }
+        self.workers.clear();
+    }
+}
+
+/// Pointer to pinned worker owned by `PooledAioWorkQueue`
+#[derive(Clone, Copy)]
+struct WorkerPtr(*const PooledWorker);
+
+#[derive(Default)]
+struct Lane {
+    pending: VecDeque<AioWorkRequest>,
+    idle: Vec<WorkerPtr>,
+    closed: bool,
+}
+
+struct PooledWorker {
+    aio: NngAio,
+    lane: Arc<Mutex<Lane>>,
+    current: Mutex<Option<AioWorkRequest>>,
+    _phantom: PhantomPinned,
+}
+
+impl PooledWorker {
+    fn new(aio: NngAio, lane: Arc<Mutex<Lane>>) -> Self {
+        Self {
+            aio,
+            lane,
+            current: Mutex::new(None),
+            _phantom: PhantomPinned,
+        }
+    }
+
+    fn start(&self, obj: AioWorkRequest) {
+        // Hold the lock so the callback doesn't run until `begin()` returns
+        let mut current = self.current.lock().unwrap();
+        obj.begin(&self.aio);
+        *current = Some(obj);
+    }
+
+    fn callback(&self) {
+        let work = self.current.lock().unwrap().take();
+        match work {
+            Some(mut work) => work.finish(&self.aio),
+            None => {
+                let res = unsafe { self.aio.result() };
+                debug!("Unexpected callback: {:?}", res);
+                return;
+            }
+        }
+        let mut lane = self.lane.lock().unwrap();
+        if lane.closed {
+            return;
+        }
+        match lane.pending.pop_front() {
+            Some(next) => self.start(next),
+            None => lane.idle.push(WorkerPtr(self as *const PooledWorker)),
+        }
+    }
+}
+
+impl Aio for PooledWorker {
+    fn aio(&self) -> &NngAio {
+        &self.aio
+    }
+    fn aio_mut(&mut self) -> &mut NngAio {
+        &mut self.aio
+    }
+}
+
+unsafe extern "C" fn native_callback(arg: AioArgPtr) {
+    let worker = &*(arg as *const PooledWorker);
+    worker.callback();
+}
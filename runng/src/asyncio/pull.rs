@@ -11,8 +11,8 @@ struct PullAioArg {
 }
 
 impl PullAioArg {
-    pub fn new(socket: NngSocket) -> Result<AioArg<Self>> {
-        let queue = Mutex::new(WorkQueue::default());
+    pub fn new(socket: NngSocket, metrics: AsyncMetrics) -> Result<AioArg<Self>> {
+        let queue = Mutex::new(WorkQueue::new(metrics));
         let context = NngAio::create(|aio| Self { aio, queue, socket }, read_callback)?;
         context.receive();
         Ok(context)
@@ -42,13 +42,21 @@ pub struct PullAsyncHandle {
 
 impl AsyncContext for PullAsyncHandle {
     fn new(socket: NngSocket) -> Result<Self> {
-        let aio_arg = PullAioArg::new(socket)?;
+        Self::with_metrics(socket, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, metrics: AsyncMetrics) -> Result<Self> {
+        let aio_arg = PullAioArg::new(socket, metrics)?;
         Ok(Self { aio_arg })
     }
 }
 
 pub trait ReadAsync {
     fn receive(&mut self) -> AsyncMsg;
+
+    /// Asynchronously receive a message and take its envelope.
+    fn receive_envelope(&mut self) -> AsyncEnvelopeMsg {
+        Box::pin(self.receive().map(|res| res.and_then(Envelope::open)))
+    }
 }
 
 impl ReadAsync for PullAsyncHandle {
@@ -67,9 +75,9 @@ pub struct SubAioArg {
 }
 
 impl SubAioArg {
-    pub fn new(socket: NngSocket) -> Result<AioArg<Self>> {
+    pub fn new(socket: NngSocket, metrics: AsyncMetrics) -> Result<AioArg<Self>> {
         let ctx = NngCtx::new(socket.clone())?;
-        let queue = Mutex::new(WorkQueue::default());
+        let queue = Mutex::new(WorkQueue::new(metrics));
         let context = NngAio::create(
             |aio| Self {
                 aio,
@@ -107,7 +115,10 @@ pub struct SubscribeAsyncHandle {
 
 impl AsyncContext for SubscribeAsyncHandle {
     fn new(socket: NngSocket) -> Result<Self> {
-        let aio_arg = SubAioArg::new(socket)?;
+        Self::with_metrics(socket, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, metrics: AsyncMetrics) -> Result<Self> {
+        let aio_arg = SubAioArg::new(socket, metrics)?;
         Ok(Self { aio_arg })
     }
 }
@@ -125,23 +136,24 @@ unsafe extern "C" fn read_callback(arg: AioArgPtr) {
     let aio_res = nng_aio_result(aio);
     let res = nng_int_to_result(aio_res);
     trace!("read_callback::{:?}", res);
+    ctx.queue.lock().unwrap().metrics.result(&res, false);
+    let span = OpSpan::new(Op::Pull, &ctx.socket, None);
+    span.result("receive", &res);
     match res {
         Err(res) => {
-            match res {
-                // nng_aio_close() calls nng_aio_stop which nng_aio_abort(NNG_ECANCELED) and waits.
-                // If we call start_receive() it will fail with ECANCELED and we infinite loop...
-                Error::Errno(NngErrno::ECLOSED) | Error::Errno(NngErrno::ECANCELED) => {
-                    debug!("read_callback {:?}", res);
-                }
-                _ => {
-                    trace!("read_callback::Err({:?})", res);
-                    ctx.receive();
-                }
+            // nng_aio_close() calls nng_aio_stop which nng_aio_abort(NNG_ECANCELED) and waits.
+            // If we call start_receive() it will fail with ECANCELED and we infinite loop...
+            if res.is_closed() || res.errno() == Some(NngErrno::ECANCELED) {
+                debug!("read_callback {:?}", res);
+            } else {
+                trace!("read_callback::Err({:?})", res);
+                ctx.receive();
             }
             ctx.queue.lock().unwrap().push_back(Err(res));
         }
         Ok(()) => {
             let msg = NngMsg::from_raw(nng_aio_get_msg(aio));
+            span.message(&msg);
             ctx.queue.lock().unwrap().push_back(Ok(msg));
             // Don't start next read until after notifying this one is complete.
             ctx.receive();
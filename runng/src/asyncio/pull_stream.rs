@@ -15,16 +15,22 @@ struct PullContextAioArg {
     state: PullState,
     sender: mpsc::Sender<Result<NngMsg>>,
     socket: NngSocket,
+    metrics: AsyncMetrics,
 }
 
 impl PullContextAioArg {
-    pub fn new(socket: NngSocket, sender: mpsc::Sender<Result<NngMsg>>) -> Result<AioArg<Self>> {
+    pub fn new(
+        socket: NngSocket,
+        sender: mpsc::Sender<Result<NngMsg>>,
+        metrics: AsyncMetrics,
+    ) -> Result<AioArg<Self>> {
         NngAio::create(
             |aio| Self {
                 aio,
                 state: PullState::Ready,
                 sender,
                 socket,
+                metrics,
             },
             pull_callback,
         )
@@ -56,8 +62,11 @@ pub struct PullAsyncStream {
 
 impl AsyncStreamContext for PullAsyncStream {
     fn new(socket: NngSocket, buffer: usize) -> Result<Self> {
+        Self::with_metrics(socket, buffer, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, buffer: usize, metrics: AsyncMetrics) -> Result<Self> {
         let (sender, receiver) = mpsc::channel::<Result<NngMsg>>(buffer);
-        let aio_arg = PullContextAioArg::new(socket, sender)?;
+        let aio_arg = PullContextAioArg::new(socket, sender, metrics)?;
         let receiver = Some(receiver);
         Ok(Self { aio_arg, receiver })
     }
@@ -88,27 +97,28 @@ unsafe extern "C" fn pull_callback(arg: AioArgPtr) {
             let aio = ctx.aio.nng_aio();
             let aio_res = nng_aio_result(aio);
             let res = nng_int_to_result(aio_res);
+            ctx.metrics.result(&res, false);
+            let span = OpSpan::new(Op::Pull, &ctx.socket, None);
+            span.result("receive", &res);
             match res {
                 Err(res) => {
-                    match res {
-                        // nng_aio_close() calls nng_aio_stop which nng_aio_abort(NNG_ECANCELED) and waits.
-                        // If we call start_receive() it will fail with ECANCELED and we infinite loop...
-                        Error::Errno(NngErrno::ECLOSED) | Error::Errno(NngErrno::ECANCELED) => {
-                            debug!("pull_callback {:?}", res);
-                        }
-                        _ => {
-                            trace!("pull_callback::Err({:?})", res);
-                            ctx.start_receive();
-                        }
+                    // nng_aio_close() calls nng_aio_stop which nng_aio_abort(NNG_ECANCELED) and waits.
+                    // If we call start_receive() it will fail with ECANCELED and we infinite loop...
+                    if res.is_closed() || res.errno() == Some(NngErrno::ECANCELED) {
+                        debug!("pull_callback {:?}", res);
+                    } else {
+                        trace!("pull_callback::Err({:?})", res);
+                        ctx.start_receive();
                     }
-                    try_signal_complete(&mut ctx.sender, Err(res));
+                    try_signal_complete(&mut ctx.sender, Err(res), &ctx.metrics);
                 }
                 Ok(()) => {
                     let msg = NngMsg::from_raw(nng_aio_get_msg(aio));
+                    span.message(&msg);
                     // Make sure to reset state before signaling completion.  Otherwise
                     // have race-condition where receiver can receive None promise
                     ctx.start_receive();
-                    try_signal_complete(&mut ctx.sender, Ok(msg));
+                    try_signal_complete(&mut ctx.sender, Ok(msg), &ctx.metrics);
                 }
             }
         }
@@ -128,9 +138,11 @@ impl AsyncPull for SubscribeAsyncStream {
 }
 
 impl AsyncStreamContext for SubscribeAsyncStream {
-    /// Create an asynchronous context using the specified socket.
     fn new(socket: NngSocket, buffer: usize) -> Result<Self> {
-        let ctx = PullAsyncStream::new(socket, buffer)?;
+        Self::with_metrics(socket, buffer, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, buffer: usize, metrics: AsyncMetrics) -> Result<Self> {
+        let ctx = PullAsyncStream::with_metrics(socket, buffer, metrics)?;
         let ctx = Self { ctx };
         Ok(ctx)
     }
@@ -15,16 +15,20 @@ struct PushContextAioArg {
     state: PushState,
     sender: Option<oneshot::Sender<Result<()>>>,
     socket: NngSocket,
+    metrics: AsyncMetrics,
+    span: OpSpan,
 }
 
 impl PushContextAioArg {
-    pub fn new(socket: NngSocket) -> Result<AioArg<Self>> {
+    pub fn new(socket: NngSocket, metrics: AsyncMetrics) -> Result<AioArg<Self>> {
         NngAio::create(
             |aio| Self {
                 aio,
                 state: PushState::Ready,
                 sender: None,
                 socket,
+                metrics,
+                span: OpSpan::default(),
             },
             publish_callback,
         )
@@ -34,7 +38,13 @@ impl PushContextAioArg {
         if self.state != PushState::Ready {
             panic!();
         }
+        if let Err(err) = self.socket.check_accepting() {
+            let _ = sender.send(Err(err));
+            return;
+        }
         self.sender = Some(sender);
+        self.span = OpSpan::new(Op::Push, &self.socket, None);
+        self.span.message(&msg);
         unsafe {
             self.state = PushState::Sending;
 
@@ -45,6 +55,7 @@ impl PushContextAioArg {
             }
             let nng_aio = self.aio.nng_aio();
             nng_aio_set_msg(nng_aio, msg);
+            self.socket.send_started();
             nng_send_aio(self.socket.nng_socket(), nng_aio);
         }
     }
@@ -66,9 +77,11 @@ pub struct PushAsyncHandle {
 }
 
 impl AsyncContext for PushAsyncHandle {
-    /// Create an asynchronous context using the specified socket.
     fn new(socket: NngSocket) -> Result<Self> {
-        let aio_arg = PushContextAioArg::new(socket)?;
+        Self::with_metrics(socket, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, metrics: AsyncMetrics) -> Result<Self> {
+        let aio_arg = PushContextAioArg::new(socket, metrics)?;
         Ok(Self { aio_arg })
     }
 }
@@ -77,6 +90,14 @@ impl AsyncContext for PushAsyncHandle {
 pub trait AsyncPush {
     /// Asynchronously send a message.
     fn send(&mut self, msg: NngMsg) -> AsyncUnit;
+
+    /// Prepend `envelope` to `msg` and asynchronously send it.
+    fn send_envelope(&mut self, envelope: &Envelope, mut msg: NngMsg) -> AsyncUnit {
+        match envelope.inject(&mut msg) {
+            Ok(()) => self.send(msg),
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }
 }
 
 impl AsyncPush for PushAsyncHandle {
@@ -97,6 +118,9 @@ unsafe extern "C" fn publish_callback(arg: AioArgPtr) {
         PushState::Sending => {
             let nng_aio = ctx.aio.nng_aio();
             let res = nng_int_to_result(nng_aio_result(nng_aio));
+            ctx.metrics.result(&res, true);
+            std::mem::take(&mut ctx.span).result("send", &res);
+            ctx.socket.send_finished(&res);
             if let Err(ref err) = res {
                 debug!("Push failed: {:?}", err);
                 // Nng requires that we retrieve the message and free it
@@ -106,6 +130,7 @@ unsafe extern "C" fn publish_callback(arg: AioArgPtr) {
             ctx.state = PushState::Ready;
             let res = ctx.sender.take().unwrap().send(res);
             if let Err(ref err) = res {
+                ctx.metrics.dropped();
                 // Unable to send result.  Receiver probably went away.  Not necessarily a problem.
                 debug!("Send finish failed: {:?}", err);
             }
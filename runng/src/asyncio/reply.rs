@@ -20,12 +20,13 @@ struct ReplyContextAioArg {
     reply_sender: Option<oneshot::Sender<Result<()>>>,
     socket: NngSocket,
     state: ReplyState,
+    span: OpSpan,
 }
 
 impl ReplyContextAioArg {
-    pub fn new(socket: NngSocket) -> Result<AioArg<Self>> {
+    pub fn new(socket: NngSocket, metrics: AsyncMetrics) -> Result<AioArg<Self>> {
         let ctx = NngCtx::new(socket.clone())?;
-        let queue = Mutex::new(WorkQueue::default());
+        let queue = Mutex::new(WorkQueue::new(metrics));
         let mut context = NngAio::create(
             |aio| Self {
                 aio,
@@ -34,6 +35,7 @@ impl ReplyContextAioArg {
                 reply_sender: None,
                 socket,
                 state: ReplyState::Idle,
+                span: OpSpan::default(),
             },
             reply_callback,
         )?;
@@ -64,6 +66,7 @@ impl ReplyContextAioArg {
             self.state = ReplyState::Sending;
             // Nng assumes ownership of the message
             nng_aio_set_msg(aio, msg.take());
+            self.socket.send_started();
             nng_ctx_send(self.ctx.ctx(), aio);
         }
     }
@@ -86,7 +89,10 @@ pub struct ReplyAsyncHandle {
 
 impl AsyncContext for ReplyAsyncHandle {
     fn new(socket: NngSocket) -> Result<Self> {
-        let aio_arg = ReplyContextAioArg::new(socket)?;
+        Self::with_metrics(socket, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, metrics: AsyncMetrics) -> Result<Self> {
+        let aio_arg = ReplyContextAioArg::new(socket, metrics)?;
         Ok(Self { aio_arg })
     }
 }
@@ -98,19 +104,32 @@ pub trait ReplyAsync {
     fn receive(&mut self) -> AsyncMsg;
     /// Asynchronously reply to previously received request.
     fn reply(&mut self, msg: NngMsg) -> oneshot::Receiver<Result<()>>;
+
+    /// Asynchronously receive a request and take its envelope.
+    fn receive_envelope(&mut self) -> AsyncEnvelopeMsg {
+        Box::pin(self.receive().map(|res| res.and_then(Envelope::open)))
+    }
+    /// Prepend `envelope` to `msg` and asynchronously reply with it.
+    fn reply_envelope(
+        &mut self,
+        envelope: &Envelope,
+        mut msg: NngMsg,
+    ) -> oneshot::Receiver<Result<()>> {
+        match envelope.inject(&mut msg) {
+            Ok(()) => self.reply(msg),
+            Err(err) => {
+                let (sender, receiver) = oneshot::channel();
+                let _ = sender.send(Err(err));
+                receiver
+            }
+        }
+    }
 }
 
 impl ReplyAsync for ReplyAsyncHandle {
     fn receive(&mut self) -> AsyncMsg {
         let mut queue = self.aio_arg.queue.lock().unwrap();
-        if let Some(item) = queue.ready.pop_front() {
-            Box::pin(future::ready(item))
-        } else {
-            let (sender, receiver) = oneshot::channel();
-            queue.waiting.push_back(sender);
-            let receiver = receiver.map(result::flatten_result);
-            Box::pin(receiver)
-        }
+        queue.pop_front()
     }
 
     fn reply(&mut self, msg: NngMsg) -> oneshot::Receiver<Result<()>> {
@@ -128,22 +147,25 @@ unsafe extern "C" fn reply_callback(arg: AioArgPtr) {
         ReplyState::Idle => panic!(),
         ReplyState::Receiving => {
             let res = nng_int_to_result(nng_aio_result(aio_nng));
+            ctx.queue.lock().unwrap().metrics.result(&res, false);
+            let span = OpSpan::new(Op::Reply, &ctx.socket, Some(&ctx.ctx));
+            span.result("receive", &res);
             match res {
                 Err(res) => {
-                    match res {
-                        Error::Errno(NngErrno::ECLOSED) | Error::Errno(NngErrno::ECANCELED) => {
-                            debug!("reply_callback {:?}", res);
-                        }
-                        _ => {
-                            trace!("reply_callback::Err({:?})", res);
-                            ctx.receive();
-                        }
+                    if res.is_closed() || res.errno() == Some(NngErrno::ECANCELED) {
+                        debug!("reply_callback {:?}", res);
+                    } else {
+                        trace!("reply_callback::Err({:?})", res);
+                        ctx.receive();
                     }
 
                     ctx.queue.lock().unwrap().push_back(Err(res));
                 }
                 Ok(()) => {
                     let msg = NngMsg::from_raw(nng_aio_get_msg(aio_nng));
+                    span.message(&msg);
+                    // Span covers the request until the reply is sent
+                    ctx.span = span;
                     // Reset state before signaling completion
                     ctx.state = ReplyState::Wait;
                     ctx.queue.lock().unwrap().push_back(Ok(msg));
@@ -153,6 +175,9 @@ unsafe extern "C" fn reply_callback(arg: AioArgPtr) {
         ReplyState::Wait => panic!(),
         ReplyState::Sending => {
             let res = nng_int_to_result(nng_aio_result(aio_nng));
+            ctx.queue.lock().unwrap().metrics.result(&res, true);
+            std::mem::take(&mut ctx.span).result("send", &res);
+            ctx.socket.send_finished(&res);
             if res.is_err() {
                 // Nng requires we resume ownership of the message
                 let _ = NngMsg::from_raw(nng_aio_get_msg(aio_nng));
@@ -163,7 +188,13 @@ unsafe extern "C" fn reply_callback(arg: AioArgPtr) {
             // signaling completion to avoid race condition where we say we're done, but
             // not yet ready for receive() to be called.
             ctx.state = ReplyState::Idle;
-            ctx.receive();
+            if ctx.socket.is_draining() {
+                // Shutting down, so don't take more requests
+                let err = Error::Errno(NngErrno::ECLOSED).context("receive while shutting down");
+                ctx.queue.lock().unwrap().push_back(Err(err));
+            } else {
+                ctx.receive();
+            }
             sender.send(res).unwrap();
         }
     }
@@ -17,14 +17,18 @@ struct ReplyContextAioArg {
     state: ReplyState,
     request_sender: mpsc::Sender<Result<NngMsg>>,
     reply_sender: Option<oneshot::Sender<Result<()>>>,
+    metrics: AsyncMetrics,
+    socket: NngSocket,
+    span: OpSpan,
 }
 
 impl ReplyContextAioArg {
     pub fn new(
         socket: NngSocket,
         request_sender: mpsc::Sender<Result<NngMsg>>,
+        metrics: AsyncMetrics,
     ) -> Result<AioArg<Self>> {
-        let ctx = NngCtx::new(socket)?;
+        let ctx = NngCtx::new(socket.clone())?;
         NngAio::create(
             |aio| Self {
                 aio,
@@ -32,6 +36,9 @@ impl ReplyContextAioArg {
                 state: ReplyState::Receiving,
                 request_sender,
                 reply_sender: None,
+                metrics,
+                socket,
+                span: OpSpan::default(),
             },
             reply_callback,
         )
@@ -59,6 +66,7 @@ impl ReplyContextAioArg {
             self.state = ReplyState::Sending;
             // Nng assumes ownership of the message
             nng_aio_set_msg(aio, msg.take());
+            self.socket.send_started();
             nng_ctx_send(self.ctx.ctx(), aio);
         }
     }
@@ -82,8 +90,11 @@ pub struct ReplyStreamHandle {
 
 impl AsyncStreamContext for ReplyStreamHandle {
     fn new(socket: NngSocket, buffer: usize) -> Result<Self> {
+        Self::with_metrics(socket, buffer, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, buffer: usize, metrics: AsyncMetrics) -> Result<Self> {
         let (sender, receiver) = mpsc::channel(buffer);
-        let aio_arg = ReplyContextAioArg::new(socket, sender)?;
+        let aio_arg = ReplyContextAioArg::new(socket, sender, metrics)?;
         let receiver = Some(receiver);
         let ctx = Self { aio_arg, receiver };
         Ok(ctx)
@@ -121,31 +132,37 @@ unsafe extern "C" fn reply_callback(arg: AioArgPtr) {
     match ctx.state {
         ReplyState::Receiving => {
             let res = nng_int_to_result(nng_aio_result(aio_nng));
+            ctx.metrics.result(&res, false);
+            let span = OpSpan::new(Op::Reply, &ctx.socket, Some(&ctx.ctx));
+            span.result("receive", &res);
             match res {
                 Err(res) => {
-                    match res {
-                        Error::Errno(NngErrno::ECLOSED) | Error::Errno(NngErrno::ECANCELED) => {
-                            debug!("reply_callback {:?}", res);
-                        }
-                        _ => {
-                            trace!("reply_callback::Err({:?})", res);
-                            ctx.start_receive();
-                        }
+                    if res.is_closed() || res.errno() == Some(NngErrno::ECANCELED) {
+                        debug!("reply_callback {:?}", res);
+                    } else {
+                        trace!("reply_callback::Err({:?})", res);
+                        ctx.start_receive();
                     }
 
-                    try_signal_complete(&mut ctx.request_sender, Err(res));
+                    try_signal_complete(&mut ctx.request_sender, Err(res), &ctx.metrics);
                 }
                 Ok(()) => {
                     let msg = NngMsg::from_raw(nng_aio_get_msg(aio_nng));
+                    span.message(&msg);
+                    // Span covers the request until the reply is sent
+                    ctx.span = span;
                     // Reset state before signaling completion
                     ctx.state = ReplyState::Wait;
-                    try_signal_complete(&mut ctx.request_sender, Ok(msg));
+                    try_signal_complete(&mut ctx.request_sender, Ok(msg), &ctx.metrics);
                 }
             }
         }
         ReplyState::Wait => panic!(),
         ReplyState::Sending => {
             let res = nng_int_to_result(nng_aio_result(aio_nng));
+            ctx.metrics.result(&res, true);
+            std::mem::take(&mut ctx.span).result("send", &res);
+            ctx.socket.send_finished(&res);
             if res.is_err() {
                 // Nng requires we resume ownership of the message
                 let _ = NngMsg::from_raw(nng_aio_get_msg(aio_nng));
@@ -155,7 +172,13 @@ unsafe extern "C" fn reply_callback(arg: AioArgPtr) {
             // Reset state and start receiving again before
             // signaling completion to avoid race condition where we say we're done, but
             // not yet ready for receive() to be called.
-            ctx.start_receive();
+            if ctx.socket.is_draining() {
+                // Shutting down, so don't take more requests
+                let err = Error::Errno(NngErrno::ECLOSED).context("receive while shutting down");
+                try_signal_complete(&mut ctx.request_sender, Err(err), &ctx.metrics);
+            } else {
+                ctx.start_receive();
+            }
             sender.send(res).unwrap();
         }
     }
@@ -3,6 +3,7 @@
 use super::*;
 use crate::ctx::NngCtx;
 use log::{debug, info};
+use std::time::Instant;
 
 #[derive(Debug, PartialEq)]
 enum RequestState {
@@ -18,10 +19,14 @@ struct RequestContextAioArg {
     sender: Option<oneshot::Sender<Result<NngMsg>>>,
     socket: NngSocket,
     state: RequestState,
+    metrics: AsyncMetrics,
+    /// When the current request was sent, if timing
+    started: Option<Instant>,
+    span: OpSpan,
 }
 
 impl RequestContextAioArg {
-    pub fn new(socket: NngSocket) -> Result<AioArg<Self>> {
+    pub fn new(socket: NngSocket, metrics: AsyncMetrics) -> Result<AioArg<Self>> {
         let ctx = NngCtx::new(socket.clone())?;
         NngAio::create(
             |aio| Self {
@@ -30,6 +35,9 @@ impl RequestContextAioArg {
                 sender: None,
                 socket,
                 state: RequestState::Ready,
+                metrics,
+                started: None,
+                span: OpSpan::default(),
             },
             request_callback,
         )
@@ -38,7 +46,14 @@ impl RequestContextAioArg {
         if self.state != RequestState::Ready {
             panic!();
         }
+        if let Err(err) = self.socket.check_accepting() {
+            let _ = sender.send(Err(err));
+            return;
+        }
         self.sender = Some(sender);
+        self.started = self.metrics.start();
+        self.span = OpSpan::new(Op::Request, &self.socket, Some(&self.ctx));
+        self.span.message(&msg);
         unsafe {
             let aio = self.aio.nng_aio();
             let ctx = self.ctx.ctx();
@@ -47,6 +62,7 @@ impl RequestContextAioArg {
             // Nng assumes ownership of the message
             let msg = msg.take();
             nng_aio_set_msg(aio, msg);
+            self.socket.send_started();
             nng_ctx_send(ctx, aio);
         }
     }
@@ -69,7 +85,10 @@ pub struct RequestAsyncHandle {
 
 impl AsyncContext for RequestAsyncHandle {
     fn new(socket: NngSocket) -> Result<Self> {
-        let aio_arg = RequestContextAioArg::new(socket)?;
+        Self::with_metrics(socket, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, metrics: AsyncMetrics) -> Result<Self> {
+        let aio_arg = RequestContextAioArg::new(socket, metrics)?;
         let ctx = Self { aio_arg };
         Ok(ctx)
     }
@@ -79,6 +98,18 @@ impl AsyncContext for RequestAsyncHandle {
 pub trait AsyncRequest {
     /// Asynchronously send a request and return a future for the reply.
     fn send(&mut self, msg: NngMsg) -> oneshot::Receiver<Result<NngMsg>>;
+
+    /// Prepend `envelope` to the request and asynchronously send it, then take the reply's
+    /// envelope.
+    fn send_envelope(&mut self, envelope: &Envelope, mut msg: NngMsg) -> AsyncEnvelopeMsg {
+        match envelope.inject(&mut msg) {
+            Ok(()) => Box::pin(
+                self.send(msg)
+                    .map(|res| result::flatten_result(res).and_then(Envelope::open)),
+            ),
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }
 }
 
 impl AsyncRequest for RequestAsyncHandle {
@@ -98,12 +129,17 @@ unsafe extern "C" fn request_callback(arg: AioArgPtr) {
         RequestState::Ready => panic!(),
         RequestState::Sending => {
             let res = nng_int_to_result(nng_aio_result(aionng));
+            ctx.metrics.result(&res, true);
+            ctx.span.result("send", &res);
+            ctx.socket.send_finished(&res);
             match res {
                 Err(res) => {
                     // Nng requries we resume ownership of the message
                     let _ = NngMsg::from_raw(nng_aio_get_msg(aionng));
 
                     ctx.state = RequestState::Ready;
+                    // Request is over
+                    ctx.span = OpSpan::default();
                     let sender = ctx.sender.take().unwrap();
                     sender.send(Err(res)).unwrap();
                 }
@@ -116,20 +152,29 @@ unsafe extern "C" fn request_callback(arg: AioArgPtr) {
         RequestState::Receiving => {
             let sender = ctx.sender.take().unwrap();
             let res = nng_int_to_result(nng_aio_result(aionng));
+            ctx.metrics.result(&res, false);
+            let span = std::mem::take(&mut ctx.span);
+            span.result("receive", &res);
             match res {
                 Err(res) => {
                     ctx.state = RequestState::Ready;
                     let res = sender.send(Err(res));
                     if let Err(res) = res {
+                        ctx.metrics.dropped();
                         debug!("Receive failed to send error: {:?}", res);
                     }
                 }
                 Ok(()) => {
                     let msg = NngMsg::from_raw(nng_aio_get_msg(aionng));
+                    span.pipe(&msg);
+                    if let Some(started) = ctx.started.take() {
+                        ctx.metrics.latency(started.elapsed());
+                    }
 
                     ctx.state = RequestState::Ready;
                     let res = sender.send(Ok(msg));
                     if let Err(msg) = res {
+                        ctx.metrics.dropped();
                         info!("Dropping request: {:?}", msg);
                     }
                 }
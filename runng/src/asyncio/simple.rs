@@ -14,6 +14,12 @@ impl SimpleAioWorkQueue {
         let worker = AioQueue::new()?;
         Ok(Self { worker })
     }
+
+    /// Fail operations started after this with `ETIMEDOUT` if they take longer than `timeout`
+    /// milliseconds.  See [nng_aio_set_timeout](https://nng.nanomsg.org/man/v1.2.2/nng_aio_set_timeout.3).
+    pub fn set_timeout(&self, timeout: nng_duration) {
+        self.worker.aio.set_timeout(timeout);
+    }
 }
 
 impl AioWorkQueue for SimpleAioWorkQueue {
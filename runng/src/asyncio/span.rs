@@ -0,0 +1,108 @@
+//! Spans of asynchronous operations for the optional `tracing` feature.
+//!
+//! Each request, reply, push and pull operation gets a span named `nng.<operation>` with fields
+//! `socket_id`, `ctx_id`, `pipe_id` and `msg_size`, plus an event with the result when it
+//! completes.  Request and push spans are created by the caller, so they're children of the span
+//! current there; reply and pull spans start when a message arrives.  Without the feature spans
+//! compile to nothing.
+
+use super::*;
+
+/// Kind of asynchronous operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Op {
+    Request,
+    Reply,
+    Push,
+    Pull,
+}
+
+/// Span of a single asynchronous operation.
+#[derive(Debug, Default)]
+pub(crate) struct OpSpan {
+    #[cfg(feature = "tracing")]
+    span: Option<tracing::Span>,
+}
+
+#[cfg(feature = "tracing")]
+mod enabled {
+    use super::*;
+    use tracing::{field::Empty, Level};
+
+    macro_rules! op_span {
+        ($name:expr, $socket_id:expr, $ctx_id:expr) => {
+            tracing::debug_span!(
+                $name,
+                socket_id = $socket_id,
+                ctx_id = $ctx_id,
+                pipe_id = Empty,
+                msg_size = Empty
+            )
+        };
+    }
+
+    impl OpSpan {
+        /// Start span of `op` on `socket` and optional `ctx`.
+        pub(crate) fn new(op: Op, socket: &NngSocket, ctx: Option<&NngCtx>) -> Self {
+            if !tracing::enabled!(Level::DEBUG) {
+                return Self::default();
+            }
+            let socket_id = unsafe { nng_socket_id(socket.nng_socket()) };
+            let ctx_id = ctx.map(|ctx| ctx.id());
+            let span = match op {
+                Op::Request => op_span!("nng.request", socket_id, ctx_id),
+                Op::Reply => op_span!("nng.reply", socket_id, ctx_id),
+                Op::Push => op_span!("nng.push", socket_id, ctx_id),
+                Op::Pull => op_span!("nng.pull", socket_id, ctx_id),
+            };
+            Self { span: Some(span) }
+        }
+
+        /// Record size and pipe of `msg`.
+        pub(crate) fn message(&self, msg: &NngMsg) {
+            if let Some(span) = &self.span {
+                span.record("msg_size", msg.len() as u64);
+            }
+            self.pipe(msg);
+        }
+
+        /// Record pipe `msg` arrived on.
+        pub(crate) fn pipe(&self, msg: &NngMsg) {
+            if let Some(span) = &self.span {
+                let pipe_id = unsafe { nng_pipe_id(nng_msg_get_pipe(msg.msg())) };
+                if pipe_id > 0 {
+                    span.record("pipe_id", pipe_id);
+                }
+            }
+        }
+
+        /// Record result of a step of the operation (e.g. a request being sent).
+        pub(crate) fn result<T>(&self, step: &'static str, res: &Result<T>) {
+            if let Some(span) = &self.span {
+                match res {
+                    Ok(_) => tracing::event!(parent: span, Level::DEBUG, step, "ok"),
+                    Err(err) => {
+                        tracing::event!(parent: span, Level::DEBUG, step, error = %err, "failed")
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl OpSpan {
+    #[inline]
+    pub(crate) fn new(_op: Op, _socket: &NngSocket, _ctx: Option<&NngCtx>) -> Self {
+        Self {}
+    }
+
+    #[inline]
+    pub(crate) fn message(&self, _msg: &NngMsg) {}
+
+    #[inline]
+    pub(crate) fn pipe(&self, _msg: &NngMsg) {}
+
+    #[inline]
+    pub(crate) fn result<T>(&self, _step: &'static str, _res: &Result<T>) {}
+}
@@ -1,9 +1,18 @@
 //! Byte streams.
 
 use super::*;
+use futures::{
+    future::FutureExt,
+    stream::Stream,
+    task::{Context, Poll, Waker},
+};
 use log::trace;
 use runng_derive::{NngGetOpts, NngSetOpts};
-use std::ptr;
+use std::{
+    pin::Pin,
+    ptr,
+    sync::{Arc, Mutex},
+};
 
 /// Byte stream corresponding to TCP, UNIX domain socket, named pipe, etc. connection.
 /// Wraps `nng_stream`
@@ -11,6 +20,8 @@ use std::ptr;
 #[prefix = "nng_stream_"]
 pub struct NngStream {
     stream: *mut nng_stream,
+    // Counts the stream against the limit of the `Incoming` it came from
+    permit: Option<Permit>,
 }
 
 /// List of scather/gather bytes for vectored I/O.
@@ -18,19 +29,56 @@ pub struct NngStream {
 /// https://github.com/jeikabu/runng/issues/47
 pub type IoVec = Vec<Vec<u8>>;
 
-#[allow(clippy::ptr_arg)]
-fn as_iov(vec: &IoVec) -> Vec<nng_iov> {
-    let mut iovs = Vec::new();
-    for buffer in vec.iter() {
-        let iov = nng_iov {
-            iov_buf: buffer.as_ptr() as *mut _,
-            iov_len: buffer.len(),
-        };
-        iovs.push(iov);
+/// Buffers used for stream I/O.
+///
+/// Buffers are moved into an operation and handed back once it completes, so nng reads and writes
+/// them in place.  This also keeps them alive if the future is dropped while nng still uses them.
+///
+/// # Safety
+///
+/// Memory described by `with_iov()` must be valid for reads and writes of `iov_len` bytes and must
+/// not move when `self` is moved (i.e. it must be on the heap or owned by nng).
+pub unsafe trait IoBuffers: Send + 'static {
+    /// Call `f` with the list of regions to read into or write from.
+    fn with_iov<R>(&mut self, f: impl FnOnce(&[nng_iov]) -> R) -> R;
+}
+
+fn iov(buffer: &mut [u8]) -> nng_iov {
+    nng_iov {
+        iov_buf: buffer.as_mut_ptr() as *mut _,
+        iov_len: buffer.len(),
+    }
+}
+
+unsafe impl IoBuffers for Vec<u8> {
+    fn with_iov<R>(&mut self, f: impl FnOnce(&[nng_iov]) -> R) -> R {
+        f(&[iov(self)])
+    }
+}
+
+unsafe impl IoBuffers for Box<[u8]> {
+    fn with_iov<R>(&mut self, f: impl FnOnce(&[nng_iov]) -> R) -> R {
+        f(&[iov(self)])
+    }
+}
+
+unsafe impl IoBuffers for IoVec {
+    fn with_iov<R>(&mut self, f: impl FnOnce(&[nng_iov]) -> R) -> R {
+        let iovs: Vec<nng_iov> = self.iter_mut().map(|buffer| iov(buffer)).collect();
+        f(&iovs)
     }
-    iovs
 }
 
+/// Uses the message body, so a pooled message can be filled without copying.
+unsafe impl IoBuffers for NngMsg {
+    fn with_iov<R>(&mut self, f: impl FnOnce(&[nng_iov]) -> R) -> R {
+        f(&[iov(self.as_mut_slice())])
+    }
+}
+
+/// Result of stream I/O: the buffers and number of bytes transferred.
+pub type IoResult<B> = (B, Result<usize>);
+
 impl NngStream {
     /// Send to byte stream.
     pub fn send(
@@ -38,11 +86,7 @@ impl NngStream {
         queue: &mut impl AioWorkQueue,
         iov: IoVec,
     ) -> oneshot::Receiver<Result<usize>> {
-        let (sender, receiver) = oneshot::channel();
-        let send = SendAioWork(self.stream, iov, Some(sender));
-        let send = Box::new(send);
-        queue.push_back(send);
-        receiver
+        self.start(queue, nng_stream_send, AioLane::Write, iov, |_, res| res)
     }
 
     /// Receive from byte stream.
@@ -51,10 +95,63 @@ impl NngStream {
         queue: &mut impl AioWorkQueue,
         iov: IoVec,
     ) -> oneshot::Receiver<Result<IoVec>> {
+        self.start(queue, nng_stream_recv, AioLane::Read, iov, |iov, res| {
+            res.map(|_| iov)
+        })
+    }
+
+    /// Send contents of `buffers` to byte stream.
+    ///
+    /// May send fewer bytes than available, the count is returned along with `buffers`.
+    pub fn send_buffers<B: IoBuffers>(
+        &self,
+        queue: &mut impl AioWorkQueue,
+        buffers: B,
+    ) -> oneshot::Receiver<IoResult<B>> {
+        self.start(
+            queue,
+            nng_stream_send,
+            AioLane::Write,
+            buffers,
+            |buffers, res| (buffers, res),
+        )
+    }
+
+    /// Receive from byte stream into `buffers`.
+    ///
+    /// May receive fewer bytes than requested, the count is returned along with `buffers`.
+    pub fn recv_buffers<B: IoBuffers>(
+        &self,
+        queue: &mut impl AioWorkQueue,
+        buffers: B,
+    ) -> oneshot::Receiver<IoResult<B>> {
+        self.start(
+            queue,
+            nng_stream_recv,
+            AioLane::Read,
+            buffers,
+            |buffers, res| (buffers, res),
+        )
+    }
+
+    fn start<B: IoBuffers, T: Send + 'static>(
+        &self,
+        queue: &mut impl AioWorkQueue,
+        op: StreamOp,
+        lane: AioLane,
+        buffers: B,
+        complete: fn(B, Result<usize>) -> T,
+    ) -> oneshot::Receiver<T> {
         let (sender, receiver) = oneshot::channel();
-        let recv = RecvAioWork(self.stream, iov, Some(sender));
-        let recv = Box::new(recv);
-        queue.push_back(recv);
+        let work = StreamAioWork {
+            stream: self.stream,
+            op,
+            lane,
+            buffers: Mutex::new(Some(buffers)),
+            complete,
+            sender: Some(sender),
+        };
+        queue.push_back(Box::new(work));
         receiver
     }
 
@@ -71,35 +168,9 @@ impl Drop for NngStream {
     }
 }
 
-struct SendAioWork(
-    *mut nng_stream,
-    IoVec,
-    Option<oneshot::Sender<Result<usize>>>,
-);
-
-impl AioWork for SendAioWork {
-    fn begin(&self, aio: &NngAio) {
-        trace!("Sending...");
-        unsafe {
-            let iovs = as_iov(&self.1);
-            aio.set_iov(&iovs).unwrap();
-            nng_stream_send(self.0, aio.nng_aio());
-        }
-    }
-    fn finish(&mut self, aio: &NngAio) {
-        unsafe {
-            let res = aio.result();
-            trace!("Send: {:?}", res);
-            let res = match res {
-                Ok(()) => Ok(aio.aio_count()),
-                Err(err) => Err(err),
-            };
-            if let Err(err) = self.2.take().unwrap().send(res) {
-                debug!("Finish failed: {:?}", err);
-            }
-        }
-    }
-}
+// nng_stream and nng_stream_listener functions are thread-safe
+unsafe impl Send for NngStream {}
+unsafe impl Sync for NngStream {}
 
 impl NngWrapper for NngStream {
     type NngType = *mut nng_stream;
@@ -108,31 +179,45 @@ impl NngWrapper for NngStream {
     }
 }
 
-struct RecvAioWork(
-    *mut nng_stream,
-    IoVec,
-    Option<oneshot::Sender<Result<IoVec>>>,
-);
+/// `nng_stream_send()` or `nng_stream_recv()`
+type StreamOp = unsafe extern "C" fn(*mut nng_stream, *mut nng_aio);
+
+struct StreamAioWork<B, T> {
+    stream: *mut nng_stream,
+    op: StreamOp,
+    lane: AioLane,
+    // Only locked by `begin()` and `finish()`, which nng never runs concurrently
+    buffers: Mutex<Option<B>>,
+    complete: fn(B, Result<usize>) -> T,
+    sender: Option<oneshot::Sender<T>>,
+}
 
-impl AioWork for RecvAioWork {
+impl<B: IoBuffers, T> AioWork for StreamAioWork<B, T> {
     fn begin(&self, aio: &NngAio) {
-        trace!("Receiving...");
+        trace!("Stream I/O...");
+        let mut buffers = self.buffers.lock().unwrap();
+        let buffers = buffers.as_mut().unwrap();
         unsafe {
-            let iovs = as_iov(&self.1);
-            aio.set_iov(&iovs).unwrap();
-            nng_stream_recv(self.0, aio.nng_aio());
+            // nng copies the iov array so it needn't outlive this call
+            buffers.with_iov(|iovs| aio.set_iov(iovs)).unwrap();
+            (self.op)(self.stream, aio.nng_aio());
         }
     }
     fn finish(&mut self, aio: &NngAio) {
         unsafe {
             let res = aio.result();
-            trace!("Receive: {:?}", res);
-            let res = res.map(|_| self.1.to_owned());
-            if let Err(err) = self.2.take().unwrap().send(res) {
-                debug!("Finish failed: {:?}", err);
+            trace!("Stream I/O: {:?}", res);
+            let res = res.map(|_| aio.aio_count());
+            let buffers = self.buffers.get_mut().unwrap().take().unwrap();
+            let output = (self.complete)(buffers, res);
+            if self.sender.take().unwrap().send(output).is_err() {
+                debug!("Finish failed: receiver dropped");
             }
         }
     }
+    fn lane(&self) -> AioLane {
+        self.lane
+    }
 }
 
 /// Byte stream listener.
@@ -175,11 +260,45 @@ impl StreamListener {
         &mut self,
         queue: &mut impl AioWorkQueue,
     ) -> oneshot::Receiver<Result<NngStream>> {
-        let (sender, receiver) = oneshot::channel();
-        let accept = AcceptAioWork(self.listener, Some(sender));
-        let accept = Box::new(accept);
-        queue.push_back(accept);
-        receiver
+        accept(self.listener, queue)
+    }
+
+    /// Stream of incoming connections.
+    ///
+    /// Accepts connections while fewer than `limit` of the streams it returned are alive, so at
+    /// most `limit` connections are handled at once.  Ends once the listener is closed.
+    /// Fails with `EINVAL` if `limit` is zero.
+    ///
+    /// # Examples
+    /// ```
+    /// use futures::{executor::block_on, stream::StreamExt};
+    /// use runng::asyncio::*;
+    ///
+    /// fn test() -> runng::Result<()> {
+    ///     let listener = StreamListener::alloc("tcp://127.0.0.1:0")?;
+    ///     listener.listen()?;
+    ///     let mut incoming = listener.incoming(4)?;
+    ///     block_on(async {
+    ///         while let Some(stream) = incoming.next().await {
+    ///             let stream = stream?;
+    ///             // Handle connection, dropping `stream` lets another one in...
+    ///         }
+    ///         Ok(())
+    ///     })
+    /// }
+    /// ```
+    pub fn incoming(&self, limit: usize) -> Result<Incoming<'_>> {
+        if limit == 0 {
+            return Err(Error::Errno(NngErrno::EINVAL));
+        }
+        Ok(Incoming {
+            listener: self,
+            queue: SimpleAioWorkQueue::new()?,
+            pending: None,
+            permits: Arc::new(Mutex::new(Permits::default())),
+            limit,
+            closed: false,
+        })
     }
 
     /// Close the stream.
@@ -195,6 +314,9 @@ impl Drop for StreamListener {
     }
 }
 
+unsafe impl Send for StreamListener {}
+unsafe impl Sync for StreamListener {}
+
 impl NngWrapper for StreamListener {
     type NngType = *mut nng_stream_listener;
     unsafe fn get_nng_type(&self) -> Self::NngType {
@@ -202,6 +324,99 @@ impl NngWrapper for StreamListener {
     }
 }
 
+fn accept(
+    listener: *mut nng_stream_listener,
+    queue: &mut impl AioWorkQueue,
+) -> oneshot::Receiver<Result<NngStream>> {
+    let (sender, receiver) = oneshot::channel();
+    let accept = AcceptAioWork(listener, Some(sender));
+    let accept = Box::new(accept);
+    queue.push_back(accept);
+    receiver
+}
+
+/// Stream of connections accepted by a [`StreamListener`](struct.StreamListener.html).
+/// Created by `StreamListener::incoming()`.
+pub struct Incoming<'a> {
+    listener: &'a StreamListener,
+    queue: SimpleAioWorkQueue,
+    pending: Option<oneshot::Receiver<Result<NngStream>>>,
+    permits: Arc<Mutex<Permits>>,
+    limit: usize,
+    closed: bool,
+}
+
+#[derive(Debug, Default)]
+struct Permits {
+    active: usize,
+    waker: Option<Waker>,
+}
+
+/// Held by a stream returned from `Incoming` until it's dropped.
+#[derive(Debug)]
+struct Permit(Arc<Mutex<Permits>>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut permits = self.0.lock().unwrap();
+        permits.active -= 1;
+        if let Some(waker) = permits.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<'a> Stream for Incoming<'a> {
+    type Item = Result<NngStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.closed {
+            return Poll::Ready(None);
+        }
+        if this.pending.is_none() {
+            let mut permits = this.permits.lock().unwrap();
+            if permits.active >= this.limit {
+                // Woken once a stream is dropped
+                permits.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            this.pending = Some(accept(this.listener.listener, &mut this.queue));
+        }
+        let res = match this.pending.as_mut().unwrap().poll_unpin(cx) {
+            Poll::Ready(Ok(res)) => res,
+            // Accept was dropped without completing
+            Poll::Ready(Err(_)) => Err(Error::Errno(NngErrno::ECLOSED)),
+            Poll::Pending => return Poll::Pending,
+        };
+        this.pending = None;
+        match res {
+            Ok(mut stream) => {
+                this.permits.lock().unwrap().active += 1;
+                stream.permit = Some(Permit(this.permits.clone()));
+                Poll::Ready(Some(Ok(stream)))
+            }
+            Err(ref err) if err.is_closed() => {
+                debug!("Listener closed");
+                this.closed = true;
+                Poll::Ready(None)
+            }
+            Err(err) => Poll::Ready(Some(Err(err))),
+        }
+    }
+}
+
+impl<'a> std::fmt::Debug for Incoming<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Incoming")
+            .field("listener", &self.listener)
+            .field("pending", &self.pending.is_some())
+            .field("limit", &self.limit)
+            .field("closed", &self.closed)
+            .finish()
+    }
+}
+
 struct AcceptAioWork(
     *mut nng_stream_listener,
     Option<oneshot::Sender<Result<NngStream>>>,
@@ -223,6 +438,7 @@ impl AioWork for AcceptAioWork {
                     let ptr = aio.get_output(0);
                     let stream = NngStream {
                         stream: ptr as *mut nng_stream,
+                        permit: None,
                     };
                     Ok(stream)
                 }
@@ -308,6 +524,7 @@ impl AioWork for DialAioWork {
                     let ptr = aio.get_output(0);
                     let stream = NngStream {
                         stream: ptr as *mut nng_stream,
+                        permit: None,
                     };
                     Ok(stream)
                 }
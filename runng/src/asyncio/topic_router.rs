@@ -0,0 +1,258 @@
+//! Demultiplex subscribe socket by topic.
+
+use super::*;
+use crate::protocol::{subscribe, unsubscribe, Sub0};
+use futures::{
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+type TopicSenders = Vec<(usize, mpsc::Sender<Result<NngMsg>>)>;
+
+/// Topic streams registered with a router.
+#[derive(Debug)]
+struct Routes {
+    socket: NngSocket,
+    buffer: usize,
+    next_id: usize,
+    topics: HashMap<Vec<u8>, TopicSenders>,
+    metrics: AsyncMetrics,
+    /// Receiving stopped for good, so streams have ended
+    closed: bool,
+}
+
+impl Routes {
+    fn add(&mut self, topic: &[u8]) -> Result<(usize, mpsc::Receiver<Result<NngMsg>>)> {
+        if self.closed {
+            return Err(Error::Errno(NngErrno::ECLOSED));
+        }
+        if !self.topics.contains_key(topic) {
+            // First stream for this topic
+            unsafe { subscribe(self.socket.nng_socket(), topic)? };
+        }
+        let (sender, receiver) = mpsc::channel(self.buffer);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.topics
+            .entry(topic.to_vec())
+            .or_default()
+            .push((id, sender));
+        Ok((id, receiver))
+    }
+
+    fn remove(&mut self, topic: &[u8], id: usize) {
+        let is_last = if let Some(senders) = self.topics.get_mut(topic) {
+            senders.retain(|(sender_id, _)| *sender_id != id);
+            senders.is_empty()
+        } else {
+            false
+        };
+        if is_last {
+            self.topics.remove(topic);
+            // Last stream for this topic
+            let res = unsafe { unsubscribe(self.socket.nng_socket(), topic) };
+            if let Err(err) = res {
+                debug!("Unsubscribe failed: {:?}", err);
+            }
+        }
+    }
+
+    fn route(&mut self, message: Result<NngMsg>) {
+        let metrics = &self.metrics;
+        metrics.result(&message, false);
+        match message {
+            Ok(msg) => {
+                for (topic, senders) in self.topics.iter_mut() {
+                    if !msg.body().starts_with(topic) {
+                        continue;
+                    }
+                    for (_, sender) in senders.iter_mut() {
+                        match msg.dup() {
+                            Ok(msg) => try_signal_complete(sender, Ok(msg), metrics),
+                            Err(err) => try_signal_complete(sender, Err(err), metrics),
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                // No more messages after these, so end the streams by dropping their senders
+                let is_last = matches!(
+                    err.errno(),
+                    Some(NngErrno::ECLOSED) | Some(NngErrno::ECANCELED)
+                );
+                for (_, sender) in self.topics.values_mut().flatten() {
+                    try_signal_complete(sender, Err(err.clone()), metrics);
+                }
+                if is_last {
+                    self.closed = true;
+                    self.topics.clear();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TopicRouterAioArg {
+    aio: NngAio,
+    socket: NngSocket,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl TopicRouterAioArg {
+    pub fn new(socket: NngSocket, routes: Arc<Mutex<Routes>>) -> Result<AioArg<Self>> {
+        let context = NngAio::create(
+            |aio| Self {
+                aio,
+                socket,
+                routes,
+            },
+            router_callback,
+        )?;
+        context.receive();
+        Ok(context)
+    }
+
+    fn receive(&self) {
+        unsafe {
+            nng_recv_aio(self.socket.nng_socket(), self.aio.nng_aio());
+        }
+    }
+}
+
+impl Aio for TopicRouterAioArg {
+    fn aio(&self) -> &NngAio {
+        &self.aio
+    }
+    fn aio_mut(&mut self) -> &mut NngAio {
+        &mut self.aio
+    }
+}
+
+/// Asynchronous context for subscribe socket that routes messages to a stream per topic.
+///
+/// # Examples
+/// ```
+/// use runng::{asyncio::*, protocol::Sub0};
+/// fn test() -> runng::Result<()> {
+///     let subscriber = Sub0::open()?;
+///     let router = subscriber.create_topic_router(16)?;
+///     // Subscribes to "prices." until `prices` is dropped
+///     let prices = router.topic(b"prices.")?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct TopicRouter {
+    aio_arg: AioArg<TopicRouterAioArg>,
+}
+
+impl TopicRouter {
+    /// Returns a stream of messages that start with `topic`.
+    /// Subscribes to `topic` if this is the first stream for it.
+    /// Streams end after the error once the socket is closed.
+    pub fn topic(&self, topic: &[u8]) -> Result<TopicStream> {
+        let routes = self.aio_arg.routes.clone();
+        let (id, receiver) = routes.lock().unwrap().add(topic)?;
+        Ok(TopicStream {
+            id,
+            topic: topic.to_vec(),
+            receiver,
+            routes,
+        })
+    }
+
+    /// Returns a stream of messages that start with `topic`.
+    pub fn topic_str(&self, topic: &str) -> Result<TopicStream> {
+        self.topic(topic.as_bytes())
+    }
+}
+
+impl AsyncStreamContext for TopicRouter {
+    fn new(socket: NngSocket, buffer: usize) -> Result<Self> {
+        Self::with_metrics(socket, buffer, AsyncMetrics::disabled())
+    }
+    fn with_metrics(socket: NngSocket, buffer: usize, metrics: AsyncMetrics) -> Result<Self> {
+        let routes = Routes {
+            socket: socket.clone(),
+            buffer,
+            next_id: 0,
+            topics: HashMap::new(),
+            metrics,
+            closed: false,
+        };
+        let routes = Arc::new(Mutex::new(routes));
+        let aio_arg = TopicRouterAioArg::new(socket, routes)?;
+        Ok(Self { aio_arg })
+    }
+}
+
+impl Sub0 {
+    /// Create a [`TopicRouter`](../asyncio/struct.TopicRouter.html) for this socket.
+    pub fn create_topic_router(&self, buffer: usize) -> Result<TopicRouter> {
+        TopicRouter::new(self.socket().clone(), buffer)
+    }
+}
+
+/// Stream of messages for a single topic of a [`TopicRouter`](struct.TopicRouter.html).
+/// Unsubscribes from the topic when the last stream for it is dropped.
+#[derive(Debug)]
+pub struct TopicStream {
+    id: usize,
+    topic: Vec<u8>,
+    receiver: mpsc::Receiver<Result<NngMsg>>,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl TopicStream {
+    /// Topic prefix of messages in this stream.
+    pub fn topic(&self) -> &[u8] {
+        &self.topic
+    }
+}
+
+impl Stream for TopicStream {
+    type Item = Result<NngMsg>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+impl Drop for TopicStream {
+    fn drop(&mut self) {
+        self.routes.lock().unwrap().remove(&self.topic, self.id);
+    }
+}
+
+unsafe extern "C" fn router_callback(arg: AioArgPtr) {
+    let ctx = &mut *(arg as *mut TopicRouterAioArg);
+    let aio = ctx.aio.nng_aio();
+    let res = nng_int_to_result(nng_aio_result(aio));
+    trace!("router_callback::{:?}", res);
+    let span = OpSpan::new(Op::Pull, &ctx.socket, None);
+    span.result("receive", &res);
+    match res {
+        Err(res) => {
+            // See read_callback()
+            if res.is_closed() || res.errno() == Some(NngErrno::ECANCELED) {
+                debug!("router_callback {:?}", res);
+            } else {
+                trace!("router_callback::Err({:?})", res);
+                ctx.receive();
+            }
+            ctx.routes.lock().unwrap().route(Err(res));
+        }
+        Ok(()) => {
+            let msg = NngMsg::from_raw(nng_aio_get_msg(aio));
+            span.message(&msg);
+            ctx.routes.lock().unwrap().route(Ok(msg));
+            ctx.receive();
+        }
+    }
+}
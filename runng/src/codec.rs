@@ -0,0 +1,339 @@
+//! Typed messages using [serde](https://serde.rs/).
+//!
+//! Requires the `serde` feature.
+//!
+//! # Examples
+//! ```
+//! use runng::{codec::*, factory::latest::ProtocolFactory, *};
+//! fn test() -> runng::Result<()> {
+//!     let url = "inproc://codec";
+//!     let factory = ProtocolFactory::default();
+//!     let mut pull = factory.puller_open()?;
+//!     pull.listen(url)?;
+//!     let mut push = factory.pusher_open()?;
+//!     push.dial(url)?;
+//!     let push = TypedSocket::<_, Vec<u32>, Json>::new(push);
+//!     let pull = TypedSocket::<_, Vec<u32>, Json>::new(pull);
+//!     push.send(&vec![1, 2, 3])?;
+//!     assert_eq!(pull.recv()?, vec![1, 2, 3]);
+//!     Ok(())
+//! }
+//! ```
+
+#![cfg(feature = "serde")]
+
+use crate::{
+    asyncio::*,
+    msg::{Envelope, NngMsg},
+    *,
+};
+use futures::future::{self, BoxFuture, FutureExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+
+// Marks request, reply and codec types without owning them
+type RequestReply<Req, Rep, C> = PhantomData<fn() -> (Req, Rep, C)>;
+
+/// Asynchronous operation that produces a decoded value.
+pub type AsyncValue<T> = BoxFuture<'static, Result<T>>;
+
+/// Serialization format for message bodies.
+pub trait Codec {
+    /// Serialize `value` to bytes.
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>>;
+    /// Deserialize a value from `bytes`.
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T>;
+
+    /// Serialize `value` to a new message body.
+    fn encode_msg<T: Serialize + ?Sized>(value: &T) -> Result<NngMsg> {
+        let bytes = Self::encode(value)?;
+        let mut msg = NngMsg::with_reserved(bytes.len())?;
+        msg.append_slice(&bytes)?;
+        Ok(msg)
+    }
+    /// Deserialize a value from the body of `msg`.
+    fn decode_msg<T: DeserializeOwned>(msg: &NngMsg) -> Result<T> {
+        Self::decode(msg.body())
+    }
+    /// Serialize `value` to a new message body following `envelope`.
+    fn encode_envelope<T: Serialize + ?Sized>(envelope: &Envelope, value: &T) -> Result<NngMsg> {
+        let mut msg = Self::encode_msg(value)?;
+        envelope.inject(&mut msg)?;
+        Ok(msg)
+    }
+    /// Take the envelope of `msg` and deserialize a value from the rest of the body.
+    fn decode_envelope<T: DeserializeOwned>(msg: NngMsg) -> Result<(Envelope, T)> {
+        let (envelope, msg) = Envelope::open(msg)?;
+        Ok((envelope, Self::decode_msg(&msg)?))
+    }
+}
+
+/// [bincode](https://github.com/servo/bincode) codec.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        bincode::serialize(value).map_err(|err| Error::Encode(err.to_string()))
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        bincode::deserialize(bytes).map_err(|err| Error::Decode(err.to_string()))
+    }
+}
+
+/// JSON codec.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|err| Error::Encode(err.to_string()))
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(|err| Error::Decode(err.to_string()))
+    }
+}
+
+/// [MessagePack](https://msgpack.org/) codec.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessagePack;
+
+impl Codec for MessagePack {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        rmp_serde::to_vec(value).map_err(|err| Error::Encode(err.to_string()))
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        rmp_serde::from_slice(bytes).map_err(|err| Error::Decode(err.to_string()))
+    }
+}
+
+/// [CBOR](https://cbor.io/) codec.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cbor;
+
+impl Codec for Cbor {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        serde_cbor::to_vec(&value).map_err(|err| Error::Encode(err.to_string()))
+    }
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_cbor::from_slice(bytes).map_err(|err| Error::Decode(err.to_string()))
+    }
+}
+
+/// Wraps a synchronous socket to send and receive values of type `T`.
+#[derive(Debug)]
+pub struct TypedSocket<S, T, C = Bincode> {
+    socket: S,
+    _phantom: PhantomData<fn() -> (T, C)>,
+}
+
+impl<S: Socket, T, C: Codec> TypedSocket<S, T, C> {
+    pub fn new(socket: S) -> Self {
+        Self {
+            socket,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Underlying socket.
+    pub fn get_ref(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_inner(self) -> S {
+        self.socket
+    }
+}
+
+impl<S: SendSocket, T: Serialize, C: Codec> TypedSocket<S, T, C> {
+    /// Encode and send `value`.
+    pub fn send(&self, value: &T) -> Result<()> {
+        let msg = C::encode_msg(value)?;
+        self.socket.sendmsg(msg)
+    }
+
+    /// Encode and send `value` following `envelope`.
+    pub fn send_envelope(&self, envelope: &Envelope, value: &T) -> Result<()> {
+        let msg = C::encode_envelope(envelope, value)?;
+        self.socket.sendmsg(msg)
+    }
+}
+
+impl<S: RecvSocket, T: DeserializeOwned, C: Codec> TypedSocket<S, T, C> {
+    /// Receive and decode a value.
+    pub fn recv(&self) -> Result<T> {
+        let msg = self.socket.recvmsg()?;
+        C::decode_msg(&msg)
+    }
+
+    /// Receive a value and its envelope.
+    pub fn recv_envelope(&self) -> Result<(Envelope, T)> {
+        let msg = self.socket.recvmsg()?;
+        C::decode_envelope(msg)
+    }
+}
+
+/// Wraps an asynchronous push context to send values of type `T`.
+#[derive(Debug)]
+pub struct TypedPush<T, C = Bincode, P = PushAsyncHandle> {
+    handle: P,
+    _phantom: PhantomData<fn() -> (T, C)>,
+}
+
+impl<T: Serialize, C: Codec, P: AsyncPush> TypedPush<T, C, P> {
+    pub fn new(handle: P) -> Self {
+        Self {
+            handle,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Encode and asynchronously send `value`.
+    pub fn send(&mut self, value: &T) -> AsyncUnit {
+        match C::encode_msg(value) {
+            Ok(msg) => self.handle.send(msg),
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }
+
+    /// Encode and asynchronously send `value` following `envelope`.
+    pub fn send_envelope(&mut self, envelope: &Envelope, value: &T) -> AsyncUnit {
+        match C::encode_envelope(envelope, value) {
+            Ok(msg) => self.handle.send(msg),
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }
+
+    pub fn into_inner(self) -> P {
+        self.handle
+    }
+}
+
+/// Wraps an asynchronous pull or subscribe context to receive values of type `T`.
+#[derive(Debug)]
+pub struct TypedPull<T, C = Bincode, P = PullAsyncHandle> {
+    handle: P,
+    _phantom: PhantomData<fn() -> (T, C)>,
+}
+
+impl<T: DeserializeOwned + Send + 'static, C: Codec, P: ReadAsync> TypedPull<T, C, P> {
+    pub fn new(handle: P) -> Self {
+        Self {
+            handle,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Asynchronously receive and decode a value.
+    pub fn receive(&mut self) -> AsyncValue<T> {
+        let msg = self.handle.receive();
+        Box::pin(msg.map(|res| res.and_then(|msg| C::decode_msg(&msg))))
+    }
+
+    /// Asynchronously receive a value and its envelope.
+    pub fn receive_envelope(&mut self) -> AsyncValue<(Envelope, T)> {
+        let msg = self.handle.receive();
+        Box::pin(msg.map(|res| res.and_then(C::decode_envelope)))
+    }
+
+    pub fn into_inner(self) -> P {
+        self.handle
+    }
+}
+
+/// Wraps an asynchronous request context to send requests of type `Req` and receive replies of type `Rep`.
+#[derive(Debug)]
+pub struct TypedRequest<Req, Rep, C = Bincode> {
+    handle: RequestAsyncHandle,
+    _phantom: RequestReply<Req, Rep, C>,
+}
+
+impl<Req: Serialize, Rep: DeserializeOwned + Send + 'static, C: Codec> TypedRequest<Req, Rep, C> {
+    pub fn new(handle: RequestAsyncHandle) -> Self {
+        Self {
+            handle,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Encode and send `request`, then decode the reply.
+    pub fn send(&mut self, request: &Req) -> AsyncValue<Rep> {
+        match C::encode_msg(request) {
+            Ok(msg) => {
+                let reply = self.handle.send(msg);
+                Box::pin(
+                    reply
+                        .map(|res| result::flatten_result(res).and_then(|msg| C::decode_msg(&msg))),
+                )
+            }
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }
+
+    /// Encode and send `request` following `envelope`, then decode the reply and its envelope.
+    pub fn send_envelope(
+        &mut self,
+        envelope: &Envelope,
+        request: &Req,
+    ) -> AsyncValue<(Envelope, Rep)> {
+        match C::encode_envelope(envelope, request) {
+            Ok(msg) => {
+                let reply = self.handle.send(msg);
+                Box::pin(reply.map(|res| result::flatten_result(res).and_then(C::decode_envelope)))
+            }
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }
+
+    pub fn into_inner(self) -> RequestAsyncHandle {
+        self.handle
+    }
+}
+
+/// Wraps an asynchronous reply context to receive requests of type `Req` and send replies of type `Rep`.
+#[derive(Debug)]
+pub struct TypedReply<Req, Rep, C = Bincode> {
+    handle: ReplyAsyncHandle,
+    _phantom: RequestReply<Req, Rep, C>,
+}
+
+impl<Req: DeserializeOwned + Send + 'static, Rep: Serialize, C: Codec> TypedReply<Req, Rep, C> {
+    pub fn new(handle: ReplyAsyncHandle) -> Self {
+        Self {
+            handle,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Asynchronously receive and decode a request.
+    pub fn receive(&mut self) -> AsyncValue<Req> {
+        let msg = self.handle.receive();
+        Box::pin(msg.map(|res| res.and_then(|msg| C::decode_msg(&msg))))
+    }
+
+    /// Asynchronously receive a request and its envelope.
+    pub fn receive_envelope(&mut self) -> AsyncValue<(Envelope, Req)> {
+        let msg = self.handle.receive();
+        Box::pin(msg.map(|res| res.and_then(C::decode_envelope)))
+    }
+
+    /// Encode and send `reply` to the last request received.
+    pub fn reply(&mut self, reply: &Rep) -> AsyncUnit {
+        match C::encode_msg(reply) {
+            Ok(msg) => Box::pin(self.handle.reply(msg).map(result::flatten_result)),
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }
+
+    /// Encode and send `reply` following `envelope` to the last request received.
+    pub fn reply_envelope(&mut self, envelope: &Envelope, reply: &Rep) -> AsyncUnit {
+        match C::encode_envelope(envelope, reply) {
+            Ok(msg) => Box::pin(self.handle.reply(msg).map(result::flatten_result)),
+            Err(err) => Box::pin(future::err(err)),
+        }
+    }
+
+    pub fn into_inner(self) -> ReplyAsyncHandle {
+        self.handle
+    }
+}
@@ -14,7 +14,8 @@ pub trait Ctx {
 #[derive(Debug)]
 pub struct NngCtx {
     ctx: nng_ctx,
-    // FIXME: should ctx keep a reference to the socket?
+    /// Socket the context was opened on, which tracks it for shutdown
+    socket: NngSocket,
 }
 
 impl NngCtx {
@@ -23,7 +24,8 @@ impl NngCtx {
         let mut ctx = nng_ctx::default();
         let res = unsafe { nng_ctx_open(&mut ctx, socket.nng_socket()) };
         nng_int_to_result(res)?;
-        let ctx = Self { ctx };
+        socket.register_ctx(ctx);
+        let ctx = Self { ctx, socket };
         Ok(ctx)
     }
 
@@ -45,6 +47,7 @@ impl Drop for NngCtx {
             let id = self.id();
             if id > 0 {
                 trace!("NngCtx.drop {:x}", id);
+                self.socket.unregister_ctx(id);
                 nng_ctx_close(self.ctx);
             }
         }
@@ -1,18 +1,37 @@
 //! Dialers connect to listeners.
+//!
+//! Options can generally only be set before a dialer is started, so `Dial::dialer_create()`
+//! returns a `DialerBuilder` which becomes a `StartedDialer` once started.  Either closes the
+//! dialer when dropped.
+//!
+//! # Examples
+//! ```
+//! use runng::{factory::latest::ProtocolFactory, options::*, *};
+//!
+//! fn test() -> runng::Result<()> {
+//!     let factory = ProtocolFactory::default();
+//!     let requester = factory.requester_open()?;
+//!     let mut builder = requester.dialer_create("tcp://127.0.0.1:5555")?;
+//!     builder.set_ms(NngOption::RECONNMINT, 100)?;
+//!     let dialer = builder.start()?;
+//!     assert_eq!(requester.socket().dialers().len(), 1);
+//!     dialer.close()
+//! }
+//! ```
 
 use crate::*;
 use runng_derive::{NngGetOpts, NngSetOpts};
 use runng_sys::*;
 
-/// Wraps `nng_dialer`.  See [nng_dialer](https://nng.nanomsg.org/man/v1.2.2/nng_dialer.5).
+/// Dialer not yet started.  Options can be set until `start()`.
+/// See [nng_dialer](https://nng.nanomsg.org/man/v1.2.2/nng_dialer.5).
 #[derive(Debug, NngGetOpts, NngSetOpts)]
 #[prefix = "nng_dialer_"]
-pub struct NngDialer {
-    dialer: nng_dialer,
-    socket: NngSocket,
+pub struct DialerBuilder {
+    inner: InnerDialer,
 }
 
-impl NngDialer {
+impl DialerBuilder {
     /// See [nng_dialer_create](https://nng.nanomsg.org/man/v1.2.2/nng_dialer_create.3).
     pub(crate) fn new(socket: NngSocket, url: &str) -> Result<Self> {
         unsafe {
@@ -20,40 +39,138 @@ impl NngDialer {
             let (_cstring, url) = to_cstr(url)?;
             Error::zero_map(
                 nng_dialer_create(&mut dialer, socket.nng_socket(), url),
-                || NngDialer { dialer, socket },
+                || {
+                    socket.register_dialer(dialer);
+                    let inner = InnerDialer {
+                        dialer,
+                        socket,
+                        closed: false,
+                    };
+                    DialerBuilder { inner }
+                },
             )
         }
     }
 
-    // TODO: Use different type for started vs non-started dialer?  According to nng docs options can generally only
-    // be set before the dialer is started.
-    pub fn start(&self) -> Result<()> {
-        unsafe { nng_int_to_result(nng_dialer_start(self.dialer, 0)) }
+    /// Start connecting.  If it fails the dialer is closed.
+    /// See [nng_dialer_start](https://nng.nanomsg.org/man/v1.2.2/nng_dialer_start.3).
+    pub fn start(self) -> Result<StartedDialer> {
+        self.start_flags(Default::default())
+    }
+
+    /// Start connecting with [`SocketFlags`](../struct.SocketFlags.html).  With `NONBLOCK` the
+    /// first connection is attempted in the background.
+    pub fn start_flags(self, flags: SocketFlags) -> Result<StartedDialer> {
+        let inner = self.inner;
+        unsafe { nng_int_to_result(nng_dialer_start(inner.dialer, flags.bits())) }
+            .log_context(|| format!("start dialer {}", inner.id()))?;
+        Ok(StartedDialer { inner })
+    }
+
+    /// See [nng_dialer_id](https://nng.nanomsg.org/man/v1.2.2/nng_dialer_id.3).
+    pub fn id(&self) -> i32 {
+        self.inner.id()
+    }
+
+    /// Close without starting.  See [nng_dialer_close](https://nng.nanomsg.org/man/v1.2.2/nng_dialer_close.3).
+    pub fn close(mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
+
+impl NngWrapper for DialerBuilder {
+    type NngType = nng_dialer;
+    unsafe fn get_nng_type(&self) -> Self::NngType {
+        self.inner.dialer
+    }
+}
+
+/// Dialer that was started.  Options can still be read, but no longer set.
+#[derive(Debug, NngGetOpts)]
+#[prefix = "nng_dialer_"]
+pub struct StartedDialer {
+    inner: InnerDialer,
+}
+
+impl StartedDialer {
+    /// See [nng_dialer_id](https://nng.nanomsg.org/man/v1.2.2/nng_dialer_id.3).
+    pub fn id(&self) -> i32 {
+        self.inner.id()
+    }
+
+    /// Snapshot of statistics of the dialer.
+    pub fn stats(&self) -> Result<stats::DialerStats> {
+        stats::DialerStats::for_id(self.id())
     }
 
     pub fn get_sockaddr(&self, option: NngOption) -> Result<SockAddr> {
         unsafe {
             let mut sockaddr = nng_sockaddr::default();
             nng_int_to_result(nng_dialer_getopt_sockaddr(
-                self.dialer,
+                self.inner.dialer,
                 option.as_cptr(),
                 &mut sockaddr,
             ))
             .and_then(|_| SockAddr::try_from(sockaddr))
         }
     }
+
+    /// Close the dialer and any connection it made.
+    /// See [nng_dialer_close](https://nng.nanomsg.org/man/v1.2.2/nng_dialer_close.3).
+    pub fn close(mut self) -> Result<()> {
+        self.inner.close()
+    }
 }
 
-impl NngWrapper for NngDialer {
+impl NngWrapper for StartedDialer {
     type NngType = nng_dialer;
     unsafe fn get_nng_type(&self) -> Self::NngType {
-        self.dialer
+        self.inner.dialer
     }
 }
 
-/// "Unsafe" version of `NngDialer`.  Merely wraps `nng_dialer` and makes no attempt to manage the underlying resources.
-/// May be invalid, close unexpectedly, etc.
+/// Dialer owned by `DialerBuilder` then `StartedDialer`.  Closed when dropped.
 #[derive(Debug)]
+struct InnerDialer {
+    dialer: nng_dialer,
+    socket: NngSocket,
+    closed: bool,
+}
+
+impl InnerDialer {
+    fn id(&self) -> i32 {
+        unsafe { nng_dialer_id(self.dialer) }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let id = self.id();
+        self.socket.unregister_dialer(id);
+        // Closing the socket already closed its dialers
+        if self.socket.is_closed() {
+            return Ok(());
+        }
+        trace!("Dialer close: {}", id);
+        unsafe { nng_int_to_result(nng_dialer_close(self.dialer)) }
+            .log_context(|| format!("close dialer {}", id))
+    }
+}
+
+impl Drop for InnerDialer {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            debug!("{}", err);
+        }
+    }
+}
+
+/// "Unsafe" version of `StartedDialer`.  Merely wraps `nng_dialer` and makes no attempt to manage the underlying resources.
+/// May be invalid, close unexpectedly, etc.
+#[derive(Debug, NngGetOpts)]
+#[prefix = "nng_dialer_"]
 pub struct UnsafeDialer {
     dialer: nng_dialer,
 }
@@ -67,3 +184,10 @@ impl UnsafeDialer {
         unsafe { nng_dialer_id(self.dialer) }
     }
 }
+
+impl NngWrapper for UnsafeDialer {
+    type NngType = nng_dialer;
+    unsafe fn get_nng_type(&self) -> Self::NngType {
+        self.dialer
+    }
+}
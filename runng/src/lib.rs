@@ -71,16 +71,21 @@ Additional examples [in `examples/` folder](https://github.com/jeikabu/runng/tre
 */
 
 pub mod asyncio;
+pub mod codec;
 pub mod ctx;
 pub mod dialer;
 pub mod factory;
 pub mod listener;
 pub mod mem;
 pub mod msg;
+pub mod mux;
 pub mod options;
 pub mod pipe;
 pub mod protocol;
+pub mod proxy;
 pub mod result;
+pub mod rpc;
+pub mod shutdown;
 pub mod socket;
 pub mod stats;
 pub mod transport;
@@ -95,6 +100,9 @@ pub use self::socket::*;
 use log::{debug, trace};
 use runng_sys::*;
 
+#[cfg(feature = "serde")]
+extern crate serde_crate as serde;
+
 /// Type which wraps a native nng type
 trait NngWrapper {
     /// Native nng type wrapped
@@ -1,67 +1,172 @@
 //! Listeners accept connections from dialers.
+//!
+//! Options can generally only be set before a listener is started, so `Listen::listener_create()`
+//! returns a `ListenerBuilder` which becomes a `StartedListener` once started.  Either closes the
+//! listener when dropped.
 
 use super::*;
 use runng_derive::{NngGetOpts, NngSetOpts};
 use runng_sys::*;
 
-/// Wraps `nng_listener`.  See [nng_listener](https://nng.nanomsg.org/man/v1.2.2/nng_listener.5).
+/// Listener not yet started.  Options can be set until `start()`.
+/// See [nng_listener](https://nng.nanomsg.org/man/v1.2.2/nng_listener.5).
 #[derive(Debug, NngGetOpts, NngSetOpts)]
 #[prefix = "nng_listener_"]
-pub struct NngListener {
-    listener: nng_listener,
-    socket: NngSocket,
+pub struct ListenerBuilder {
+    inner: InnerListener,
 }
 
-impl NngListener {
+impl ListenerBuilder {
     /// See [nng_listener_create](https://nng.nanomsg.org/man/v1.2.2/nng_listener_create.3).
     pub(crate) fn new(socket: NngSocket, url: &str) -> Result<Self> {
         unsafe {
             let mut listener = nng_listener::default();
             let (_cstring, url) = to_cstr(url)?;
             let res = nng_listener_create(&mut listener, socket.nng_socket(), url);
-            Error::zero_map(res, || NngListener { listener, socket })
+            Error::zero_map(res, || {
+                socket.register_listener(listener);
+                let inner = InnerListener {
+                    listener,
+                    socket,
+                    closed: false,
+                };
+                ListenerBuilder { inner }
+            })
         }
     }
 
+    /// Start accepting connections.  If it fails the listener is closed.
     /// See [nng_listener_start](https://nng.nanomsg.org/man/v1.2.2/nng_listener_start.3).
-    pub fn start(&self) -> Result<()> {
-        // TODO: Use different type for started vs non-started dialer?  According to nng docs options can generally only
-        // be set before the dialer is started.
-        unsafe { nng_int_to_result(nng_listener_start(self.listener, 0)) }
+    pub fn start(self) -> Result<StartedListener> {
+        let inner = self.inner;
+        unsafe { nng_int_to_result(nng_listener_start(inner.listener, 0)) }
+            .log_context(|| format!("start listener {}", inner.id()))?;
+        Ok(StartedListener { inner })
+    }
+
+    /// See [nng_listener_id](https://nng.nanomsg.org/man/v1.2.2/nng_listener_id.3).
+    pub fn id(&self) -> i32 {
+        self.inner.id()
+    }
+
+    /// Close without starting.  See [nng_listener_close](https://nng.nanomsg.org/man/v1.2.2/nng_listener_close.3).
+    pub fn close(mut self) -> Result<()> {
+        self.inner.close()
+    }
+}
+
+impl NngWrapper for ListenerBuilder {
+    type NngType = nng_listener;
+    unsafe fn get_nng_type(&self) -> Self::NngType {
+        self.inner.listener
+    }
+}
+
+impl GetSocket for ListenerBuilder {
+    fn socket(&self) -> &NngSocket {
+        &self.inner.socket
+    }
+    fn socket_mut(&mut self) -> &mut NngSocket {
+        &mut self.inner.socket
+    }
+}
+
+/// Listener that was started.  Options can still be read (e.g. `NngOption::TCP_BOUND_PORT`), but
+/// no longer set.
+#[derive(Debug, NngGetOpts)]
+#[prefix = "nng_listener_"]
+pub struct StartedListener {
+    inner: InnerListener,
+}
+
+impl StartedListener {
+    /// See [nng_listener_id](https://nng.nanomsg.org/man/v1.2.2/nng_listener_id.3).
+    pub fn id(&self) -> i32 {
+        self.inner.id()
+    }
+
+    /// Snapshot of statistics of the listener.  Use `GetSocket::stats()` for those of its socket.
+    pub fn stats(&self) -> Result<stats::ListenerStats> {
+        stats::ListenerStats::for_id(self.id())
     }
 
     pub fn get_sockaddr(&self, option: NngOption) -> Result<SockAddr> {
         unsafe {
             let mut sockaddr = nng_sockaddr::default();
             nng_int_to_result(nng_listener_getopt_sockaddr(
-                self.listener,
+                self.inner.listener,
                 option.as_cptr(),
                 &mut sockaddr,
             ))
             .and_then(|_| SockAddr::try_from(sockaddr))
         }
     }
+
+    /// Stop accepting connections and close those it accepted.
+    /// See [nng_listener_close](https://nng.nanomsg.org/man/v1.2.2/nng_listener_close.3).
+    pub fn close(mut self) -> Result<()> {
+        self.inner.close()
+    }
 }
 
-impl NngWrapper for NngListener {
+impl NngWrapper for StartedListener {
     type NngType = nng_listener;
     unsafe fn get_nng_type(&self) -> Self::NngType {
-        self.listener
+        self.inner.listener
     }
 }
 
-impl GetSocket for NngListener {
+impl GetSocket for StartedListener {
     fn socket(&self) -> &NngSocket {
-        &self.socket
+        &self.inner.socket
     }
     fn socket_mut(&mut self) -> &mut NngSocket {
-        &mut self.socket
+        &mut self.inner.socket
     }
 }
 
-/// "Unsafe" version of `NngListener`.  Merely wraps `nng_listener` and makes no attempt to manage the underlying resources.
-/// May be invalid, close unexpectedly, etc.
+/// Listener owned by `ListenerBuilder` then `StartedListener`.  Closed when dropped.
 #[derive(Debug)]
+struct InnerListener {
+    listener: nng_listener,
+    socket: NngSocket,
+    closed: bool,
+}
+
+impl InnerListener {
+    fn id(&self) -> i32 {
+        unsafe { nng_listener_id(self.listener) }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+        let id = self.id();
+        self.socket.unregister_listener(id);
+        // Closing the socket already closed its listeners
+        if self.socket.is_closed() {
+            return Ok(());
+        }
+        trace!("Listener close: {}", id);
+        unsafe { nng_int_to_result(nng_listener_close(self.listener)) }
+            .log_context(|| format!("close listener {}", id))
+    }
+}
+
+impl Drop for InnerListener {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            debug!("{}", err);
+        }
+    }
+}
+
+/// "Unsafe" version of `StartedListener`.  Merely wraps `nng_listener` and makes no attempt to manage the underlying resources.
+/// May be invalid, close unexpectedly, etc.
+#[derive(Debug, NngGetOpts)]
+#[prefix = "nng_listener_"]
 pub struct UnsafeListener {
     listener: nng_listener,
 }
@@ -76,3 +181,10 @@ impl UnsafeListener {
         unsafe { nng_listener_id(self.listener) }
     }
 }
+
+impl NngWrapper for UnsafeListener {
+    type NngType = nng_listener;
+    unsafe fn get_nng_type(&self) -> Self::NngType {
+        self.listener
+    }
+}
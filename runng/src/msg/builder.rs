@@ -0,0 +1,128 @@
+//! Build a `NngMsg` in place.
+//!
+//! # Examples
+//! ```
+//! use runng::msg::NngMsgBuilder;
+//!
+//! fn test() -> runng::Result<()> {
+//!     let mut builder = NngMsgBuilder::with_capacity(64)?;
+//!     // Reserve a length prefix and fill it in once the payload is written
+//!     let prefix = builder.len();
+//!     builder.put_u32(0)?.put_slice(b"hello")?.encode("world")?;
+//!     let payload = (builder.len() - prefix - 4) as u32;
+//!     builder.set_u32(prefix, payload);
+//!     let msg = builder.build();
+//!     assert_eq!(msg.len(), 4 + 5 + 4 + 5);
+//!     Ok(())
+//! }
+//! ```
+
+use super::{NngEncode, NngMsg};
+use crate::*;
+
+/// Writes fields directly into the body of a pre-sized `NngMsg`.
+///
+/// Unlike appending to a message field by field, reserves space once up front so encoding doesn't
+/// reallocate.  Finish with `build()`.
+#[derive(Debug)]
+pub struct NngMsgBuilder {
+    msg: NngMsg,
+}
+
+impl NngMsgBuilder {
+    pub fn new() -> Result<Self> {
+        NngMsg::new().map(Self::from)
+    }
+
+    /// Create a builder with room for `size_bytes` of body.
+    pub fn with_capacity(size_bytes: usize) -> Result<Self> {
+        NngMsg::with_reserved(size_bytes).map(Self::from)
+    }
+
+    /// Current length of the body.
+    pub fn len(&self) -> usize {
+        self.msg.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.msg.is_empty()
+    }
+
+    /// Make room for at least `additional` more bytes of body.
+    pub fn reserve(&mut self, additional: usize) -> Result<&mut Self> {
+        self.msg.reserve(additional)?;
+        Ok(self)
+    }
+
+    /// Extend the body by `len` zeroed bytes and return them to be written in place.
+    pub fn put(&mut self, len: usize) -> Result<&mut [u8]> {
+        let start = self.msg.len();
+        self.msg.resize(start + len, 0)?;
+        Ok(&mut self.msg.as_mut_slice()[start..])
+    }
+
+    pub fn put_slice(&mut self, data: &[u8]) -> Result<&mut Self> {
+        self.put(data.len())?.copy_from_slice(data);
+        Ok(self)
+    }
+
+    pub fn put_u16(&mut self, value: u16) -> Result<&mut Self> {
+        self.put_slice(&value.to_be_bytes())
+    }
+
+    pub fn put_u32(&mut self, value: u32) -> Result<&mut Self> {
+        self.put_slice(&value.to_be_bytes())
+    }
+
+    pub fn put_u64(&mut self, value: u64) -> Result<&mut Self> {
+        self.put_slice(&value.to_be_bytes())
+    }
+
+    /// Overwrite a `u32` previously written at `offset`, like a length prefix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset + 4` is past the end of the body.
+    pub fn set_u32(&mut self, offset: usize, value: u32) -> &mut Self {
+        self.msg.as_mut_slice()[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    /// Append `value` using its `NngEncode` implementation.
+    pub fn encode<T: NngEncode + ?Sized>(&mut self, value: &T) -> Result<&mut Self> {
+        value.encode(&mut self.msg)?;
+        Ok(self)
+    }
+
+    pub fn header_put_slice(&mut self, data: &[u8]) -> Result<&mut Self> {
+        self.msg.header_append_slice(data)?;
+        Ok(self)
+    }
+
+    /// Append `value` to the header using its `NngEncode` implementation.
+    pub fn encode_header<T: NngEncode + ?Sized>(&mut self, value: &T) -> Result<&mut Self> {
+        value.encode_header(&mut self.msg)?;
+        Ok(self)
+    }
+
+    /// Body written so far.
+    pub fn body(&self) -> &[u8] {
+        self.msg.body()
+    }
+
+    pub fn body_mut(&mut self) -> &mut [u8] {
+        self.msg.as_mut_slice()
+    }
+
+    /// Finish building and return the message.
+    pub fn build(self) -> NngMsg {
+        self.msg
+    }
+}
+
+impl From<NngMsg> for NngMsgBuilder {
+    /// Continue building on an existing message.
+    fn from(msg: NngMsg) -> Self {
+        Self { msg }
+    }
+}
@@ -0,0 +1,414 @@
+//! Encode values into and decode values out of `NngMsg`.
+//!
+//! Values are appended to the end of the message and removed from the front, so they are decoded
+//! in the order they were encoded.  Integers use network byte order (like `append_u32()`),
+//! strings and sequences are prefixed with their `u32` length, and enums with their `u32`
+//! variant index.  Tuples are encoded field by field.
+//!
+//! # Examples
+//! ```
+//! use runng::msg::{NngDecode, NngEncode, NngMsg};
+//!
+//! #[derive(Debug, PartialEq, NngEncode, NngDecode)]
+//! struct Request {
+//!     #[nng(header)]
+//!     id: u32,
+//!     name: String,
+//!     data: Vec<u8>,
+//! }
+//!
+//! fn test() -> runng::Result<()> {
+//!     let request = Request { id: 1, name: "runng".to_owned(), data: vec![1, 2, 3] };
+//!     let mut msg = NngMsg::new()?;
+//!     request.encode(&mut msg)?;
+//!     assert_eq!(msg.header_len(), 4);
+//!     assert_eq!(Request::decode(&mut msg)?, request);
+//!     Ok(())
+//! }
+//! ```
+
+use super::NngMsg;
+use crate::*;
+
+/// Type that can be written to a `NngMsg`.
+///
+/// Derived impls write fields marked `#[nng(header)]` to the message header.  nng protocols own
+/// the header of messages sent through a socket: most send it in front of the body (so the
+/// receiver finds those bytes at the start of the body) and some, like req, replace it.  Header
+/// fields are therefore only for messages that stay in the process or go through raw sockets
+/// (e.g. a device that forwards the header unchanged), not for data sent to a peer.
+pub trait NngEncode {
+    /// Append to the message body.
+    fn encode(&self, msg: &mut NngMsg) -> Result<()>;
+    /// Append to the message header.  Sockets don't deliver the header as is, see above.
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()>;
+
+    /// Append a slice of values to the message body.
+    #[doc(hidden)]
+    fn encode_slice(items: &[Self], msg: &mut NngMsg) -> Result<()>
+    where
+        Self: Sized,
+    {
+        items.iter().try_for_each(|item| item.encode(msg))
+    }
+    /// Append a slice of values to the message header.
+    #[doc(hidden)]
+    fn encode_header_slice(items: &[Self], msg: &mut NngMsg) -> Result<()>
+    where
+        Self: Sized,
+    {
+        items.iter().try_for_each(|item| item.encode_header(msg))
+    }
+}
+
+/// Type that can be read from a `NngMsg`.
+pub trait NngDecode: Sized {
+    /// Remove from the front of the message body.
+    fn decode(msg: &mut NngMsg) -> Result<Self>;
+    /// Remove from the front of the message header.
+    fn decode_header(msg: &mut NngMsg) -> Result<Self>;
+
+    /// Remove `len` values from the front of the message body.
+    #[doc(hidden)]
+    fn decode_vec(len: usize, msg: &mut NngMsg) -> Result<Vec<Self>> {
+        (0..len).map(|_| Self::decode(msg)).collect()
+    }
+    /// Remove `len` values from the front of the message header.
+    #[doc(hidden)]
+    fn decode_header_vec(len: usize, msg: &mut NngMsg) -> Result<Vec<Self>> {
+        (0..len).map(|_| Self::decode_header(msg)).collect()
+    }
+}
+
+fn check_len(available: usize, needed: usize) -> Result<()> {
+    if available < needed {
+        Err(Error::Decode(format!(
+            "Need {} bytes, only {} available",
+            needed, available
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+fn trim_bytes(msg: &mut NngMsg, len: usize) -> Result<Vec<u8>> {
+    check_len(msg.len(), len)?;
+    let bytes = msg.body()[..len].to_vec();
+    msg.trim(len)?;
+    Ok(bytes)
+}
+
+fn header_trim_bytes(msg: &mut NngMsg, len: usize) -> Result<Vec<u8>> {
+    check_len(msg.header_len(), len)?;
+    let bytes = msg.header()[..len].to_vec();
+    msg.header_trim(len)?;
+    Ok(bytes)
+}
+
+fn encode_len(len: usize, msg: &mut NngMsg, header: bool) -> Result<()> {
+    if len > u32::MAX as usize {
+        return Err(Error::Encode(format!("Length {} exceeds u32", len)));
+    }
+    if header {
+        msg.header_append_u32(len as u32)
+    } else {
+        msg.append_u32(len as u32)
+    }
+}
+
+macro_rules! impl_uint {
+    ($utype:ty, $size:expr, $append:ident, $header_append:ident, $trim:ident, $header_trim:ident) => {
+        impl NngEncode for $utype {
+            fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+                msg.$append(*self)
+            }
+            fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+                msg.$header_append(*self)
+            }
+        }
+
+        impl NngDecode for $utype {
+            fn decode(msg: &mut NngMsg) -> Result<Self> {
+                check_len(msg.len(), $size)?;
+                msg.$trim()
+            }
+            fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+                check_len(msg.header_len(), $size)?;
+                msg.$header_trim()
+            }
+        }
+    };
+}
+
+impl_uint!(
+    u16,
+    2,
+    append_u16,
+    header_append_u16,
+    trim_u16,
+    header_trim_u16
+);
+impl_uint!(
+    u32,
+    4,
+    append_u32,
+    header_append_u32,
+    trim_u32,
+    header_trim_u32
+);
+impl_uint!(
+    u64,
+    8,
+    append_u64,
+    header_append_u64,
+    trim_u64,
+    header_trim_u64
+);
+
+// Types encoded as the bits of another type
+macro_rules! impl_cast {
+    ($type:ty, $as:ty, $to:expr, $from:expr) => {
+        impl NngEncode for $type {
+            fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+                let value: $as = $to(*self);
+                value.encode(msg)
+            }
+            fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+                let value: $as = $to(*self);
+                value.encode_header(msg)
+            }
+        }
+
+        impl NngDecode for $type {
+            fn decode(msg: &mut NngMsg) -> Result<Self> {
+                <$as>::decode(msg).map($from)
+            }
+            fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+                <$as>::decode_header(msg).map($from)
+            }
+        }
+    };
+}
+
+impl_cast!(i8, u8, |v: i8| v as u8, |v: u8| v as i8);
+impl_cast!(i16, u16, |v: i16| v as u16, |v: u16| v as i16);
+impl_cast!(i32, u32, |v: i32| v as u32, |v: u32| v as i32);
+impl_cast!(i64, u64, |v: i64| v as u64, |v: u64| v as i64);
+impl_cast!(f32, u32, f32::to_bits, f32::from_bits);
+impl_cast!(f64, u64, f64::to_bits, f64::from_bits);
+
+impl NngEncode for u8 {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        msg.append_slice(&[*self])
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        msg.header_append_slice(&[*self])
+    }
+    fn encode_slice(items: &[Self], msg: &mut NngMsg) -> Result<()> {
+        msg.append_slice(items)
+    }
+    fn encode_header_slice(items: &[Self], msg: &mut NngMsg) -> Result<()> {
+        msg.header_append_slice(items)
+    }
+}
+
+impl NngDecode for u8 {
+    fn decode(msg: &mut NngMsg) -> Result<Self> {
+        trim_bytes(msg, 1).map(|bytes| bytes[0])
+    }
+    fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+        header_trim_bytes(msg, 1).map(|bytes| bytes[0])
+    }
+    fn decode_vec(len: usize, msg: &mut NngMsg) -> Result<Vec<Self>> {
+        trim_bytes(msg, len)
+    }
+    fn decode_header_vec(len: usize, msg: &mut NngMsg) -> Result<Vec<Self>> {
+        header_trim_bytes(msg, len)
+    }
+}
+
+impl NngEncode for bool {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        (*self as u8).encode(msg)
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        (*self as u8).encode_header(msg)
+    }
+}
+
+impl NngDecode for bool {
+    fn decode(msg: &mut NngMsg) -> Result<Self> {
+        u8::decode(msg).map(|value| value != 0)
+    }
+    fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+        u8::decode_header(msg).map(|value| value != 0)
+    }
+}
+
+impl<T: NngEncode> NngEncode for [T] {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        // Size in memory is only an estimate of encoded size, but avoids growing one item at a time
+        msg.reserve(4 + std::mem::size_of_val(self))?;
+        encode_len(self.len(), msg, false)?;
+        T::encode_slice(self, msg)
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        encode_len(self.len(), msg, true)?;
+        T::encode_header_slice(self, msg)
+    }
+}
+
+impl<T: NngEncode> NngEncode for Vec<T> {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        self.as_slice().encode(msg)
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        self.as_slice().encode_header(msg)
+    }
+}
+
+impl<T: NngDecode> NngDecode for Vec<T> {
+    fn decode(msg: &mut NngMsg) -> Result<Self> {
+        let len = u32::decode(msg)?;
+        T::decode_vec(len as usize, msg)
+    }
+    fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+        let len = u32::decode_header(msg)?;
+        T::decode_header_vec(len as usize, msg)
+    }
+}
+
+impl NngEncode for str {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        self.as_bytes().encode(msg)
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        self.as_bytes().encode_header(msg)
+    }
+}
+
+impl NngEncode for String {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        self.as_str().encode(msg)
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        self.as_str().encode_header(msg)
+    }
+}
+
+impl NngDecode for String {
+    fn decode(msg: &mut NngMsg) -> Result<Self> {
+        let bytes = Vec::<u8>::decode(msg)?;
+        String::from_utf8(bytes).map_err(|err| Error::Decode(err.to_string()))
+    }
+    fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+        let bytes = Vec::<u8>::decode_header(msg)?;
+        String::from_utf8(bytes).map_err(|err| Error::Decode(err.to_string()))
+    }
+}
+
+impl<T: NngEncode> NngEncode for Option<T> {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        match self {
+            Some(value) => {
+                true.encode(msg)?;
+                value.encode(msg)
+            }
+            None => false.encode(msg),
+        }
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        match self {
+            Some(value) => {
+                true.encode_header(msg)?;
+                value.encode_header(msg)
+            }
+            None => false.encode_header(msg),
+        }
+    }
+}
+
+impl<T: NngDecode> NngDecode for Option<T> {
+    fn decode(msg: &mut NngMsg) -> Result<Self> {
+        if bool::decode(msg)? {
+            T::decode(msg).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+    fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+        if bool::decode_header(msg)? {
+            T::decode_header(msg).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+// Tuple fields are encoded in order, `()` is empty
+macro_rules! impl_tuple {
+    ($($name:ident)*) => {
+        impl<$($name: NngEncode),*> NngEncode for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+                let ($($name,)*) = self;
+                $($name.encode(msg)?;)*
+                Ok(())
+            }
+            #[allow(non_snake_case, unused_variables)]
+            fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+                let ($($name,)*) = self;
+                $($name.encode_header(msg)?;)*
+                Ok(())
+            }
+        }
+
+        impl<$($name: NngDecode),*> NngDecode for ($($name,)*) {
+            #[allow(unused_variables)]
+            fn decode(msg: &mut NngMsg) -> Result<Self> {
+                Ok(($($name::decode(msg)?,)*))
+            }
+            #[allow(unused_variables)]
+            fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+                Ok(($($name::decode_header(msg)?,)*))
+            }
+        }
+    };
+}
+
+impl_tuple!();
+impl_tuple!(A);
+impl_tuple!(A B);
+impl_tuple!(A B C);
+impl_tuple!(A B C D);
+impl_tuple!(A B C D E);
+impl_tuple!(A B C D E F);
+impl_tuple!(A B C D E F G);
+impl_tuple!(A B C D E F G H);
+
+impl<T: NngEncode + ?Sized> NngEncode for &T {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        (**self).encode(msg)
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        (**self).encode_header(msg)
+    }
+}
+
+impl<T: NngEncode + ?Sized> NngEncode for Box<T> {
+    fn encode(&self, msg: &mut NngMsg) -> Result<()> {
+        (**self).encode(msg)
+    }
+    fn encode_header(&self, msg: &mut NngMsg) -> Result<()> {
+        (**self).encode_header(msg)
+    }
+}
+
+impl<T: NngDecode> NngDecode for Box<T> {
+    fn decode(msg: &mut NngMsg) -> Result<Self> {
+        T::decode(msg).map(Box::new)
+    }
+    fn decode_header(msg: &mut NngMsg) -> Result<Self> {
+        T::decode_header(msg).map(Box::new)
+    }
+}
@@ -0,0 +1,296 @@
+//! Metadata carried in front of the message body, like trace context.
+//!
+//! Protocol headers belong to nng, so an `Envelope` is prepended to the body instead:
+//! ```text
+//! [magic: "RNGE"][version: u8][count: u16]{[key length: u16][key][value length: u16][value]}[payload]
+//! ```
+//! Lengths are in network byte order and keys and values are UTF-8.  A body that doesn't start
+//! with the magic has no envelope, so peers that don't use envelopes can still send to those that
+//! do (as long as their payloads don't happen to start with the magic).
+//!
+//! Trace context uses the [W3C](https://www.w3.org/TR/trace-context/) `traceparent` format, so
+//! it can be handed to and from other tracing systems unchanged.
+//!
+//! # Examples
+//! ```
+//! use runng::msg::{Envelope, NngMsg, TraceContext};
+//!
+//! fn test() -> runng::Result<()> {
+//!     let trace = TraceContext::new_root(true);
+//!     let mut msg = NngMsg::new()?;
+//!     msg.append_u32(42)?;
+//!     Envelope::with_trace_context(&trace).inject(&mut msg)?;
+//!
+//!     let (envelope, msg) = Envelope::open(msg)?;
+//!     assert_eq!(envelope.trace_context(), Some(trace));
+//!     assert_eq!(msg.body(), &[0, 0, 0, 42]);
+//!     Ok(())
+//! }
+//! ```
+
+use super::NngMsg;
+use crate::*;
+use std::{collections::BTreeMap, convert::TryFrom, fmt, str::FromStr};
+
+/// First bytes of a body with an envelope.
+pub const ENVELOPE_MAGIC: &[u8; 4] = b"RNGE";
+const ENVELOPE_VERSION: u8 = 1;
+// Magic, version, and count
+const PREFIX_LEN: usize = 4 + 1 + 2;
+
+/// Metadata key of the W3C trace parent.
+pub const TRACEPARENT: &str = "traceparent";
+/// Metadata key of the W3C vendor-specific trace state.
+pub const TRACESTATE: &str = "tracestate";
+
+/// Key/value metadata prepended to the body of a message.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Envelope {
+    metadata: BTreeMap<String, String>,
+}
+
+impl Envelope {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Envelope containing only `trace`.
+    pub fn with_trace_context(trace: &TraceContext) -> Self {
+        let mut envelope = Self::new();
+        envelope.set_trace_context(trace);
+        envelope
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+
+    /// Set `key` to `value`, returning the previous value.
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> Option<String> {
+        self.metadata.insert(key.into(), value.into())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.metadata.remove(key)
+    }
+
+    /// Metadata in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.metadata
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.metadata.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metadata.is_empty()
+    }
+
+    /// Parsed `traceparent`.  `None` if missing or invalid.
+    pub fn trace_context(&self) -> Option<TraceContext> {
+        self.get(TRACEPARENT)?.parse().ok()
+    }
+
+    pub fn set_trace_context(&mut self, trace: &TraceContext) {
+        self.insert(TRACEPARENT, trace.to_string());
+    }
+
+    /// Prepend the envelope to the body of `msg`.
+    pub fn inject(&self, msg: &mut NngMsg) -> Result<()> {
+        let bytes = self.encode()?;
+        msg.insert_slice(&bytes)
+    }
+
+    /// Remove the envelope from the front of the body of `msg`.  `None` if it has none.
+    pub fn extract(msg: &mut NngMsg) -> Result<Option<Envelope>> {
+        match Self::decode(msg.body())? {
+            Some((envelope, len)) => {
+                msg.trim(len)?;
+                Ok(Some(envelope))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Split `msg` into its envelope (empty if it has none) and the remaining message.
+    pub fn open(mut msg: NngMsg) -> Result<(Envelope, NngMsg)> {
+        let envelope = Self::extract(&mut msg)?.unwrap_or_default();
+        Ok((envelope, msg))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let count = u16::try_from(self.metadata.len())
+            .map_err(|_| Error::Encode("Too many envelope entries".to_owned()))?;
+        let mut bytes = Vec::with_capacity(PREFIX_LEN);
+        bytes.extend_from_slice(ENVELOPE_MAGIC);
+        bytes.push(ENVELOPE_VERSION);
+        bytes.extend_from_slice(&count.to_be_bytes());
+        for (key, value) in self.metadata.iter() {
+            encode_str(&mut bytes, key)?;
+            encode_str(&mut bytes, value)?;
+        }
+        Ok(bytes)
+    }
+
+    /// Envelope at the front of `bytes` and its length in bytes.
+    fn decode(bytes: &[u8]) -> Result<Option<(Envelope, usize)>> {
+        if !bytes.starts_with(ENVELOPE_MAGIC) {
+            return Ok(None);
+        }
+        if bytes.len() < PREFIX_LEN {
+            return Err(Error::Decode("Truncated envelope".to_owned()));
+        }
+        let version = bytes[4];
+        if version != ENVELOPE_VERSION {
+            return Err(Error::Decode(format!(
+                "Unsupported envelope version {}",
+                version
+            )));
+        }
+        let count = u16::from_be_bytes([bytes[5], bytes[6]]);
+        let mut pos = PREFIX_LEN;
+        let mut metadata = BTreeMap::new();
+        for _ in 0..count {
+            let key = decode_str(bytes, &mut pos)?;
+            let value = decode_str(bytes, &mut pos)?;
+            metadata.insert(key, value);
+        }
+        Ok(Some((Envelope { metadata }, pos)))
+    }
+}
+
+fn encode_str(bytes: &mut Vec<u8>, string: &str) -> Result<()> {
+    let len = u16::try_from(string.len())
+        .map_err(|_| Error::Encode(format!("Envelope entry too long: {} bytes", string.len())))?;
+    bytes.extend_from_slice(&len.to_be_bytes());
+    bytes.extend_from_slice(string.as_bytes());
+    Ok(())
+}
+
+fn decode_str(bytes: &[u8], pos: &mut usize) -> Result<String> {
+    let truncated = || Error::Decode("Truncated envelope".to_owned());
+    let len = bytes.get(*pos..*pos + 2).ok_or_else(truncated)?;
+    let len = usize::from(u16::from_be_bytes([len[0], len[1]]));
+    let start = *pos + 2;
+    let string = bytes.get(start..start + len).ok_or_else(truncated)?;
+    *pos = start + len;
+    String::from_utf8(string.to_vec()).map_err(|err| Error::Decode(err.to_string()))
+}
+
+/// W3C trace context: `traceparent` of the operation that sent a message.
+///
+/// Formats as and parses from `00-<trace id>-<parent id>-<flags>` in lowercase hexadecimal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TraceContext {
+    /// Id of the whole trace, never all zeros
+    pub trace_id: [u8; 16],
+    /// Id of the span that sent the message, never all zeros
+    pub parent_id: [u8; 8],
+    pub flags: u8,
+}
+
+impl TraceContext {
+    /// Flag set if the caller may have recorded the trace.
+    pub const SAMPLED: u8 = 0x01;
+
+    /// Start a new trace with random ids.
+    pub fn new_root(sampled: bool) -> Self {
+        let flags = if sampled { Self::SAMPLED } else { 0 };
+        Self {
+            trace_id: random_id::<u128>().to_be_bytes(),
+            parent_id: random_id::<u64>().to_be_bytes(),
+            flags,
+        }
+    }
+
+    /// Context for a new span of the same trace, e.g. before forwarding a message.
+    pub fn child(&self) -> Self {
+        Self {
+            parent_id: random_id::<u64>().to_be_bytes(),
+            ..*self
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags & Self::SAMPLED != 0
+    }
+}
+
+/// Random non-zero id.
+fn random_id<T: PartialEq + Default>() -> T
+where
+    rand::distributions::Standard: rand::distributions::Distribution<T>,
+{
+    loop {
+        let id = rand::random::<T>();
+        if id != T::default() {
+            return id;
+        }
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "00-")?;
+        write_hex(f, &self.trace_id)?;
+        write!(f, "-")?;
+        write_hex(f, &self.parent_id)?;
+        write!(f, "-{:02x}", self.flags)
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+}
+
+impl FromStr for TraceContext {
+    type Err = Error;
+
+    /// Parse a `traceparent`.  Fields added by versions after `00` are ignored.
+    fn from_str(traceparent: &str) -> Result<Self> {
+        let invalid = || Error::Decode(format!("Invalid traceparent: {}", traceparent));
+        let fields: Vec<&str> = traceparent.trim().split('-').collect();
+        if fields.len() < 4 {
+            return Err(invalid());
+        }
+        let mut version = [0; 1];
+        parse_hex(fields[0], &mut version).ok_or_else(invalid)?;
+        // ff is forbidden, and version 00 has exactly four fields
+        if version[0] == 0xff || (version[0] == 0 && fields.len() != 4) {
+            return Err(invalid());
+        }
+        let mut trace = TraceContext {
+            trace_id: [0; 16],
+            parent_id: [0; 8],
+            flags: 0,
+        };
+        parse_hex(fields[1], &mut trace.trace_id).ok_or_else(invalid)?;
+        parse_hex(fields[2], &mut trace.parent_id).ok_or_else(invalid)?;
+        let mut flags = [0; 1];
+        parse_hex(fields[3], &mut flags).ok_or_else(invalid)?;
+        trace.flags = flags[0];
+        if trace.trace_id == [0; 16] || trace.parent_id == [0; 8] {
+            return Err(invalid());
+        }
+        Ok(trace)
+    }
+}
+
+/// Fill `bytes` from exactly `2 * bytes.len()` lowercase hex digits.
+fn parse_hex(hex: &str, bytes: &mut [u8]) -> Option<()> {
+    if hex.len() != bytes.len() * 2 {
+        return None;
+    }
+    let digit = |c: u8| match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    };
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = digit(pair[0])? << 4 | digit(pair[1])?;
+    }
+    Some(())
+}
@@ -0,0 +1,193 @@
+//! `std::io` and [bytes](https://docs.rs/bytes) support for `NngMsg`.
+//!
+//! Writing appends to the message body, reading uses a [`NngMsgReader`](struct.NngMsgReader.html)
+//! cursor over the body.  With the `bytes` feature, `NngMsgReader` implements `bytes::Buf` and
+//! [`NngMsgWriter`](struct.NngMsgWriter.html) implements `bytes::BufMut`.
+//!
+//! # Examples
+//! ```
+//! use runng::msg::NngMsg;
+//! use std::io::{Read, Write};
+//!
+//! fn test() -> runng::Result<()> {
+//!     let mut msg = NngMsg::new()?;
+//!     write!(msg, "hello {}", 42).unwrap();
+//!     let mut text = String::new();
+//!     msg.reader().read_to_string(&mut text).unwrap();
+//!     assert_eq!(text, "hello 42");
+//!     Ok(())
+//! }
+//! ```
+
+use super::NngMsg;
+use crate::*;
+use log::debug;
+use std::{cmp, io};
+
+impl io::Write for NngMsg {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.append_slice(buf).map_err(io::Error::from)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.append_slice(buf).map_err(io::Error::from)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl NngMsg {
+    /// Returns a cursor that reads the message body from the beginning.
+    pub fn reader(&self) -> NngMsgReader<'_> {
+        NngMsgReader { msg: self, pos: 0 }
+    }
+
+    /// Returns a writer that appends to the message body.
+    pub fn writer(&mut self) -> NngMsgWriter<'_> {
+        let len = self.len();
+        NngMsgWriter { msg: self, len }
+    }
+}
+
+/// Cursor over the body of a `NngMsg`.
+///
+/// Implements `io::Read`, `io::BufRead` and, with the `bytes` feature, `bytes::Buf`.
+#[derive(Debug)]
+pub struct NngMsgReader<'a> {
+    msg: &'a NngMsg,
+    pos: usize,
+}
+
+impl<'a> NngMsgReader<'a> {
+    /// Offset of the cursor into the message body.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Unread part of the message body.
+    pub fn remaining_slice(&self) -> &'a [u8] {
+        let body = self.msg.body();
+        &body[cmp::min(self.pos, body.len())..]
+    }
+
+    pub fn get_ref(&self) -> &'a NngMsg {
+        self.msg
+    }
+}
+
+impl<'a> io::Read for NngMsgReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.remaining_slice();
+        let len = cmp::min(buf.len(), remaining.len());
+        buf[..len].copy_from_slice(&remaining[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl<'a> io::BufRead for NngMsgReader<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining_slice())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+/// Appends to the body of a `NngMsg`.
+///
+/// With the `bytes` feature, implements `bytes::BufMut`.  The body grows ahead of what's written,
+/// and is shortened to what was written when the writer is dropped.
+#[derive(Debug)]
+pub struct NngMsgWriter<'a> {
+    msg: &'a mut NngMsg,
+    /// Length of the body written so far
+    len: usize,
+}
+
+impl<'a> NngMsgWriter<'a> {
+    /// Length of the body written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Make room to write at least `additional` more bytes without growing the message.
+    pub fn reserve(&mut self, additional: usize) -> Result<()> {
+        let spare = self.msg.len() - self.len;
+        if spare < additional {
+            self.msg.resize(self.len + additional, 0)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Drop for NngMsgWriter<'a> {
+    fn drop(&mut self) {
+        if let Err(err) = self.msg.truncate(self.len) {
+            debug!("Truncate failed: {:?}", err);
+        }
+    }
+}
+
+#[cfg(feature = "bytes")]
+mod bytes_impl {
+    use super::*;
+    use bytes::{Buf, BufMut};
+    use std::mem::MaybeUninit;
+
+    impl<'a> Buf for NngMsgReader<'a> {
+        fn remaining(&self) -> usize {
+            self.remaining_slice().len()
+        }
+
+        fn bytes(&self) -> &[u8] {
+            self.remaining_slice()
+        }
+
+        fn advance(&mut self, cnt: usize) {
+            assert!(
+                cnt <= self.remaining(),
+                "cannot advance past end of message"
+            );
+            self.pos += cnt;
+        }
+    }
+
+    // Minimum number of bytes reserved by `bytes_mut()`
+    const MIN_RESERVE: usize = 64;
+
+    /// # Panics
+    ///
+    /// `bytes_mut()` panics if the message can't grow.  Use `reserve()` beforehand to handle that.
+    impl<'a> BufMut for NngMsgWriter<'a> {
+        fn remaining_mut(&self) -> usize {
+            usize::MAX - self.len
+        }
+
+        unsafe fn advance_mut(&mut self, cnt: usize) {
+            assert!(
+                self.len + cnt <= self.msg.len(),
+                "cannot advance past reserved bytes"
+            );
+            self.len += cnt;
+        }
+
+        fn bytes_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+            if self.len == self.msg.len() {
+                let reserve = cmp::max(MIN_RESERVE, self.len);
+                self.reserve(reserve).expect("nng_msg_realloc");
+            }
+            // Bytes past what was written are zero-filled
+            let spare = &mut self.msg.as_mut_slice()[self.len..];
+            unsafe { &mut *(spare as *mut [u8] as *mut [MaybeUninit<u8>]) }
+        }
+    }
+}
@@ -1,5 +1,18 @@
 //! Messages.
 
+mod builder;
+mod encode;
+mod envelope;
+mod io;
+mod pool;
+
+pub use self::builder::*;
+pub use self::encode::*;
+pub use self::envelope::*;
+pub use self::io::*;
+pub use self::pool::*;
+pub use runng_derive::{NngDecode, NngEncode};
+
 use crate::*;
 use runng_derive::NngMsgOpts;
 use runng_sys::*;
@@ -18,7 +31,22 @@ impl NngMsg {
     }
 
     /// Create a message with body length `size_bytes`.  See [nng_msg_alloc](https://nng.nanomsg.org/man/v1.2.2/nng_msg_alloc.3).
+    ///
+    /// Same as `with_len()`.  Use `with_reserved()` for an empty body with room to grow.
     pub fn with_capacity(size_bytes: usize) -> Result<Self> {
+        NngMsg::with_len(size_bytes)
+    }
+
+    /// Create an empty message with room for `size_bytes` of body before it reallocates.
+    pub fn with_reserved(size_bytes: usize) -> Result<Self> {
+        let mut msg = NngMsg::with_len(size_bytes)?;
+        msg.chop(size_bytes)?;
+        Ok(msg)
+    }
+
+    /// Create a message with body length `size_bytes`.  The body is zero-filled.
+    /// See [nng_msg_alloc](https://nng.nanomsg.org/man/v1.2.2/nng_msg_alloc.3).
+    pub fn with_len(size_bytes: usize) -> Result<Self> {
         unsafe {
             let mut msg: *mut nng_msg = ptr::null_mut();
             let res = nng_msg_alloc(&mut msg, size_bytes);
@@ -86,6 +114,52 @@ impl NngMsg {
         unsafe { nng_msg_len(self.msg()) == 0 }
     }
 
+    // Change body length to `size`, keeping existing contents.  New bytes are uninitialized, so
+    // callers must fill or chop them before they can be read (see `set_len()`).
+    fn realloc(&mut self, size: usize) -> Result<()> {
+        unsafe { nng_int_to_result(nng_msg_realloc(self.msg(), size)) }
+    }
+
+    /// Make room for at least `additional` more bytes of body without changing its length.
+    pub fn reserve(&mut self, additional: usize) -> Result<()> {
+        // nng keeps the allocation when the body shrinks
+        let len = self.len();
+        self.realloc(len + additional)?;
+        self.chop(additional)
+    }
+
+    /// Shorten the body to `len` bytes.  Has no effect if it's already shorter.
+    pub fn truncate(&mut self, len: usize) -> Result<()> {
+        let current = self.len();
+        if len < current {
+            self.chop(current - len)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Change body length to `new_len`, filling any new bytes with `value`.
+    pub fn resize(&mut self, new_len: usize, value: u8) -> Result<()> {
+        let len = self.len();
+        if new_len <= len {
+            return self.truncate(new_len);
+        }
+        self.realloc(new_len)?;
+        for byte in &mut self.as_mut_slice()[len..] {
+            *byte = value;
+        }
+        Ok(())
+    }
+
+    /// Change body length to `len` without initializing new bytes.
+    ///
+    /// # Safety
+    ///
+    /// Bytes past the old length are uninitialized and must be written before they are read.
+    pub unsafe fn set_len(&mut self, len: usize) -> Result<()> {
+        self.realloc(len)
+    }
+
     pub fn append_slice(&mut self, data: &[u8]) -> Result<()> {
         self.append_ptr(data.as_ptr(), data.len())
     }
@@ -160,6 +234,12 @@ impl NngMsg {
         }
     }
 
+    pub fn header_clear(&mut self) {
+        unsafe {
+            nng_msg_header_clear(self.msg());
+        }
+    }
+
     pub fn get_pipe(&self) -> Option<pipe::NngPipe> {
         pipe::NngPipe::new(self)
     }
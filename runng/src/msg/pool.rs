@@ -0,0 +1,194 @@
+//! Recycle `NngMsg` allocations.
+//!
+//! # Examples
+//! ```
+//! use runng::{
+//!     factory::latest::ProtocolFactory,
+//!     msg::NngMsgPool,
+//!     protocol::*,
+//!     *,
+//! };
+//!
+//! fn test() -> runng::Result<()> {
+//!     const url: &str = "inproc://msg_pool";
+//!     let pool = NngMsgPool::new();
+//!     let factory = ProtocolFactory::default();
+//!     let mut pull = factory.puller_open()?;
+//!     pull.listen(url)?;
+//!     let mut push = factory.pusher_open()?;
+//!     push.dial(url)?;
+//!
+//!     let mut msg = pool.get(8)?;
+//!     msg.append_u64(42)?;
+//!     push.sendmsg(msg)?;
+//!     // Return the received message once done with it
+//!     let msg = pull.recvmsg()?;
+//!     pool.recycle(msg);
+//!     assert_eq!(pool.stats().pooled, 1);
+//!     Ok(())
+//! }
+//! ```
+
+use super::NngMsg;
+use crate::*;
+use std::sync::{Arc, Mutex};
+
+/// Counters of a `NngMsgPool`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// `get()` calls satisfied by a pooled message
+    pub hits: u64,
+    /// `get()` calls that allocated a new message
+    pub misses: u64,
+    /// Messages taken back by `recycle()`
+    pub recycled: u64,
+    /// Messages freed by `recycle()` because they were too large or their class was full
+    pub discarded: u64,
+    /// Messages currently in the pool
+    pub pooled: usize,
+}
+
+#[derive(Debug)]
+struct SizeClass {
+    size: usize,
+    free: Vec<NngMsg>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    classes: Vec<SizeClass>,
+    max_per_class: usize,
+    stats: PoolStats,
+}
+
+impl Inner {
+    // Index of the smallest class that holds `size` bytes
+    fn class_index(&self, size: usize) -> Option<usize> {
+        self.classes.iter().position(|class| class.size >= size)
+    }
+}
+
+/// Pool of pre-sized messages, grouped into size classes.
+///
+/// `get()` hands out an empty message with room for at least the requested size, `recycle()` takes
+/// back a message (typically one that was received), clears it and keeps it for later use.
+/// Messages are plain `NngMsg` so they can be sent with any `SendSocket` or asyncio context.  Once
+/// sent, nng owns the message, so only received messages and messages returned in `SendError` can
+/// be recycled.
+///
+/// Each size class holds at most `max_per_class` messages; recycling past that frees the message.
+/// Clones share the same pool.
+#[derive(Clone, Debug)]
+pub struct NngMsgPool {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl NngMsgPool {
+    /// Default size classes in bytes.
+    pub const DEFAULT_CLASSES: &'static [usize] = &[64, 256, 1024, 4096, 16 * 1024, 64 * 1024];
+    /// Default bound on pooled messages of each size class.
+    pub const DEFAULT_MAX_PER_CLASS: usize = 64;
+
+    /// Create pool with `DEFAULT_CLASSES` and `DEFAULT_MAX_PER_CLASS`.
+    pub fn new() -> Self {
+        Self::with_classes(Self::DEFAULT_CLASSES, Self::DEFAULT_MAX_PER_CLASS)
+    }
+
+    /// Create pool with the given size classes that each hold at most `max_per_class` messages.
+    pub fn with_classes(classes: &[usize], max_per_class: usize) -> Self {
+        let mut sizes = classes.to_vec();
+        sizes.sort_unstable();
+        sizes.dedup();
+        let classes = sizes
+            .into_iter()
+            .map(|size| SizeClass {
+                size,
+                free: Vec::new(),
+            })
+            .collect();
+        let inner = Inner {
+            classes,
+            max_per_class,
+            stats: PoolStats::default(),
+        };
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Get an empty message with room for at least `size` bytes of body.
+    ///
+    /// Sizes larger than the largest class are allocated directly.
+    pub fn get(&self, size: usize) -> Result<NngMsg> {
+        let capacity = {
+            let mut guard = self.inner.lock().unwrap();
+            let inner = &mut *guard;
+            let capacity = match inner.class_index(size) {
+                Some(index) => {
+                    let class = &mut inner.classes[index];
+                    if let Some(msg) = class.free.pop() {
+                        inner.stats.hits += 1;
+                        inner.stats.pooled -= 1;
+                        return Ok(msg);
+                    }
+                    class.size
+                }
+                None => size,
+            };
+            inner.stats.misses += 1;
+            capacity
+        };
+        NngMsg::with_reserved(capacity)
+    }
+
+    /// Return `msg` to the pool.
+    ///
+    /// `msg` is cleared and kept by the class that holds its length.  Messages smaller than their
+    /// class are grown to the class size first.  nng can't shrink a message, so a trimmed message
+    /// keeps whatever it allocated before.
+    pub fn recycle(&self, mut msg: NngMsg) {
+        let (index, size) = {
+            let mut inner = self.inner.lock().unwrap();
+            inner.stats.recycled += 1;
+            match inner.class_index(msg.len()) {
+                Some(index) if inner.classes[index].free.len() < inner.max_per_class => {
+                    (index, inner.classes[index].size)
+                }
+                _ => {
+                    inner.stats.discarded += 1;
+                    return;
+                }
+            }
+        };
+        msg.clear();
+        msg.header_clear();
+        let reserved = msg.reserve(size);
+        let mut inner = self.inner.lock().unwrap();
+        // Class may have filled up while the lock was released
+        if reserved.is_err() || inner.classes[index].free.len() >= inner.max_per_class {
+            inner.stats.discarded += 1;
+            return;
+        }
+        inner.classes[index].free.push(msg);
+        inner.stats.pooled += 1;
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.inner.lock().unwrap().stats
+    }
+
+    /// Free all pooled messages.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        for class in inner.classes.iter_mut() {
+            class.free.clear();
+        }
+        inner.stats.pooled = 0;
+    }
+}
+
+impl Default for NngMsgPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,575 @@
+//! Multiplex bidirectional streams over a pair socket.
+//!
+//! Every message on the socket is a frame:
+//! ```text
+//! [stream id: u32][kind: u16][payload]
+//! ```
+//!
+//! | Kind     | Payload                          |
+//! |----------|----------------------------------|
+//! | `Open`   | `[window: u32][name]`            |
+//! | `Data`   | Message body                     |
+//! | `Close`  | None.  Sender won't send more    |
+//! | `Reset`  | `[code: u32]`.  Stream aborted   |
+//! | `Window` | `[credit: u32]`                  |
+//!
+//! Flow control is per stream and counted in messages.  Each side may only send as many `Data`
+//! frames as the other side has granted with its `Open` window and `Window` frames.  Credit is
+//! granted back as received messages are consumed.
+//!
+//! Streams opened by the client side have odd ids, those opened by the server side have even ids.
+//!
+//! # Examples
+//! ```
+//! use futures::{executor::block_on, SinkExt, StreamExt};
+//! use runng::{asyncio::*, msg::NngMsg, mux::*, protocol::*, *};
+//!
+//! fn test() -> runng::Result<()> {
+//!     let url = "inproc://mux";
+//!     let mut server = Pair1::open()?;
+//!     server.listen(url)?;
+//!     let mut client = Pair1::open()?;
+//!     client.dial(url)?;
+//!
+//!     let (client, driver) = Mux::new(&client, MuxConfig::client())?;
+//!     std::thread::spawn(move || block_on(driver));
+//!     let (mut server, driver) = Mux::new(&server, MuxConfig::server())?;
+//!     std::thread::spawn(move || block_on(driver));
+//!
+//!     let mut stream = client.open("tail")?;
+//!     block_on(stream.send(NngMsg::new()?))?;
+//!     let mut accepted = block_on(server.next()).unwrap();
+//!     assert_eq!(accepted.name(), "tail");
+//!     let _msg = block_on(accepted.next()).unwrap()?;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::{
+    asyncio::*,
+    msg::{NngDecode, NngEncode, NngMsg, NngMsgBuilder},
+    *,
+};
+use futures::{
+    channel::mpsc,
+    future::{self, BoxFuture, FutureExt},
+    stream::{Stream, StreamExt},
+    task::{Context, Poll, Waker},
+    Sink,
+};
+use log::debug;
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+/// Reset code when a stream is dropped or reset without a reason.
+pub const RESET_CANCEL: u32 = 0;
+/// Reset code when the peer sent more data than its credit.
+pub const RESET_FLOW_CONTROL: u32 = 1;
+/// Reset code when a frame arrives for a stream that doesn't exist.
+pub const RESET_UNKNOWN_STREAM: u32 = 2;
+
+/// Future that performs I/O for a `Mux`.  Completes once the socket is closed.
+pub type MuxDriver = BoxFuture<'static, ()>;
+
+/// Identifies a stream of a `Mux`.
+pub type StreamId = u32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FrameKind {
+    Open = 0,
+    Data = 1,
+    Close = 2,
+    Reset = 3,
+    Window = 4,
+}
+
+impl FrameKind {
+    fn from_u16(kind: u16) -> Option<Self> {
+        match kind {
+            0 => Some(FrameKind::Open),
+            1 => Some(FrameKind::Data),
+            2 => Some(FrameKind::Close),
+            3 => Some(FrameKind::Reset),
+            4 => Some(FrameKind::Window),
+            _ => None,
+        }
+    }
+}
+
+fn frame(id: StreamId, kind: FrameKind, mut msg: NngMsg) -> Result<NngMsg> {
+    msg.insert_u16(kind as u16)?;
+    msg.insert_u32(id)?;
+    Ok(msg)
+}
+
+fn control_frame(id: StreamId, kind: FrameKind, value: u32) -> Result<NngMsg> {
+    // id, kind and value
+    let mut builder = NngMsgBuilder::with_capacity(4 + 2 + 4)?;
+    builder.put_u32(id)?.put_u16(kind as u16)?.put_u32(value)?;
+    Ok(builder.build())
+}
+
+/// Which side of the connection a `Mux` is.  Determines ids of streams it opens.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MuxSide {
+    Client,
+    Server,
+}
+
+/// Configuration of a `Mux`.
+#[derive(Clone, Copy, Debug)]
+pub struct MuxConfig {
+    pub side: MuxSide,
+    /// Number of messages each stream buffers before the peer must wait for credit.
+    pub window: u32,
+}
+
+impl MuxConfig {
+    pub const DEFAULT_WINDOW: u32 = 16;
+
+    pub fn client() -> Self {
+        Self {
+            side: MuxSide::Client,
+            window: Self::DEFAULT_WINDOW,
+        }
+    }
+
+    pub fn server() -> Self {
+        Self {
+            side: MuxSide::Server,
+            window: Self::DEFAULT_WINDOW,
+        }
+    }
+
+    pub fn window(mut self, window: u32) -> Self {
+        self.window = window;
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+struct StreamState {
+    received: VecDeque<NngMsg>,
+    recv_waker: Option<Waker>,
+    /// Peer sent `Close`
+    recv_closed: bool,
+    /// Messages consumed since credit was last granted to the peer
+    consumed: u32,
+    send_credit: u32,
+    send_waker: Option<Waker>,
+    /// Sent `Close`
+    send_closed: bool,
+    reset: Option<u32>,
+}
+
+impl StreamState {
+    fn wake(&mut self) {
+        if let Some(waker) = self.recv_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.send_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.reset.is_some() || (self.recv_closed && self.send_closed)
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: MuxConfig,
+    next_id: StreamId,
+    streams: HashMap<StreamId, StreamState>,
+    /// Taken once closed so the writer ends
+    frames: Option<mpsc::UnboundedSender<NngMsg>>,
+    incoming: Option<mpsc::UnboundedSender<MuxStream>>,
+    closed: bool,
+}
+
+impl Shared {
+    fn send_frame(&self, msg: Result<NngMsg>) {
+        match (msg, self.frames.as_ref()) {
+            (Ok(msg), Some(frames)) => {
+                if let Err(err) = frames.unbounded_send(msg) {
+                    debug!("Dropping frame: {:?}", err);
+                }
+            }
+            (Ok(_), None) => debug!("Dropping frame: mux closed"),
+            (Err(err), _) => debug!("Failed to create frame: {:?}", err),
+        }
+    }
+
+    fn reset(&mut self, id: StreamId, code: u32) {
+        if let Some(state) = self.streams.get_mut(&id) {
+            state.reset = Some(code);
+            state.wake();
+        }
+        self.send_frame(control_frame(id, FrameKind::Reset, code));
+    }
+
+    /// Handle frame received from the peer.
+    ///
+    /// Returns a stream that was refused because the `Mux` was dropped.  It must be dropped after
+    /// releasing the lock.
+    fn receive(
+        &mut self,
+        shared: &Arc<Mutex<Shared>>,
+        mut msg: NngMsg,
+    ) -> Result<Option<MuxStream>> {
+        let id = u32::decode(&mut msg)?;
+        let kind = u16::decode(&mut msg)?;
+        let kind = FrameKind::from_u16(kind)
+            .ok_or_else(|| Error::Decode(format!("Invalid frame kind {}", kind)))?;
+        if kind == FrameKind::Open {
+            let credit = u32::decode(&mut msg)?;
+            let name = String::decode(&mut msg)?;
+            return self.accept(shared, id, credit, name);
+        }
+
+        let window = self.config.window;
+        let state = match self.streams.get_mut(&id) {
+            Some(state) => state,
+            None => {
+                debug!("Frame {:?} for unknown stream {}", kind, id);
+                if kind != FrameKind::Reset {
+                    self.send_frame(control_frame(id, FrameKind::Reset, RESET_UNKNOWN_STREAM));
+                }
+                return Ok(None);
+            }
+        };
+        match kind {
+            FrameKind::Open => unreachable!(),
+            FrameKind::Data => {
+                if state.received.len() as u32 >= window {
+                    self.reset(id, RESET_FLOW_CONTROL);
+                    return Ok(None);
+                }
+                state.received.push_back(msg);
+                if let Some(waker) = state.recv_waker.take() {
+                    waker.wake();
+                }
+            }
+            FrameKind::Close => {
+                state.recv_closed = true;
+                state.wake();
+            }
+            FrameKind::Reset => {
+                state.reset = Some(u32::decode(&mut msg)?);
+                state.wake();
+            }
+            FrameKind::Window => {
+                state.send_credit += u32::decode(&mut msg)?;
+                if let Some(waker) = state.send_waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn accept(
+        &mut self,
+        shared: &Arc<Mutex<Shared>>,
+        id: StreamId,
+        credit: u32,
+        name: String,
+    ) -> Result<Option<MuxStream>> {
+        if self.streams.contains_key(&id) {
+            debug!("Stream {} already open", id);
+            self.reset(id, RESET_CANCEL);
+            return Ok(None);
+        }
+        let state = StreamState {
+            send_credit: credit,
+            ..Default::default()
+        };
+        self.streams.insert(id, state);
+        let stream = MuxStream {
+            id,
+            name,
+            shared: shared.clone(),
+        };
+        let accepted = match &self.incoming {
+            Some(incoming) => incoming
+                .unbounded_send(stream)
+                .map_err(|err| err.into_inner()),
+            None => Err(stream),
+        };
+        match accepted {
+            Ok(()) => {
+                self.send_frame(control_frame(id, FrameKind::Window, self.config.window));
+                Ok(None)
+            }
+            // Mux was dropped, dropping the stream resets it
+            Err(stream) => Ok(Some(stream)),
+        }
+    }
+
+    /// Socket closed, no more frames will arrive.
+    fn close(&mut self) {
+        self.closed = true;
+        self.incoming = None;
+        self.frames = None;
+        for state in self.streams.values_mut() {
+            if state.reset.is_none() {
+                state.reset = Some(RESET_CANCEL);
+            }
+            state.wake();
+        }
+    }
+}
+
+/// Multiplexes `MuxStream`s over a pair socket.
+///
+/// Is a `Stream` of streams opened by the peer.
+#[derive(Debug)]
+pub struct Mux {
+    shared: Arc<Mutex<Shared>>,
+    incoming: mpsc::UnboundedReceiver<MuxStream>,
+}
+
+impl Mux {
+    /// Create a `Mux` using `socket` and the future that must be run to perform I/O.
+    pub fn new<S: GetSocket>(socket: &S, config: MuxConfig) -> Result<(Self, MuxDriver)> {
+        let handle = PairAsyncHandle::new(socket.socket().clone())?;
+        let (frames, frames_receiver) = mpsc::unbounded();
+        let (incoming_sender, incoming) = mpsc::unbounded();
+        let next_id = match config.side {
+            MuxSide::Client => 1,
+            MuxSide::Server => 2,
+        };
+        let shared = Shared {
+            config,
+            next_id,
+            streams: HashMap::new(),
+            frames: Some(frames),
+            incoming: Some(incoming_sender),
+            closed: false,
+        };
+        let shared = Arc::new(Mutex::new(shared));
+        let handle = Arc::new(Mutex::new(handle));
+        let reader = read_frames(handle.clone(), shared.clone());
+        let writer = write_frames(handle, frames_receiver);
+        let driver = future::join(reader, writer).map(|_| ());
+        let mux = Self { shared, incoming };
+        Ok((mux, Box::pin(driver)))
+    }
+
+    /// Open a new stream.  `name` is available to the peer with [`MuxStream::name()`](struct.MuxStream.html#method.name).
+    pub fn open(&self, name: &str) -> Result<MuxStream> {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.closed {
+            return Err(Error::Errno(NngErrno::ECLOSED));
+        }
+        let id = shared.next_id;
+        shared.next_id = id.wrapping_add(2);
+
+        let mut msg = NngMsg::new()?;
+        shared.config.window.encode(&mut msg)?;
+        name.encode(&mut msg)?;
+        let msg = frame(id, FrameKind::Open, msg)?;
+        // No send credit until the peer grants its window
+        shared.streams.insert(id, StreamState::default());
+        shared.send_frame(Ok(msg));
+        Ok(MuxStream {
+            id,
+            name: name.to_owned(),
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+impl Stream for Mux {
+    type Item = MuxStream;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.incoming).poll_next(cx)
+    }
+}
+
+async fn read_frames(handle: Arc<Mutex<PairAsyncHandle>>, shared: Arc<Mutex<Shared>>) {
+    loop {
+        let msg = handle.lock().unwrap().receive();
+        match msg.await {
+            Ok(msg) => {
+                let res = shared.lock().unwrap().receive(&shared, msg);
+                match res {
+                    Ok(refused) => drop(refused),
+                    Err(err) => debug!("Invalid frame: {:?}", err),
+                }
+            }
+            Err(ref err) if err.is_closed() => break,
+            Err(err) => debug!("Mux receive failed: {:?}", err),
+        }
+    }
+    shared.lock().unwrap().close();
+}
+
+async fn write_frames(
+    handle: Arc<Mutex<PairAsyncHandle>>,
+    mut frames: mpsc::UnboundedReceiver<NngMsg>,
+) {
+    while let Some(msg) = frames.next().await {
+        let res = handle.lock().unwrap().send(msg);
+        match res.await {
+            Ok(()) => {}
+            Err(ref err) if err.is_closed() => break,
+            Err(err) => debug!("Mux send failed: {:?}", err),
+        }
+    }
+}
+
+/// Logical bidirectional stream of a `Mux`.
+///
+/// Is a `Stream` of messages received from and a `Sink` of messages sent to the peer.
+/// Closing the sink tells the peer no more messages will be sent.
+/// Dropping the stream before both sides closed it resets it.
+/// Sending before `poll_ready()` is ready fails with `EAGAIN` rather than exceeding the window.
+#[derive(Debug)]
+pub struct MuxStream {
+    id: StreamId,
+    name: String,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl MuxStream {
+    pub fn id(&self) -> StreamId {
+        self.id
+    }
+
+    /// Name passed to [`Mux::open()`](struct.Mux.html#method.open).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Abort the stream in both directions.
+    pub fn reset(&self, code: u32) {
+        self.shared.lock().unwrap().reset(self.id, code);
+    }
+
+    /// Code of the reset that aborted the stream, if any.
+    pub fn reset_code(&self) -> Option<u32> {
+        let shared = self.shared.lock().unwrap();
+        shared.streams.get(&self.id).and_then(|state| state.reset)
+    }
+}
+
+fn reset_error() -> Error {
+    Error::Errno(NngErrno::ECONNRESET)
+}
+
+/// Stream is no longer part of the `Mux`.
+fn closed_error() -> Error {
+    Error::Errno(NngErrno::ECLOSED)
+}
+
+impl Stream for MuxStream {
+    type Item = Result<NngMsg>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = self.shared.lock().unwrap();
+        let window = shared.config.window;
+        let state = match shared.streams.get_mut(&self.id) {
+            Some(state) => state,
+            None => return Poll::Ready(None),
+        };
+        if let Some(msg) = state.received.pop_front() {
+            state.consumed += 1;
+            // Grant credit back to peer in batches
+            if state.consumed >= (window / 2).max(1) && !state.recv_closed {
+                let credit = state.consumed;
+                state.consumed = 0;
+                shared.send_frame(control_frame(self.id, FrameKind::Window, credit));
+            }
+            Poll::Ready(Some(Ok(msg)))
+        } else if state.reset.is_some() {
+            if state.recv_closed {
+                Poll::Ready(None)
+            } else {
+                // Report the reset once
+                state.recv_closed = true;
+                Poll::Ready(Some(Err(reset_error())))
+            }
+        } else if state.recv_closed {
+            Poll::Ready(None)
+        } else {
+            state.recv_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl Sink<NngMsg> for MuxStream {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut shared = self.shared.lock().unwrap();
+        let state = match shared.streams.get_mut(&self.id) {
+            Some(state) => state,
+            None => return Poll::Ready(Err(closed_error())),
+        };
+        if state.reset.is_some() {
+            Poll::Ready(Err(reset_error()))
+        } else if state.send_closed {
+            Poll::Ready(Err(Error::Errno(NngErrno::ECLOSED)))
+        } else if state.send_credit > 0 {
+            Poll::Ready(Ok(()))
+        } else {
+            state.send_waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: NngMsg) -> Result<()> {
+        let mut shared = self.shared.lock().unwrap();
+        let state = shared.streams.get_mut(&self.id).ok_or_else(closed_error)?;
+        if state.reset.is_some() {
+            return Err(reset_error());
+        }
+        // Only if `poll_ready()` wasn't `Ready(Ok)` first
+        if state.send_credit == 0 {
+            return Err(Error::Errno(NngErrno::EAGAIN));
+        }
+        state.send_credit -= 1;
+        let msg = frame(self.id, FrameKind::Data, msg)?;
+        shared.send_frame(Ok(msg));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        // Frames are queued for the driver
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let mut shared = self.shared.lock().unwrap();
+        let state = match shared.streams.get_mut(&self.id) {
+            Some(state) => state,
+            None => return Poll::Ready(Ok(())),
+        };
+        if !state.send_closed && state.reset.is_none() {
+            state.send_closed = true;
+            shared.send_frame(frame(self.id, FrameKind::Close, NngMsg::new()?));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        let is_done = match shared.streams.get(&self.id) {
+            Some(state) => state.is_done(),
+            None => true,
+        };
+        if !is_done && !shared.closed {
+            shared.reset(self.id, RESET_CANCEL);
+        }
+        shared.streams.remove(&self.id);
+    }
+}
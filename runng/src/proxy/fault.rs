@@ -0,0 +1,205 @@
+//! Fault injection for `Proxy`.
+
+use super::ConnectionId;
+use crate::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Network conditions a [`Proxy`](struct.Proxy.html) injects into its connections.
+///
+/// Conditions can be changed at any time, including while connections are open.  Clones control the
+/// same conditions.  Each direction of each connection draws random disconnects from its own
+/// generator, seeded from the `Faults` seed and its `ConnectionId`, so test runs are repeatable
+/// however threads are scheduled.
+///
+/// # Examples
+/// ```
+/// use runng::proxy::*;
+/// use std::time::Duration;
+///
+/// fn test() -> runng::Result<()> {
+///     let faults = Faults::new();
+///     let config = ProxyConfig::default().faults(faults.clone());
+///     let proxy = Proxy::start("ipc:///tmp/runng_faults", "tcp://127.0.0.1:5555", config)?;
+///     faults.set_delay(Duration::from_millis(50));
+///     // Hold all traffic, then let it through
+///     faults.stall();
+///     faults.resume();
+///     // Close accepted connections right away to exercise reconnecting
+///     faults.set_refuse(true);
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Faults {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    conditions: Mutex<Conditions>,
+    resumed: Condvar,
+}
+
+#[derive(Debug)]
+struct Conditions {
+    delay: Duration,
+    bandwidth: Option<u64>,
+    disconnect_probability: f64,
+    stalled: bool,
+    refuse: bool,
+    seed: u64,
+}
+
+// How often stalled connections check whether the proxy closed
+const STALL_POLL: Duration = Duration::from_millis(100);
+
+impl Faults {
+    /// No faults, random disconnects use seed 0.
+    pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+
+    /// No faults, random disconnects use `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        let conditions = Conditions {
+            delay: Duration::from_millis(0),
+            bandwidth: None,
+            disconnect_probability: 0.0,
+            stalled: false,
+            refuse: false,
+            seed,
+        };
+        Self {
+            inner: Arc::new(Inner {
+                conditions: Mutex::new(conditions),
+                resumed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Delay each chunk of data by `delay` before forwarding it.
+    pub fn set_delay(&self, delay: Duration) {
+        self.conditions().delay = delay;
+    }
+
+    /// Limit each direction of each connection to `bytes_per_sec`, or `None` for no limit.
+    pub fn set_bandwidth(&self, bytes_per_sec: Option<u64>) {
+        assert!(bytes_per_sec != Some(0), "Use stall() to stop traffic");
+        self.conditions().bandwidth = bytes_per_sec;
+    }
+
+    /// Close a connection instead of forwarding a chunk with the given probability (0 to 1).
+    pub fn set_disconnect_probability(&self, probability: f64) {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "Probability must be between 0 and 1"
+        );
+        self.conditions().disconnect_probability = probability;
+    }
+
+    /// Hold all data until `resume()`.  Connections stay open.
+    pub fn stall(&self) {
+        self.conditions().stalled = true;
+    }
+
+    /// Forward data held by `stall()`.
+    pub fn resume(&self) {
+        self.conditions().stalled = false;
+        self.inner.resumed.notify_all();
+    }
+
+    /// Close new connections as soon as they're accepted.
+    pub fn set_refuse(&self, refuse: bool) {
+        self.conditions().refuse = refuse;
+    }
+
+    /// Remove all faults.
+    pub fn clear(&self) {
+        {
+            let mut conditions = self.conditions();
+            conditions.delay = Duration::from_millis(0);
+            conditions.bandwidth = None;
+            conditions.disconnect_probability = 0.0;
+            conditions.stalled = false;
+            conditions.refuse = false;
+        }
+        self.inner.resumed.notify_all();
+    }
+
+    fn conditions(&self) -> std::sync::MutexGuard<'_, Conditions> {
+        self.inner.conditions.lock().unwrap()
+    }
+
+    pub(super) fn refusing(&self) -> bool {
+        self.conditions().refuse
+    }
+
+    /// Generator for one direction of connection `id`.
+    pub(super) fn rng(&self, id: ConnectionId, to_target: bool) -> StdRng {
+        let seed = self.conditions().seed;
+        // Spread consecutive ids over the seed space (splitmix64 increment)
+        let stream = id.wrapping_mul(2).wrapping_add(to_target as u64);
+        StdRng::seed_from_u64(seed ^ stream.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    /// Apply faults before forwarding `len` bytes.  Fails if the connection should be closed.
+    pub(super) fn before_forward(
+        &self,
+        len: usize,
+        rng: &mut StdRng,
+        closed: &AtomicBool,
+    ) -> Result<()> {
+        let mut conditions = self.conditions();
+        while conditions.stalled && !closed.load(Ordering::SeqCst) {
+            let (guard, _) = self
+                .inner
+                .resumed
+                .wait_timeout(conditions, STALL_POLL)
+                .unwrap();
+            conditions = guard;
+        }
+        let probability = conditions.disconnect_probability;
+        if probability > 0.0 && rng.gen_bool(probability) {
+            debug!("Injecting disconnect");
+            return Err(Error::Errno(NngErrno::ECONNRESET));
+        }
+        // Time the data would take to cross a link of limited bandwidth
+        let transfer = conditions.bandwidth.map_or(0, |bytes_per_sec| {
+            len as u64 * 1_000_000_000 / bytes_per_sec
+        });
+        let deadline = Instant::now() + conditions.delay + Duration::from_nanos(transfer);
+        // Wait on the condvar rather than sleep so closing the proxy cuts the delay short
+        while !closed.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            let timeout = std::cmp::min(deadline - now, STALL_POLL);
+            let (guard, _) = self
+                .inner
+                .resumed
+                .wait_timeout(conditions, timeout)
+                .unwrap();
+            conditions = guard;
+        }
+        Ok(())
+    }
+
+    /// Wake stalled and delayed connections so they notice the proxy closed.
+    pub(super) fn wake(&self) {
+        self.inner.resumed.notify_all();
+    }
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Self::new()
+    }
+}
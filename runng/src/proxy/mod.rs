@@ -0,0 +1,458 @@
+//! Forward byte streams between nng stream endpoints.
+//!
+//! A [`Proxy`](struct.Proxy.html) accepts connections on one URL (e.g. `ipc://`) and connects each
+//! to a target URL (e.g. `tcp://`), then copies bytes in both directions.
+//!
+//! nng streams can't shut down one direction, so once one side reaches the end of its stream the
+//! proxy closes the other side after forwarding data it already received.
+//!
+//! [`Faults`](struct.Faults.html) inject delay, bandwidth limits, stalls and disconnects for
+//! testing how sockets cope with a bad network.
+//!
+//! # Examples
+//! ```
+//! use runng::proxy::*;
+//!
+//! fn test() -> runng::Result<()> {
+//!     let proxy = Proxy::start("ipc:///tmp/runng_proxy", "tcp://127.0.0.1:5555", ProxyConfig::default())?;
+//!     // Sockets dialing ipc:///tmp/runng_proxy now reach tcp://127.0.0.1:5555
+//!     println!("Forwarded {} bytes", proxy.stats().bytes_to_target);
+//!     proxy.close();
+//!     Ok(())
+//! }
+//! ```
+
+mod fault;
+
+pub use self::fault::*;
+
+use crate::{asyncio::*, *};
+use futures::{channel::mpsc, executor::block_on, stream::StreamExt};
+use rand::rngs::StdRng;
+use runng_sys::{nng_duration, nng_iov};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc as std_mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+/// Identifies a connection through a `Proxy`.
+pub type ConnectionId = u64;
+
+/// Configuration of a `Proxy`.
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    buffer_size: usize,
+    max_connections: usize,
+    connect_timeout: nng_duration,
+    faults: Faults,
+}
+
+impl ProxyConfig {
+    pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+    pub const DEFAULT_MAX_CONNECTIONS: usize = 128;
+    /// Milliseconds
+    pub const DEFAULT_CONNECT_TIMEOUT: nng_duration = 5_000;
+
+    /// Size of the buffer used for each direction of a connection.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        assert!(buffer_size > 0, "Buffer can't be empty");
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Connections open at the same time.  Each takes two threads; further connections aren't
+    /// accepted until one closes.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        assert!(max_connections > 0, "Need at least one connection");
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Fail connecting to the target with `ETIMEDOUT` after `timeout` milliseconds.
+    pub fn connect_timeout(mut self, timeout: nng_duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Conditions to inject into connections.  Keep a clone to change them while running.
+    pub fn faults(mut self, faults: Faults) -> Self {
+        self.faults = faults;
+        self
+    }
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            buffer_size: Self::DEFAULT_BUFFER_SIZE,
+            max_connections: Self::DEFAULT_MAX_CONNECTIONS,
+            connect_timeout: Self::DEFAULT_CONNECT_TIMEOUT,
+            faults: Faults::new(),
+        }
+    }
+}
+
+/// Connection events reported by a `Proxy`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProxyEvent {
+    /// Accepted a connection and connected it to the target
+    Connected { id: ConnectionId },
+    /// Accepted a connection but couldn't connect to the target
+    ConnectFailed { id: ConnectionId, error: Error },
+    /// Closed an accepted connection because `Faults` refuse connections
+    Refused { id: ConnectionId },
+    /// Connection closed after forwarding `to_target` and `from_target` bytes
+    Closed {
+        id: ConnectionId,
+        to_target: u64,
+        from_target: u64,
+    },
+}
+
+/// Counters of a `Proxy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProxyStats {
+    /// Connections accepted
+    pub accepted: u64,
+    /// Connections that failed to connect to the target
+    pub failed: u64,
+    /// Connections closed by `Faults::set_refuse()`
+    pub refused: u64,
+    /// Connections currently open
+    pub active: usize,
+    /// Bytes forwarded from accepted connections to the target
+    pub bytes_to_target: u64,
+    /// Bytes forwarded from the target to accepted connections
+    pub bytes_from_target: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    accepted: AtomicU64,
+    failed: AtomicU64,
+    refused: AtomicU64,
+    active: AtomicUsize,
+    bytes_to_target: AtomicU64,
+    bytes_from_target: AtomicU64,
+}
+
+/// Accepted and target streams of a connection
+type StreamPair = (Arc<NngStream>, Arc<NngStream>);
+
+#[derive(Debug)]
+struct Shared {
+    target: String,
+    config: ProxyConfig,
+    counters: Counters,
+    events: mpsc::UnboundedSender<ProxyEvent>,
+    closed: AtomicBool,
+    /// Both streams of open connections so `close()` can end them
+    connections: Mutex<HashMap<ConnectionId, StreamPair>>,
+}
+
+impl Shared {
+    fn event(&self, event: ProxyEvent) {
+        // Nobody may be listening
+        let _ = self.events.unbounded_send(event);
+    }
+}
+
+/// Forwards connections accepted on one URL to another.
+///
+/// Runs on background threads until `close()` is called or it is dropped.
+#[derive(Debug)]
+pub struct Proxy {
+    listener: Arc<StreamListener>,
+    shared: Arc<Shared>,
+    events: Option<mpsc::UnboundedReceiver<ProxyEvent>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Proxy {
+    /// Listen on `url` and forward connections to `target`.
+    pub fn start(url: &str, target: &str, config: ProxyConfig) -> Result<Self> {
+        let listener = StreamListener::alloc(url)?;
+        listener.listen()?;
+        let listener = Arc::new(listener);
+        let (sender, receiver) = mpsc::unbounded();
+        let shared = Arc::new(Shared {
+            target: target.to_owned(),
+            config,
+            counters: Counters::default(),
+            events: sender,
+            closed: AtomicBool::new(false),
+            connections: Mutex::new(HashMap::new()),
+        });
+        let thread = {
+            let listener = listener.clone();
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("runng-proxy".to_owned())
+                .spawn(move || accept_loop(&listener, shared))
+                .map_err(|_| Error::Errno(NngErrno::ENOMEM))?
+        };
+        Ok(Self {
+            listener,
+            shared,
+            events: Some(receiver),
+            thread: Some(thread),
+        })
+    }
+
+    /// Listener accepting connections.  Use to query options like `NngOption::TCP_BOUND_PORT`.
+    pub fn listener(&self) -> &StreamListener {
+        &self.listener
+    }
+
+    /// Take the stream of connection events.  Returns `None` if already taken.
+    pub fn take_events(&mut self) -> Option<mpsc::UnboundedReceiver<ProxyEvent>> {
+        self.events.take()
+    }
+
+    pub fn stats(&self) -> ProxyStats {
+        let counters = &self.shared.counters;
+        ProxyStats {
+            accepted: counters.accepted.load(Ordering::Relaxed),
+            failed: counters.failed.load(Ordering::Relaxed),
+            refused: counters.refused.load(Ordering::Relaxed),
+            active: counters.active.load(Ordering::Relaxed),
+            bytes_to_target: counters.bytes_to_target.load(Ordering::Relaxed),
+            bytes_from_target: counters.bytes_from_target.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Close all open connections but keep accepting new ones.
+    pub fn disconnect_all(&self) {
+        for (local, remote) in self.shared.connections.lock().unwrap().values() {
+            local.close();
+            remote.close();
+        }
+    }
+
+    /// Stop accepting, close all connections, and wait for background threads to exit.
+    pub fn close(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.shared.closed.store(true, Ordering::SeqCst);
+        self.listener.close();
+        self.disconnect_all();
+        self.shared.config.faults.wake();
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .unwrap_or_else(|_| debug!("Proxy thread panicked"));
+        }
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn accept_loop(listener: &StreamListener, shared: Arc<Shared>) {
+    let mut threads = HashMap::new();
+    // Connection threads send their id once done so their handles can be joined
+    let (finished_sender, finished) = std_mpsc::channel();
+    let mut incoming = match listener.incoming(shared.config.max_connections) {
+        Ok(incoming) => incoming,
+        Err(err) => {
+            debug!("Proxy failed to accept: {:?}", err);
+            return;
+        }
+    };
+    let mut next_id: ConnectionId = 1;
+    while let Some(res) = block_on(incoming.next()) {
+        let stream = match res {
+            Ok(stream) => stream,
+            // The dialer gave up before the connection was accepted
+            Err(ref err)
+                if err.errno() == Some(NngErrno::ECONNABORTED)
+                    || err.errno() == Some(NngErrno::ECONNRESET) =>
+            {
+                debug!("Proxy accept failed: {:?}", err);
+                continue;
+            }
+            Err(err) => {
+                debug!("Proxy stopped accepting: {:?}", err);
+                break;
+            }
+        };
+        let id = next_id;
+        next_id += 1;
+        shared.counters.accepted.fetch_add(1, Ordering::Relaxed);
+        let shared = shared.clone();
+        let finished_sender = finished_sender.clone();
+        let thread = thread::Builder::new()
+            .name(format!("runng-proxy-{}", id))
+            .spawn(move || {
+                connection(id, stream, shared);
+                let _ = finished_sender.send(id);
+            });
+        match thread {
+            Ok(thread) => {
+                threads.insert(id, thread);
+            }
+            Err(err) => debug!("Proxy failed to spawn thread: {:?}", err),
+        }
+        // Don't accumulate handles of finished connections
+        while let Ok(id) = finished.try_recv() {
+            if let Some(thread) = threads.remove(&id) {
+                let _ = thread.join();
+            }
+        }
+    }
+    for (_, thread) in threads {
+        let _ = thread.join();
+    }
+}
+
+fn connect(target: &str, timeout: nng_duration) -> Result<NngStream> {
+    let mut dialer = StreamDialer::alloc(target)?;
+    let mut queue = SimpleAioWorkQueue::new()?;
+    // Unreachable targets would otherwise hold up `close()`
+    queue.set_timeout(timeout);
+    let receiver = dialer.dial(&mut queue);
+    block_on(receiver)?
+}
+
+fn connection(id: ConnectionId, local: NngStream, shared: Arc<Shared>) {
+    if shared.config.faults.refusing() {
+        local.close();
+        shared.counters.refused.fetch_add(1, Ordering::Relaxed);
+        shared.event(ProxyEvent::Refused { id });
+        return;
+    }
+    if shared.closed.load(Ordering::SeqCst) {
+        return;
+    }
+    let remote = match connect(&shared.target, shared.config.connect_timeout) {
+        Ok(remote) => remote,
+        Err(error) => {
+            shared.counters.failed.fetch_add(1, Ordering::Relaxed);
+            shared.event(ProxyEvent::ConnectFailed { id, error });
+            return;
+        }
+    };
+    let local = Arc::new(local);
+    let remote = Arc::new(remote);
+    {
+        let mut connections = shared.connections.lock().unwrap();
+        if shared.closed.load(Ordering::SeqCst) {
+            return;
+        }
+        connections.insert(id, (local.clone(), remote.clone()));
+    }
+    shared.counters.active.fetch_add(1, Ordering::Relaxed);
+    shared.event(ProxyEvent::Connected { id });
+
+    let upstream = {
+        let (local, remote, shared) = (local.clone(), remote.clone(), shared.clone());
+        let rng = shared.config.faults.rng(id, true);
+        thread::spawn(move || {
+            copy(
+                &local,
+                &remote,
+                rng,
+                &shared.counters.bytes_to_target,
+                &shared,
+            )
+        })
+    };
+    let rng = shared.config.faults.rng(id, false);
+    let from_target = copy(
+        &remote,
+        &local,
+        rng,
+        &shared.counters.bytes_from_target,
+        &shared,
+    );
+    let to_target = upstream.join().unwrap_or(0);
+
+    shared.connections.lock().unwrap().remove(&id);
+    shared.counters.active.fetch_sub(1, Ordering::Relaxed);
+    shared.event(ProxyEvent::Closed {
+        id,
+        to_target,
+        from_target,
+    });
+}
+
+/// Copy from `src` to `dst` until either fails, then close `dst`.  Returns bytes copied.
+fn copy(
+    src: &NngStream,
+    dst: &NngStream,
+    mut rng: StdRng,
+    counter: &AtomicU64,
+    shared: &Shared,
+) -> u64 {
+    let res = copy_until_error(src, dst, &mut rng, counter, shared);
+    debug!("Proxy copy finished: {:?}", res);
+    // Ends the other direction once it has forwarded what it received
+    dst.close();
+    res.unwrap_or_else(|(copied, _)| copied)
+}
+
+fn copy_until_error(
+    src: &NngStream,
+    dst: &NngStream,
+    rng: &mut StdRng,
+    counter: &AtomicU64,
+    shared: &Shared,
+) -> std::result::Result<u64, (u64, Error)> {
+    let buffer_size = shared.config.buffer_size;
+    let faults = &shared.config.faults;
+    let mut read_queue = SimpleAioWorkQueue::new().map_err(|err| (0, err))?;
+    let mut write_queue = SimpleAioWorkQueue::new().map_err(|err| (0, err))?;
+    let mut buffer = Window {
+        buffer: vec![0u8; buffer_size],
+        start: 0,
+        end: buffer_size,
+    };
+    let mut copied = 0;
+    loop {
+        let (mut filled, res) = block_on(src.recv_buffers(&mut read_queue, buffer))
+            .map_err(|err| (copied, Error::from(err)))?;
+        let received = res.map_err(|err| (copied, err))?;
+        filled.end = received;
+        faults
+            .before_forward(received, rng, &shared.closed)
+            .map_err(|err| (copied, err))?;
+        while filled.start < filled.end {
+            let (mut remaining, res) = block_on(dst.send_buffers(&mut write_queue, filled))
+                .map_err(|err| (copied, Error::from(err)))?;
+            let sent = res.map_err(|err| (copied, err))?;
+            remaining.start += sent;
+            copied += sent as u64;
+            counter.fetch_add(sent as u64, Ordering::Relaxed);
+            filled = remaining;
+        }
+        filled.start = 0;
+        filled.end = buffer_size;
+        buffer = filled;
+    }
+}
+
+/// Part of `buffer` still to be sent or received into, so partial sends don't move data.
+struct Window {
+    buffer: Vec<u8>,
+    start: usize,
+    end: usize,
+}
+
+unsafe impl IoBuffers for Window {
+    fn with_iov<R>(&mut self, f: impl FnOnce(&[nng_iov]) -> R) -> R {
+        let window = &mut self.buffer[self.start..self.end];
+        f(&[nng_iov {
+            iov_buf: window.as_mut_ptr() as *mut _,
+            iov_len: window.len(),
+        }])
+    }
+}
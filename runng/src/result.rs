@@ -2,8 +2,9 @@
 
 use core::convert::TryFrom;
 use futures::channel::oneshot;
+use log::debug;
 use runng_sys::*;
-use std::{error, fmt, result};
+use std::{error, fmt, io, result};
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -50,7 +51,7 @@ pub fn nng_int_to_result(value: i32) -> Result<()> {
 
 /// Error values returned by NNG functions.
 /// The special errno flags NNG_ESYSERR/NNG_ETRANERR are represented by Error::SysErr() and Error::TranErr()
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(i32)]
 pub enum NngErrno {
     EINTR = runng_sys::NNG_EINTR as i32,
@@ -145,6 +146,14 @@ pub enum Error {
     Unit,
     Canceled(oneshot::Canceled),
     TryFromError(i32),
+    /// Failed to serialize a message
+    Encode(String),
+    /// Failed to deserialize a message
+    Decode(String),
+    /// Remote procedure call failed
+    Rpc(crate::rpc::RpcError),
+    /// Operation (e.g. `dial tcp://127.0.0.1:5555`) failed with the boxed error
+    Context(String, Box<Error>),
 }
 
 impl Error {
@@ -152,6 +161,93 @@ impl Error {
     pub fn zero_map<T, F: FnOnce() -> T>(value: i32, result: F) -> Result<T> {
         nng_int_to_result(value).map(|_| result())
     }
+
+    /// Wrap error with `context` describing the operation that failed.
+    pub fn context<C: Into<String>>(self, context: C) -> Error {
+        Error::Context(context.into(), Box::new(self))
+    }
+
+    /// The error without any context.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context(_, err) => err.root(),
+            err => err,
+        }
+    }
+
+    /// NNG error number, if any.
+    pub fn errno(&self) -> Option<NngErrno> {
+        match self.root() {
+            Error::Errno(errno) => Some(*errno),
+            _ => None,
+        }
+    }
+
+    /// Operating system error of `SysErr`.
+    pub fn sys_error(&self) -> Option<io::Error> {
+        match self.root() {
+            Error::SysErr(code) => Some(io::Error::from_raw_os_error(*code)),
+            _ => None,
+        }
+    }
+
+    /// Closest `io::ErrorKind`.
+    pub fn kind(&self) -> io::ErrorKind {
+        use io::ErrorKind::*;
+        match self.root() {
+            Error::Errno(errno) => errno_kind(*errno),
+            Error::SysErr(code) => io::Error::from_raw_os_error(*code).kind(),
+            Error::NulError(_) | Error::Encode(_) => InvalidInput,
+            Error::TryFromError(_) | Error::Decode(_) => InvalidData,
+            _ => Other,
+        }
+    }
+
+    /// Whether the operation failed for a transient reason (like a timeout or refused/lost
+    /// connection) and retrying, or reconnecting, may succeed.
+    pub fn is_retryable(&self) -> bool {
+        use io::ErrorKind::*;
+        // `io::ErrorKind` only has unreachable kinds in newer Rust
+        self.errno() == Some(NngErrno::EUNREACHABLE)
+            || matches!(
+                self.kind(),
+                Interrupted
+                    | WouldBlock
+                    | TimedOut
+                    | ConnectionRefused
+                    | ConnectionReset
+                    | ConnectionAborted
+                    | BrokenPipe
+            )
+    }
+
+    /// Whether the socket, context, or other object used was closed.  Retrying won't succeed.
+    pub fn is_closed(&self) -> bool {
+        self.errno() == Some(NngErrno::ECLOSED)
+    }
+}
+
+// Only uses kinds available in the oldest supported Rust, the rest are `Other`
+fn errno_kind(errno: NngErrno) -> io::ErrorKind {
+    use io::ErrorKind::*;
+    use NngErrno::*;
+    match errno {
+        EINTR => Interrupted,
+        EINVAL | EADDRINVAL | ENOARG | EAMBIGUOUS | EBADTYPE => InvalidInput,
+        ETIMEDOUT => TimedOut,
+        ECONNREFUSED => ConnectionRefused,
+        ECLOSED => NotConnected,
+        EAGAIN => WouldBlock,
+        EADDRINUSE => AddrInUse,
+        ENOENT => NotFound,
+        EPROTO | EMSGSIZE | ECRYPTO => InvalidData,
+        EPERM | EPEERAUTH => PermissionDenied,
+        ECONNABORTED => ConnectionAborted,
+        ECONNRESET | ECONNSHUT => ConnectionReset,
+        EEXIST => AlreadyExists,
+        ENOMEM | EBUSY | ENOTSUP | EREADONLY | EWRITEONLY | EUNREACHABLE | ENOSPC | ESTATE
+        | ECANCELED | ENOFILES | EINTERNAL => Other,
+    }
 }
 
 impl TryFrom<i32> for Error {
@@ -174,7 +270,14 @@ impl TryFrom<i32> for Error {
     }
 }
 
-impl error::Error for Error {}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Context(_, err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -188,6 +291,10 @@ impl fmt::Display for Error {
             Unit => write!(f, "()"),
             Canceled(ref err) => err.fmt(f),
             TryFromError(value) => write!(f, "EnumFromIntError({})", value),
+            Encode(ref err) => write!(f, "Encode({})", err),
+            Decode(ref err) => write!(f, "Decode({})", err),
+            Rpc(ref err) => write!(f, "Rpc({})", err),
+            Context(ref context, ref err) => write!(f, "{} failed: {}", context, err),
         }
     }
 }
@@ -215,3 +322,57 @@ impl From<EnumFromIntError> for Error {
         Error::TryFromError(err.0)
     }
 }
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::SysErr(code) => io::Error::from_raw_os_error(code),
+            err => io::Error::new(err.kind(), err),
+        }
+    }
+}
+
+/// Adds context to the error of a `Result`.
+///
+/// # Examples
+/// ```
+/// use runng::*;
+///
+/// fn connect(url: &str) -> runng::Result<()> {
+///     Err(Error::Errno(NngErrno::ECONNREFUSED)).with_context(|| format!("dial {}", url))
+/// }
+///
+/// let err = connect("tcp://127.0.0.1:1").unwrap_err();
+/// assert_eq!(err.to_string(), "dial tcp://127.0.0.1:1 failed: ECONNREFUSED");
+/// assert!(err.is_retryable());
+/// ```
+pub trait ResultExt<T> {
+    /// Wrap error with `context` describing the operation that failed.
+    fn context<C: Into<String>>(self, context: C) -> Result<T>;
+    /// Wrap error with context returned by `context`, only called if there is an error.
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn context<C: Into<String>>(self, context: C) -> Result<T> {
+        self.map_err(|err| err.context(context))
+    }
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.map_err(|err| err.context(context()))
+    }
+}
+
+/// Logs context of failed operations that return the error unchanged, so callers can still match
+/// and compare it.
+pub(crate) trait LogContext<T> {
+    fn log_context<C: fmt::Display, F: FnOnce() -> C>(self, context: F) -> Result<T>;
+}
+
+impl<T> LogContext<T> for Result<T> {
+    fn log_context<C: fmt::Display, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        if let Err(ref err) = self {
+            debug!("{} failed: {}", context(), err);
+        }
+        self
+    }
+}
@@ -0,0 +1,511 @@
+//! Remote procedure calls using request/reply.
+//!
+//! Every request and reply starts with an envelope containing the method id and a status:
+//! ```text
+//! [method: u32][status: u32][payload]
+//! ```
+//! Method ids are the [FNV-1a](http://www.isthe.com/chongo/tech/comp/fnv/) hash of the method name.
+//! Payloads are encoded with [`NngEncode`](../msg/trait.NngEncode.html).  A reply with a status
+//! other than `Ok` contains an [`RpcError`](struct.RpcError.html) instead.
+//!
+//! The [`service`](attr.service.html) attribute generates a client and server from a trait.
+//! Its method ids are computed from `Trait.method` names.
+//!
+//! A request the server can't decode gets a `BadRequest` reply.  Its method id is zero if even
+//! that couldn't be read.
+//!
+//! # Examples
+//! ```
+//! use futures::{executor::block_on, future};
+//! use runng::{protocol::*, rpc::*, *};
+//!
+//! fn test() -> runng::Result<()> {
+//!     let url = "inproc://rpc";
+//!     let mut rep = Rep0::open()?;
+//!     rep.listen(url)?;
+//!     let mut req = Req0::open()?;
+//!     req.dial(url)?;
+//!
+//!     let router = Router::new().method("double", |value: u32| future::ok(value * 2));
+//!     let server = RpcServer::new(&rep, router);
+//!     std::thread::spawn(move || block_on(server.run(1).unwrap()));
+//!
+//!     let client = RpcClient::new(&req);
+//!     let value: u32 = block_on(client.call("double", &21u32))?;
+//!     assert_eq!(value, 42);
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Using a trait:
+//! ```
+//! use futures::{executor::block_on, future};
+//! use runng::{protocol::*, rpc::*, *};
+//!
+//! #[service]
+//! trait Calculator {
+//!     async fn add(&self, a: i32, b: i32) -> i32;
+//! }
+//!
+//! struct Local;
+//!
+//! impl Calculator for Local {
+//!     fn add(&self, a: i32, b: i32) -> RpcFuture<i32> {
+//!         Box::pin(future::ok(a + b))
+//!     }
+//! }
+//!
+//! fn test() -> runng::Result<()> {
+//!     let url = "inproc://calculator";
+//!     let mut rep = Rep0::open()?;
+//!     rep.listen(url)?;
+//!     let mut req = Req0::open()?;
+//!     req.dial(url)?;
+//!
+//!     let server = RpcServer::new(&rep, CalculatorServer::new(Local));
+//!     std::thread::spawn(move || block_on(server.run(1).unwrap()));
+//!
+//!     let client = CalculatorClient::new(&req);
+//!     assert_eq!(block_on(client.add(1, 2))?, 3);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::{
+    asyncio::*,
+    msg::{NngDecode, NngEncode, NngMsg},
+    *,
+};
+use futures::future::{self, BoxFuture, Future, FutureExt, TryFutureExt};
+use log::{debug, warn};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+pub use runng_derive::service;
+
+/// Identifies a method of a service.
+pub type MethodId = u32;
+
+/// Asynchronous result of a remote procedure call.
+pub type RpcFuture<T> = BoxFuture<'static, Result<T>>;
+
+/// Consecutive receive errors after which a server worker stops.
+const MAX_RECEIVE_ERRORS: usize = 16;
+
+/// Returns the `MethodId` of method `name`.
+pub fn method_id(name: &str) -> MethodId {
+    const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+    const FNV_PRIME: u32 = 0x0100_0193;
+    name.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Status of a remote procedure call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RpcStatus {
+    /// Success
+    Ok,
+    /// Server doesn't have a handler for the method
+    UnknownMethod,
+    /// Server failed to decode the request
+    BadRequest,
+    /// Server failed to handle the request
+    Internal,
+    /// Handler returned an application-specific error
+    Application,
+}
+
+impl RpcStatus {
+    pub fn code(self) -> u32 {
+        match self {
+            RpcStatus::Ok => 0,
+            RpcStatus::UnknownMethod => 1,
+            RpcStatus::BadRequest => 2,
+            RpcStatus::Internal => 3,
+            RpcStatus::Application => 4,
+        }
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(RpcStatus::Ok),
+            1 => Some(RpcStatus::UnknownMethod),
+            2 => Some(RpcStatus::BadRequest),
+            3 => Some(RpcStatus::Internal),
+            4 => Some(RpcStatus::Application),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned by a remote procedure call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcError {
+    pub status: RpcStatus,
+    /// Application-specific error code.  Zero unless `status` is `Application`.
+    pub code: u32,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(status: RpcStatus, message: &str) -> Self {
+        Self {
+            status,
+            code: 0,
+            message: message.to_owned(),
+        }
+    }
+
+    /// Create an application-specific error.
+    pub fn application(code: u32, message: &str) -> Self {
+        Self {
+            status: RpcStatus::Application,
+            code,
+            message: message.to_owned(),
+        }
+    }
+
+    fn from_error(err: Error) -> Self {
+        match err {
+            Error::Rpc(err) => err,
+            err => RpcError::new(RpcStatus::Internal, &err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}({}): {}", self.status, self.code, self.message)
+    }
+}
+
+impl From<RpcError> for Error {
+    fn from(err: RpcError) -> Self {
+        Error::Rpc(err)
+    }
+}
+
+/// Envelope at the front of every request and reply body.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Envelope {
+    pub method: MethodId,
+    pub status: RpcStatus,
+}
+
+impl Envelope {
+    /// Size of the envelope in bytes.
+    pub const SIZE: usize = 8;
+
+    /// Insert the envelope at the front of `msg` body.
+    pub fn insert(&self, msg: &mut NngMsg) -> Result<()> {
+        msg.insert_u32(self.status.code())?;
+        msg.insert_u32(self.method)
+    }
+
+    /// Method id at the front of `msg` body, if it's long enough.
+    fn peek_method(msg: &NngMsg) -> Option<MethodId> {
+        let id = msg.body().get(..4)?;
+        Some(u32::from_be_bytes([id[0], id[1], id[2], id[3]]))
+    }
+
+    /// Remove the envelope from the front of `msg` body.
+    pub fn trim(msg: &mut NngMsg) -> Result<Self> {
+        let method = u32::decode(msg)?;
+        let code = u32::decode(msg)?;
+        let status = RpcStatus::from_code(code)
+            .ok_or_else(|| Error::Decode(format!("Invalid RPC status {}", code)))?;
+        Ok(Self { method, status })
+    }
+}
+
+fn error_msg(method: MethodId, err: &RpcError) -> Result<NngMsg> {
+    let mut msg = NngMsg::new()?;
+    err.code.encode(&mut msg)?;
+    err.message.encode(&mut msg)?;
+    Envelope {
+        method,
+        status: err.status,
+    }
+    .insert(&mut msg)?;
+    Ok(msg)
+}
+
+/// Decode reply to `method` into its payload or the remote error.
+fn decode_reply(method: MethodId, mut msg: NngMsg) -> Result<NngMsg> {
+    let envelope = Envelope::trim(&mut msg)?;
+    // Method id is zero if the server couldn't read it from the request
+    let is_unread = envelope.method == 0 && envelope.status != RpcStatus::Ok;
+    if envelope.method != method && !is_unread {
+        return Err(Error::Decode(format!(
+            "Reply for method {:x} to request {:x}",
+            envelope.method, method
+        )));
+    }
+    if envelope.status == RpcStatus::Ok {
+        Ok(msg)
+    } else {
+        let code = u32::decode(&mut msg)?;
+        let message = String::decode(&mut msg)?;
+        Err(Error::Rpc(RpcError {
+            status: envelope.status,
+            code,
+            message,
+        }))
+    }
+}
+
+/// Service whose methods can be called remotely.
+pub trait Service: Send + Sync + 'static {
+    /// Names of the methods handled by this service.
+    fn methods(&self) -> Vec<&str>;
+    /// Handle `request` payload for `method` and return the reply payload.
+    fn call(&self, method: MethodId, request: NngMsg) -> RpcFuture<NngMsg>;
+}
+
+/// Encode the result of a handler as a reply payload.
+#[doc(hidden)]
+pub fn encode_reply<Rep, Fut>(reply: Fut) -> RpcFuture<NngMsg>
+where
+    Rep: NngEncode,
+    Fut: Future<Output = Result<Rep>> + Send + 'static,
+{
+    let reply = reply.and_then(|reply| {
+        let res = NngMsg::new().and_then(|mut msg| reply.encode(&mut msg).map(|_| msg));
+        future::ready(res)
+    });
+    Box::pin(reply)
+}
+
+/// Reply for a request that failed to decode.
+#[doc(hidden)]
+pub fn bad_request(err: Error) -> RpcFuture<NngMsg> {
+    let err = RpcError::new(RpcStatus::BadRequest, &err.to_string());
+    Box::pin(future::err(Error::Rpc(err)))
+}
+
+/// Reply for a request to a method the service doesn't have.
+#[doc(hidden)]
+pub fn unknown_method(method: MethodId) -> RpcFuture<NngMsg> {
+    let message = format!("Unknown method {:x}", method);
+    let err = RpcError::new(RpcStatus::UnknownMethod, &message);
+    Box::pin(future::err(Error::Rpc(err)))
+}
+
+type Handler = Box<dyn Fn(NngMsg) -> RpcFuture<NngMsg> + Send + Sync>;
+
+/// `Service` that dispatches requests to handlers registered by name.
+#[derive(Default)]
+pub struct Router {
+    handlers: HashMap<MethodId, (String, Handler)>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `handler` for method `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` collides with a method already registered.
+    pub fn method<Req, Rep, F, Fut>(mut self, name: &str, handler: F) -> Self
+    where
+        Req: NngDecode,
+        Rep: NngEncode,
+        F: Fn(Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Rep>> + Send + 'static,
+    {
+        let handler = move |mut msg: NngMsg| -> RpcFuture<NngMsg> {
+            match Req::decode(&mut msg) {
+                Ok(request) => encode_reply(handler(request)),
+                Err(err) => bad_request(err),
+            }
+        };
+        let id = method_id(name);
+        if let Some((existing, _)) = self.handlers.get(&id) {
+            panic!("Method {} collides with {}", name, existing);
+        }
+        self.handlers
+            .insert(id, (name.to_owned(), Box::new(handler)));
+        self
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("methods", &self.methods())
+            .finish()
+    }
+}
+
+impl Service for Router {
+    fn methods(&self) -> Vec<&str> {
+        self.handlers
+            .values()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    fn call(&self, method: MethodId, request: NngMsg) -> RpcFuture<NngMsg> {
+        match self.handlers.get(&method) {
+            Some((_, handler)) => handler(request),
+            None => unknown_method(method),
+        }
+    }
+}
+
+/// Client that calls methods of a remote `Service` using a request socket.
+#[derive(Clone, Debug)]
+pub struct RpcClient {
+    socket: NngSocket,
+}
+
+impl RpcClient {
+    pub fn new<S: GetSocket>(socket: &S) -> Self {
+        Self {
+            socket: socket.socket().clone(),
+        }
+    }
+
+    /// Call method `name` with `request`.
+    pub fn call<Req, Rep>(&self, name: &str, request: &Req) -> RpcFuture<Rep>
+    where
+        Req: NngEncode + ?Sized,
+        Rep: NngDecode + Send + 'static,
+    {
+        self.call_id(method_id(name), request)
+    }
+
+    /// Call method with id `method` with `request`.
+    pub fn call_id<Req, Rep>(&self, method: MethodId, request: &Req) -> RpcFuture<Rep>
+    where
+        Req: NngEncode + ?Sized,
+        Rep: NngDecode + Send + 'static,
+    {
+        let payload = NngMsg::new().and_then(|mut msg| request.encode(&mut msg).map(|_| msg));
+        let reply = match payload {
+            Ok(payload) => self.call_raw(method, payload),
+            Err(err) => return Box::pin(future::err(err)),
+        };
+        Box::pin(reply.and_then(|mut msg| future::ready(Rep::decode(&mut msg))))
+    }
+
+    /// Call method with id `method` with an encoded `payload` and return the reply payload.
+    pub fn call_raw(&self, method: MethodId, mut payload: NngMsg) -> RpcFuture<NngMsg> {
+        let envelope = Envelope {
+            method,
+            status: RpcStatus::Ok,
+        };
+        // Each call uses its own context so calls can be concurrent
+        let res = envelope
+            .insert(&mut payload)
+            .and_then(|_| RequestAsyncHandle::new(self.socket.clone()));
+        let mut ctx = match res {
+            Ok(ctx) => ctx,
+            Err(err) => return Box::pin(future::err(err)),
+        };
+        let reply = ctx.send(payload);
+        Box::pin(reply.map(move |res| {
+            // Context must outlive the request
+            drop(ctx);
+            result::flatten_result(res).and_then(|msg| decode_reply(method, msg))
+        }))
+    }
+}
+
+/// Server that routes requests from a reply socket to a `Service`.
+#[derive(Debug)]
+pub struct RpcServer<S> {
+    socket: NngSocket,
+    service: Arc<S>,
+}
+
+impl<S: Service> RpcServer<S> {
+    /// # Panics
+    ///
+    /// Panics if the ids of two methods of `service` collide.
+    pub fn new<T: GetSocket>(socket: &T, service: S) -> Self {
+        let mut ids: HashMap<MethodId, &str> = HashMap::new();
+        for name in service.methods() {
+            if let Some(existing) = ids.insert(method_id(name), name) {
+                panic!("Method {} collides with {}", name, existing);
+            }
+        }
+        Self {
+            socket: socket.socket().clone(),
+            service: Arc::new(service),
+        }
+    }
+
+    /// Returns a future that handles requests with `workers` concurrent contexts.
+    /// Completes once the socket is closed, or workers stop after repeated receive errors.
+    pub fn run(&self, workers: usize) -> Result<BoxFuture<'static, ()>> {
+        let mut futures = Vec::with_capacity(workers);
+        for _ in 0..workers {
+            let ctx = ReplyAsyncHandle::new(self.socket.clone())?;
+            futures.push(Self::worker(ctx, self.service.clone()));
+        }
+        Ok(Box::pin(future::join_all(futures).map(|_| ())))
+    }
+
+    async fn worker(mut ctx: ReplyAsyncHandle, service: Arc<S>) {
+        let mut errors = 0;
+        loop {
+            let request = match ctx.receive().await {
+                Ok(request) => request,
+                // Context won't receive again
+                Err(err) if err.is_closed() || err.errno() == Some(NngErrno::ECANCELED) => break,
+                Err(err) => {
+                    errors += 1;
+                    if errors >= MAX_RECEIVE_ERRORS {
+                        warn!(
+                            "RPC worker stopped after {} receive errors: {}",
+                            errors, err
+                        );
+                        break;
+                    }
+                    debug!("RPC receive failed: {:?}", err);
+                    continue;
+                }
+            };
+            errors = 0;
+            // Must always reply before the context can receive again
+            let reply = match Self::handle(&*service, request).await {
+                Ok(reply) => reply,
+                Err(err) => {
+                    // Not even an error reply could be created
+                    warn!("RPC reply failed: {:?}", err);
+                    break;
+                }
+            };
+            let res = ctx.reply(reply).await;
+            if let Ok(Err(err)) = res {
+                debug!("RPC reply failed: {:?}", err);
+            }
+        }
+    }
+
+    /// Reply to `request`, which is an `RpcError` if anything fails.
+    async fn handle(service: &S, mut request: NngMsg) -> Result<NngMsg> {
+        // Reply to the method even if the rest of the envelope is invalid
+        let peeked = Envelope::peek_method(&request).unwrap_or(0);
+        let method = match Envelope::trim(&mut request) {
+            Ok(envelope) => envelope.method,
+            Err(err) => {
+                let err = RpcError::new(RpcStatus::BadRequest, &err.to_string());
+                return error_msg(peeked, &err);
+            }
+        };
+        let reply = service.call(method, request).await.and_then(|mut reply| {
+            Envelope {
+                method,
+                status: RpcStatus::Ok,
+            }
+            .insert(&mut reply)?;
+            Ok(reply)
+        });
+        reply.or_else(|err| error_msg(method, &RpcError::from_error(err)))
+    }
+}
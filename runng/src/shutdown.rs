@@ -0,0 +1,185 @@
+//! Coordinated shutdown of sockets and their asynchronous handles.
+//!
+//! Shutting down happens in three steps:
+//! 1. Sockets stop accepting work.  New sends and requests fail with `ECLOSED` (see
+//!    `Error::is_closed()`), and reply handles stop receiving requests once their current reply
+//!    is sent.
+//! 2. Wait until asynchronous sends in progress complete and send buffers drain, or a deadline
+//!    passes.
+//! 3. Close the contexts of each socket and then the socket, in the order sockets were added.
+//!
+//! Send buffers (`NngOption::SENDBUF`) aren't visible through nng, so messages still buffered are
+//! estimated from the `tx_msgs` statistic of push and pair sockets, which send each message to
+//! exactly one peer.  The estimate is zero for other protocols, which may drop messages or send
+//! them to several peers (like pub and bus), and if statistics aren't available.
+
+use crate::*;
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+/// How often to check whether sockets have drained.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Shuts down several sockets together.
+///
+/// # Examples
+/// ```
+/// use runng::{asyncio::*, factory::latest::ProtocolFactory, shutdown::*, *};
+/// use std::time::Duration;
+///
+/// fn test() -> runng::Result<()> {
+///     let factory = ProtocolFactory::default();
+///     let mut rep = factory.replier_open()?;
+///     rep.listen("tcp://127.0.0.1:5555")?;
+///     let mut push = factory.pusher_open()?;
+///     push.dial("tcp://127.0.0.1:5556")?;
+///     let _rep_ctx = rep.create_async()?;
+///     let _push_ctx = push.create_async()?;
+///
+///     let report = ShutdownCoordinator::new()
+///         .socket(&rep)
+///         .socket(&push)
+///         .shutdown(Duration::from_secs(5));
+///     if !report.is_clean() {
+///         println!("{}", report);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct ShutdownCoordinator {
+    sockets: Vec<NngSocket>,
+}
+
+impl ShutdownCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a socket.  Sockets are closed in the order they're added.
+    pub fn socket<S: GetSocket>(&mut self, socket: &S) -> &mut Self {
+        self.sockets.push(socket.socket().clone());
+        self
+    }
+
+    /// Stop accepting work, wait up to `timeout` for sockets to drain, then close them.
+    pub fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let start = Instant::now();
+        for socket in self.sockets.iter() {
+            socket.begin_drain();
+        }
+
+        let deadline = start + timeout;
+        let mut drained = self.drained();
+        while !drained && Instant::now() < deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            thread::sleep(std::cmp::min(POLL_INTERVAL, remaining));
+            drained = self.drained();
+        }
+
+        let sockets = self
+            .sockets
+            .iter()
+            .map(|socket| {
+                let id = unsafe { nng_socket_id(socket.nng_socket()) };
+                let pending_sends = socket.pending_sends();
+                let buffered_sends = socket.buffered_sends();
+                let contexts = socket.close_contexts();
+                let close = socket.close();
+                SocketReport {
+                    id,
+                    pending_sends,
+                    buffered_sends,
+                    contexts,
+                    close,
+                }
+            })
+            .collect();
+        ShutdownReport {
+            sockets,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    fn drained(&self) -> bool {
+        self.sockets
+            .iter()
+            .all(|socket| socket.pending_sends() == 0 && socket.buffered_sends() == 0)
+    }
+}
+
+/// Outcome of `ShutdownCoordinator::shutdown()`.
+#[derive(Debug)]
+pub struct ShutdownReport {
+    /// Sockets in the order they were closed
+    pub sockets: Vec<SocketReport>,
+    /// Time taken to drain and close
+    pub elapsed: Duration,
+}
+
+impl ShutdownReport {
+    /// Whether all sockets drained and closed without error.
+    pub fn is_clean(&self) -> bool {
+        self.sockets.iter().all(SocketReport::is_clean)
+    }
+
+    /// Asynchronous sends that hadn't completed by the deadline.
+    pub fn abandoned_sends(&self) -> usize {
+        self.sockets.iter().map(|socket| socket.pending_sends).sum()
+    }
+
+    /// Estimate of messages left in send buffers.
+    pub fn abandoned_buffered(&self) -> u64 {
+        self.sockets
+            .iter()
+            .map(|socket| socket.buffered_sends)
+            .sum()
+    }
+}
+
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Shutdown in {:?}", self.elapsed)?;
+        for socket in self.sockets.iter() {
+            write!(f, "\n  {}", socket)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of shutting down a single socket.
+#[derive(Debug)]
+pub struct SocketReport {
+    /// Socket id (see `GetSocket::stats()`)
+    pub id: i32,
+    /// Asynchronous sends that hadn't completed by the deadline, and failed with `ECLOSED`
+    pub pending_sends: usize,
+    /// Estimate of messages left in the send buffer and discarded
+    pub buffered_sends: u64,
+    /// Contexts closed before the socket
+    pub contexts: usize,
+    /// Result of closing the socket
+    pub close: Result<()>,
+}
+
+impl SocketReport {
+    pub fn is_clean(&self) -> bool {
+        self.pending_sends == 0 && self.buffered_sends == 0 && self.close.is_ok()
+    }
+}
+
+impl fmt::Display for SocketReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "socket {}: {} sends pending, ~{} buffered, {} contexts closed",
+            self.id, self.pending_sends, self.buffered_sends, self.contexts
+        )?;
+        if let Err(err) = &self.close {
+            write!(f, ", {}", err)?;
+        }
+        Ok(())
+    }
+}
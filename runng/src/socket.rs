@@ -4,12 +4,25 @@
 //! A socket may be cloned and it will increase the reference count of the underlying `nng_socket`.
 //! Depending on the gurantees of the originating protocol, simultaneous use of the socket __may not be safe__.
 //! When the last reference to the socket is dropped, `nng_close()` will be called.
+//! Use `NngSocket::close()` or `NngSocket::shutdown()` to close it sooner and see errors.
 
-use crate::{dialer::NngDialer, listener::NngListener, *};
+use crate::{
+    dialer::{DialerBuilder, UnsafeDialer},
+    listener::{ListenerBuilder, UnsafeListener},
+    *,
+};
 use bitflags::bitflags;
 use core::convert::TryFrom;
+use log::{debug, warn};
 use runng_sys::*;
-use std::{fmt, result, sync::Arc};
+use std::{
+    fmt, result,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
 
 bitflags! {
     /// Flags used with [`SendSocket`](trait.SendSocket.html) and [`RecvSocket`](trait.RecvSocket.html).
@@ -72,10 +85,192 @@ pub struct NngSocket {
 impl NngSocket {
     /// Create a new `NngSocket`.
     pub fn new(socket: nng_socket) -> Self {
-        let socket = Arc::new(InnerSocket { socket });
+        let socket = Arc::new(InnerSocket {
+            socket,
+            closed: AtomicBool::new(false),
+            draining: AtomicBool::new(false),
+            pending_sends: Mutex::new(0),
+            sends_done: Condvar::new(),
+            accepted_sends: AtomicU64::new(0),
+            contexts: Mutex::new(Vec::new()),
+            dialers: Mutex::new(Vec::new()),
+            listeners: Mutex::new(Vec::new()),
+        });
         NngSocket { socket }
     }
 
+    /// Close the socket.  See [nng_close](https://nng.nanomsg.org/man/v1.2.2/nng_close.3).
+    ///
+    /// Operations in progress on the socket and its clones fail with `ECLOSED`.  Closing again,
+    /// through this or any clone, does nothing and returns `Ok`.
+    pub fn close(&self) -> Result<()> {
+        self.socket.close()
+    }
+
+    /// Stop accepting sends, wait up to `linger` for asynchronous sends in progress to complete,
+    /// then close the socket.  See `shutdown::ShutdownCoordinator` to also wait for send buffers
+    /// and shut down several sockets.
+    ///
+    /// A send completes once nng has accepted the message, so messages still buffered by the
+    /// transport may be discarded.  The socket is closed even if sends remain after `linger`, in
+    /// which case they fail and `ETIMEDOUT` is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// use runng::{asyncio::*, factory::latest::ProtocolFactory, *};
+    /// use std::time::Duration;
+    ///
+    /// fn test() -> runng::Result<()> {
+    ///     let factory = ProtocolFactory::default();
+    ///     let mut push = factory.pusher_open()?;
+    ///     push.dial("tcp://127.0.0.1:5555")?;
+    ///     let mut push_ctx = push.create_async()?;
+    ///     let _sent = push_ctx.send(msg::NngMsg::new()?);
+    ///     push.socket().shutdown(Duration::from_secs(1))
+    /// }
+    /// ```
+    pub fn shutdown(&self, linger: Duration) -> Result<()> {
+        self.begin_drain();
+        let pending = self.socket.wait_for_sends(linger);
+        self.close()?;
+        if pending > 0 {
+            let context = format!("shutdown with {} sends pending", pending);
+            return Err(Error::Errno(NngErrno::ETIMEDOUT).context(context));
+        }
+        Ok(())
+    }
+
+    /// Whether `close()` or `shutdown()` was called on the socket or a clone.
+    pub fn is_closed(&self) -> bool {
+        self.socket.closed.load(Ordering::SeqCst)
+    }
+
+    /// Whether the socket is shutting down and no longer accepts sends.
+    pub fn is_draining(&self) -> bool {
+        self.socket.draining.load(Ordering::SeqCst)
+    }
+
+    /// Reject new sends (they fail with `ECLOSED`), but let those in progress complete.
+    pub(crate) fn begin_drain(&self) {
+        self.socket.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Error if new sends aren't accepted.
+    pub(crate) fn check_accepting(&self) -> Result<()> {
+        if self.is_draining() || self.is_closed() {
+            debug!("Send while shutting down");
+            Err(Error::Errno(NngErrno::ECLOSED))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Asynchronous send started.  Must be followed by `send_finished()`.
+    pub(crate) fn send_started(&self) {
+        *self.socket.pending_sends.lock().unwrap() += 1;
+    }
+
+    /// Asynchronous send completed, failed, or was canceled.
+    pub(crate) fn send_finished<T>(&self, res: &Result<T>) {
+        if res.is_ok() {
+            self.send_accepted();
+        }
+        let mut pending = self.socket.pending_sends.lock().unwrap();
+        *pending = pending.saturating_sub(1);
+        if *pending == 0 {
+            self.socket.sends_done.notify_all();
+        }
+    }
+
+    /// Message accepted by nng for sending.
+    pub(crate) fn send_accepted(&self) {
+        self.socket.accepted_sends.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Asynchronous sends in progress.
+    pub(crate) fn pending_sends(&self) -> usize {
+        *self.socket.pending_sends.lock().unwrap()
+    }
+
+    /// Estimate of messages accepted but not yet sent to a peer, based on the socket's `tx_msgs`
+    /// statistic.  Zero if statistics aren't available or the protocol isn't push or pair.
+    pub(crate) fn buffered_sends(&self) -> u64 {
+        let accepted = self.socket.accepted_sends.load(Ordering::Relaxed);
+        if accepted == 0 {
+            return 0;
+        }
+        let stats = match self.stats() {
+            Ok(stats) => stats,
+            Err(_) => return 0,
+        };
+        // Other protocols drop messages (e.g. pub without subscribers, req that was canceled) or
+        // send them to several peers, so `tx_msgs` never catches up with accepted sends
+        match stats.protocol() {
+            Some("push") | Some("pair") | Some("pair1") => {}
+            _ => return 0,
+        }
+        stats
+            .tx_msgs()
+            .map_or(0, |tx_msgs| accepted.saturating_sub(tx_msgs))
+    }
+
+    /// Track context `ctx` opened on the socket, so it can be closed before the socket.
+    pub(crate) fn register_ctx(&self, ctx: nng_ctx) {
+        self.socket.contexts.lock().unwrap().push(ctx);
+    }
+
+    pub(crate) fn unregister_ctx(&self, id: i32) {
+        let mut contexts = self.socket.contexts.lock().unwrap();
+        contexts.retain(|ctx| unsafe { nng_ctx_id(*ctx) } != id);
+    }
+
+    /// Close contexts opened on the socket, returning how many.
+    pub(crate) fn close_contexts(&self) -> usize {
+        let contexts: Vec<_> = self.socket.contexts.lock().unwrap().drain(..).collect();
+        for ctx in contexts.iter() {
+            unsafe {
+                nng_ctx_close(*ctx);
+            }
+        }
+        contexts.len()
+    }
+
+    /// Open dialers of the socket, including those of `Dial::dial()`.
+    pub fn dialers(&self) -> Vec<UnsafeDialer> {
+        let dialers = self.socket.dialers.lock().unwrap();
+        dialers
+            .iter()
+            .map(|dialer| UnsafeDialer::new(*dialer))
+            .collect()
+    }
+
+    /// Open listeners of the socket, including those of `Listen::listen()`.
+    pub fn listeners(&self) -> Vec<UnsafeListener> {
+        let listeners = self.socket.listeners.lock().unwrap();
+        listeners
+            .iter()
+            .map(|listener| UnsafeListener::new(*listener))
+            .collect()
+    }
+
+    pub(crate) fn register_dialer(&self, dialer: nng_dialer) {
+        self.socket.dialers.lock().unwrap().push(dialer);
+    }
+
+    pub(crate) fn unregister_dialer(&self, id: i32) {
+        let mut dialers = self.socket.dialers.lock().unwrap();
+        dialers.retain(|dialer| unsafe { nng_dialer_id(*dialer) } != id);
+    }
+
+    pub(crate) fn register_listener(&self, listener: nng_listener) {
+        self.socket.listeners.lock().unwrap().push(listener);
+    }
+
+    pub(crate) fn unregister_listener(&self, id: i32) {
+        let mut listeners = self.socket.listeners.lock().unwrap();
+        listeners.retain(|listener| unsafe { nng_listener_id(*listener) } != id);
+    }
+
     /// Obtain underlying `nng_socket`
     pub unsafe fn nng_socket(&self) -> nng_socket {
         self.socket.socket
@@ -140,6 +335,12 @@ pub trait GetSocket {
     unsafe fn nng_socket(&self) -> nng_socket {
         self.socket().nng_socket()
     }
+
+    /// Snapshot of statistics of the socket, its dialers, and its listeners.
+    fn stats(&self) -> Result<stats::SocketStats> {
+        let id = unsafe { nng_socket_id(self.nng_socket()) };
+        stats::SocketStats::for_id(id)
+    }
 }
 
 /// Type which __is__ an [`NngSocket`](struct.NngSocket.html).
@@ -177,13 +378,20 @@ pub trait Listen: Socket {
     fn listen_flags(&mut self, url: &str, flags: SocketFlags) -> Result<&mut Self> {
         unsafe {
             let (_cstring, ptr) = to_cstr(url)?;
-            let res = nng_listen(self.nng_socket(), ptr, std::ptr::null_mut(), flags.bits());
-            Error::zero_map(res, || self)
+            let mut listener = nng_listener::default();
+            let res = nng_listen(self.nng_socket(), ptr, &mut listener, flags.bits());
+            Error::zero_map(res, || {
+                self.socket().register_listener(listener);
+                self
+            })
+            .log_context(|| format!("listen {}", url))
         }
     }
 
-    fn listener_create(&self, url: &str) -> Result<NngListener> {
-        NngListener::new(self.socket().clone(), url)
+    /// Create a listener whose options can be set before starting it.  See the `listener` module.
+    fn listener_create(&self, url: &str) -> Result<ListenerBuilder> {
+        ListenerBuilder::new(self.socket().clone(), url)
+            .log_context(|| format!("create listener {}", url))
     }
 }
 
@@ -198,13 +406,20 @@ pub trait Dial: Socket {
     fn dial_flags(&mut self, url: &str, flags: SocketFlags) -> Result<&mut Self> {
         unsafe {
             let (_cstring, ptr) = to_cstr(url)?;
-            let res = nng_dial(self.nng_socket(), ptr, std::ptr::null_mut(), flags.bits());
-            Error::zero_map(res, || self)
+            let mut dialer = nng_dialer::default();
+            let res = nng_dial(self.nng_socket(), ptr, &mut dialer, flags.bits());
+            Error::zero_map(res, || {
+                self.socket().register_dialer(dialer);
+                self
+            })
+            .log_context(|| format!("dial {}", url))
         }
     }
 
-    fn dialer_create(&self, url: &str) -> Result<NngDialer> {
-        NngDialer::new(self.socket().clone(), url)
+    /// Create a dialer whose options can be set before starting it.  See the `dialer` module.
+    fn dialer_create(&self, url: &str) -> Result<DialerBuilder> {
+        DialerBuilder::new(self.socket().clone(), url)
+            .log_context(|| format!("create dialer {}", url))
     }
 }
 
@@ -237,11 +452,14 @@ pub trait SendSocket: Socket {
 
     /// Send data with [`Flags`](struct.Flags.html).  See [nng_send](https://nng.nanomsg.org/man/v1.2.2/nng_send.3).
     fn send_flags(&self, data: &[u8], flags: Flags) -> Result<()> {
+        self.socket().check_accepting()?;
         unsafe {
             let ptr = data.as_ptr() as *mut std::os::raw::c_void;
             let res = nng_send(self.nng_socket(), ptr, data.len(), flags.bits());
-            nng_int_to_result(res)
+            nng_int_to_result(res)?;
         }
+        self.socket().send_accepted();
+        Ok(())
     }
 
     /// Sends data in "zero-copy" mode.  See `NNG_FLAG_ALLOC`.
@@ -255,15 +473,23 @@ pub trait SendSocket: Socket {
         data: mem::Alloc,
         flags: Flags,
     ) -> result::Result<(), SendError<mem::Alloc>> {
+        if let Err(error) = self.socket().check_accepting() {
+            return Err(SendError {
+                error,
+                message: data,
+            });
+        }
         let flags = (flags | Flags::ALLOC).bits();
         unsafe {
             let (ptr, size) = data.take();
             let res = nng_send(self.nng_socket(), ptr, size, flags);
             let error = nng_int_to_result(res);
-            error.map_err(|error| {
-                let message = mem::Alloc::from_raw_parts(ptr, size);
-                SendError { error, message }
-            })
+            error
+                .map(|()| self.socket().send_accepted())
+                .map_err(|error| {
+                    let message = mem::Alloc::from_raw_parts(ptr, size);
+                    SendError { error, message }
+                })
         }
     }
 
@@ -279,15 +505,23 @@ pub trait SendSocket: Socket {
         msg: msg::NngMsg,
         flags: Flags,
     ) -> result::Result<(), SendError<msg::NngMsg>> {
+        if let Err(error) = self.socket().check_accepting() {
+            return Err(SendError {
+                error,
+                message: msg,
+            });
+        }
         unsafe {
             let ptr = msg.take();
             assert!(!ptr.is_null());
             let res = nng_sendmsg(self.nng_socket(), ptr, flags.bits());
             let error = nng_int_to_result(res);
-            error.map_err(|error| {
-                let message = msg::NngMsg::from_raw(ptr);
-                SendError { error, message }
-            })
+            error
+                .map(|()| self.socket().send_accepted())
+                .map_err(|error| {
+                    let message = msg::NngMsg::from_raw(ptr);
+                    SendError { error, message }
+                })
         }
     }
 }
@@ -366,22 +600,57 @@ impl UnsafeSocket {
 #[derive(Debug)]
 struct InnerSocket {
     socket: nng_socket,
+    closed: AtomicBool,
+    /// No longer accepting sends
+    draining: AtomicBool,
+    /// Asynchronous sends in progress
+    pending_sends: Mutex<usize>,
+    sends_done: Condvar,
+    /// Messages nng accepted for sending
+    accepted_sends: AtomicU64,
+    /// Open contexts
+    contexts: Mutex<Vec<nng_ctx>>,
+    /// Open dialers and listeners
+    dialers: Mutex<Vec<nng_dialer>>,
+    listeners: Mutex<Vec<nng_listener>>,
+}
+
+impl InnerSocket {
+    fn close(&self) -> Result<()> {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        trace!("Socket close: {:?}", self.socket);
+        // Closing the socket closes its dialers and listeners
+        self.dialers.lock().unwrap().clear();
+        self.listeners.lock().unwrap().clear();
+        let res = unsafe { nng_int_to_result(nng_close(self.socket)) };
+        match res {
+            // Thrift's TIoChannel::split() clones the socket handle so it may already be closed
+            Err(ref err) if err.is_closed() => Ok(()),
+            res => res.log_context(|| {
+                let id = unsafe { nng_socket_id(self.socket) };
+                format!("close socket {}", id)
+            }),
+        }
+    }
+
+    /// Wait up to `timeout` for pending sends to finish, returning the number left.
+    fn wait_for_sends(&self, timeout: Duration) -> usize {
+        let pending = self.pending_sends.lock().unwrap();
+        let (pending, _) = self
+            .sends_done
+            .wait_timeout_while(pending, timeout, |pending| *pending > 0)
+            .unwrap();
+        *pending
+    }
 }
 
 impl Drop for InnerSocket {
     fn drop(&mut self) {
-        unsafe {
-            trace!("Socket close: {:?}", self.socket);
-            let res = nng_int_to_result(nng_close(self.socket));
-            match res {
-                Ok(()) => {}
-                // Can't panic here.  Thrift's TIoChannel::split() clones the socket handle so we may get ECLOSED
-                Err(Error::Errno(NngErrno::ECLOSED)) => {}
-                Err(res) => {
-                    debug!("nng_close {:?}", res);
-                    panic!("nng_close {:?}", res);
-                }
-            }
+        // Never panic in drop, the error has nowhere to go
+        if let Err(err) = self.close() {
+            warn!("{}", err);
         }
     }
 }
@@ -0,0 +1,485 @@
+//! Export statistics in the [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/).
+//!
+//! Counters become Prometheus counters (with a `_total` suffix), levels and booleans become gauges.
+//! Scopes like `socket3` or `dialer5` become part of the metric name, and their ids and strings
+//! (e.g. socket `name` and `protocol`, dialer `url`) become labels.  A scope's `id` is labeled
+//! with the scope, so nested scopes keep the ids of their parents:
+//! ```text
+//! # HELP nng_socket_tx_msgs_total messages sent
+//! # TYPE nng_socket_tx_msgs_total counter
+//! nng_socket_tx_msgs_total{name="3",protocol="pair",socket_id="3"} 12
+//! ```
+//!
+//! Metrics of asynchronous handles (see `asyncio::AsyncMetrics`) are rendered by `render_metrics()`.
+//!
+//! # Examples
+//! ```
+//! use runng::stats::export::*;
+//!
+//! fn test() -> runng::Result<()> {
+//!     // Render once
+//!     let text = render_current()?;
+//!     // Or serve at http://127.0.0.1:9100/metrics
+//!     let server = MetricsServer::start("tcp://127.0.0.1:9100")?;
+//!     server.close();
+//!     Ok(())
+//! }
+//! ```
+
+use super::*;
+use crate::asyncio::*;
+use futures::{executor::block_on, stream::StreamExt};
+use runng_sys::nng_duration;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::{mpsc as std_mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+/// Render `tree` (e.g. from `NngStatRoot::to_tree()`) in Prometheus text format.
+pub fn render(tree: &StatNode) -> String {
+    let mut families = BTreeMap::new();
+    collect(tree, "nng", &[], &mut families);
+    write_families(&families)
+}
+
+/// Render metrics of asynchronous handles in Prometheus text format, each labeled with `handle="<name>"`.
+///
+/// Append to the output of `render()` to serve both together.
+pub fn render_metrics(metrics: &[(&str, MetricsSnapshot)]) -> String {
+    let mut families = BTreeMap::new();
+    for (handle, snapshot) in metrics.iter() {
+        collect_metrics(handle, snapshot, &mut families);
+    }
+    write_families(&families)
+}
+
+fn write_families(families: &BTreeMap<String, Family>) -> String {
+    let mut text = String::new();
+    for (name, family) in families.iter() {
+        // Writing to `String` can't fail
+        let _ = writeln!(text, "# HELP {} {}", name, escape_help(&family.help));
+        let _ = writeln!(text, "# TYPE {} {}", name, family.kind);
+        for sample in family.samples.iter() {
+            let _ = writeln!(text, "{}{}", name, sample);
+        }
+    }
+    text
+}
+
+/// Take a statistics snapshot and render it in Prometheus text format.
+pub fn render_current() -> Result<String> {
+    let tree = NngStatRoot::new()?.to_tree()?;
+    Ok(render(&tree))
+}
+
+/// Metric samples with the same name
+struct Family {
+    help: String,
+    kind: &'static str,
+    /// Name suffix (for histograms), labels and value
+    samples: Vec<String>,
+}
+
+impl Family {
+    fn new(help: &str, kind: &'static str) -> Self {
+        Self {
+            help: help.to_owned(),
+            kind,
+            samples: Vec::new(),
+        }
+    }
+}
+
+type Labels = Vec<(String, String)>;
+
+fn collect(
+    node: &StatNode,
+    prefix: &str,
+    labels: &[(String, String)],
+    families: &mut BTreeMap<String, Family>,
+) {
+    for child in node.children.iter() {
+        match child.kind {
+            StatKind::Scope => {
+                // "socket3" -> "socket"
+                let scope = child.name.trim_end_matches(|c: char| c.is_ascii_digit());
+                let scope = if scope.is_empty() { &child.name } else { scope };
+                let scope = sanitize(scope);
+                let prefix = format!("{}_{}", prefix, scope);
+                let labels = scope_labels(child, &scope, labels);
+                collect(child, &prefix, &labels, families);
+            }
+            StatKind::Counter | StatKind::Level | StatKind::Boolean => {
+                let (name, kind) = match child.kind {
+                    StatKind::Counter => (
+                        format!("{}_{}_total", prefix, sanitize(&child.name)),
+                        "counter",
+                    ),
+                    _ => (format!("{}_{}", prefix, sanitize(&child.name)), "gauge"),
+                };
+                let value = match child.value {
+                    StatValue::Number(value) => value,
+                    StatValue::Bool(value) => value as u64,
+                    _ => continue,
+                };
+                let family = families
+                    .entry(name)
+                    .or_insert_with(|| Family::new(&child.desc, kind));
+                family
+                    .samples
+                    .push(format!("{} {}", format_labels(labels), value));
+            }
+            // Become labels of their scope
+            StatKind::String | StatKind::Id => {}
+        }
+    }
+}
+
+fn collect_metrics(
+    handle: &str,
+    snapshot: &MetricsSnapshot,
+    families: &mut BTreeMap<String, Family>,
+) {
+    let labels = vec![("handle".to_owned(), handle.to_owned())];
+    let mut sample = |name: &str, help: &str, kind, labels: &[(String, String)], value: String| {
+        families
+            .entry(format!("nng_async_{}", name))
+            .or_insert_with(|| Family::new(help, kind))
+            .samples
+            .push(format!("{} {}", format_labels(labels), value));
+    };
+    let counters = [
+        ("sends_total", "messages sent", snapshot.sends),
+        ("receives_total", "messages received", snapshot.receives),
+        (
+            "dropped_total",
+            "results nothing was waiting for",
+            snapshot.dropped,
+        ),
+    ];
+    for (name, help, value) in counters.iter() {
+        sample(name, help, "counter", &labels, value.to_string());
+    }
+    let mut errors: Vec<(String, u64)> = snapshot
+        .errors
+        .iter()
+        .map(|(errno, count)| (format!("{:?}", errno), *count))
+        .collect();
+    errors.push(("other".to_owned(), snapshot.other_errors));
+    errors.sort();
+    for (errno, count) in errors {
+        let mut labels = labels.clone();
+        labels.push(("errno".to_owned(), errno));
+        sample(
+            "errors_total",
+            "failed operations",
+            "counter",
+            &labels,
+            count.to_string(),
+        );
+    }
+    let depth = snapshot.queue_depth.to_string();
+    sample(
+        "queue_depth",
+        "results waiting to be taken",
+        "gauge",
+        &labels,
+        depth,
+    );
+
+    let latency = &snapshot.latency;
+    let family = families
+        .entry("nng_async_latency_seconds".to_owned())
+        .or_insert_with(|| Family::new("time from request to reply", "histogram"));
+    for (bound, cumulative) in latency.buckets() {
+        let le = bound.map_or("+Inf".to_owned(), |bound| bound.as_secs_f64().to_string());
+        let mut labels = labels.clone();
+        labels.push(("le".to_owned(), le));
+        let labels = format_labels(&labels);
+        family
+            .samples
+            .push(format!("_bucket{} {}", labels, cumulative));
+    }
+    let labels = format_labels(&labels);
+    let sum = latency.sum().as_secs_f64();
+    family.samples.push(format!("_sum{} {}", labels, sum));
+    family
+        .samples
+        .push(format!("_count{} {}", labels, latency.count()));
+}
+
+/// Labels of `scope` named `scope_name`: those of its parent plus its ids and strings.
+fn scope_labels(scope: &StatNode, scope_name: &str, parent: &[(String, String)]) -> Labels {
+    let mut labels: Labels = parent.to_vec();
+    for child in scope.children.iter() {
+        let value = match &child.value {
+            StatValue::Number(value) if child.kind == StatKind::Id => value.to_string(),
+            StatValue::String(value) => value.clone(),
+            _ => continue,
+        };
+        let name = if child.name == "id" {
+            // "socket_id", "dialer_id", etc.
+            format!("{}_id", scope_name)
+        } else {
+            sanitize(&child.name)
+        };
+        labels.retain(|(existing, _)| *existing != name);
+        labels.push((name, value));
+    }
+    labels.sort();
+    labels
+}
+
+fn format_labels(labels: &[(String, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Replace characters not allowed in metric and label names.
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label(value: &str) -> String {
+    escape_help(value).replace('"', "\\\"")
+}
+
+/// Serves statistics to Prometheus at `/metrics` over HTTP.
+///
+/// Listens on an nng stream URL (normally `tcp://`) and answers up to `MAX_CONNECTIONS` requests
+/// at a time, each on its own thread, until `close()` is called or it is dropped.  Clients that
+/// don't send a request or read the response within `REQUEST_TIMEOUT` are disconnected.
+#[derive(Debug)]
+pub struct MetricsServer {
+    listener: Arc<StreamListener>,
+    serving: Arc<Mutex<Serving>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct Serving {
+    /// Connections being served, so `close()` can end them
+    streams: HashMap<u64, Arc<NngStream>>,
+    closed: bool,
+}
+
+// Requests are tiny, anything larger isn't a scrape
+const MAX_REQUEST: usize = 8 * 1024;
+// Pause after accept failures like running out of file descriptors so they don't spin
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+impl MetricsServer {
+    /// Connections served at once.  More wait to be accepted.
+    pub const MAX_CONNECTIONS: usize = 16;
+    /// Milliseconds allowed for each read and write of a connection.
+    pub const REQUEST_TIMEOUT: nng_duration = 10_000;
+}
+
+impl MetricsServer {
+    /// Listen on `url`, e.g. `tcp://0.0.0.0:9100`.
+    pub fn start(url: &str) -> Result<Self> {
+        let listener = StreamListener::alloc(url)?;
+        listener.listen()?;
+        let listener = Arc::new(listener);
+        let serving = Arc::new(Mutex::new(Serving::default()));
+        let thread = {
+            let listener = listener.clone();
+            let serving = serving.clone();
+            thread::Builder::new()
+                .name("runng-metrics".to_owned())
+                .spawn(move || serve(&listener, &serving))
+                .map_err(|_| Error::Errno(NngErrno::ENOMEM))?
+        };
+        Ok(Self {
+            listener,
+            serving,
+            thread: Some(thread),
+        })
+    }
+
+    /// Listener accepting connections.  Use to query options like `NngOption::TCP_BOUND_PORT`.
+    pub fn listener(&self) -> &StreamListener {
+        &self.listener
+    }
+
+    /// Stop serving and wait for the background thread to exit.
+    pub fn close(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.listener.close();
+        {
+            let mut serving = self.serving.lock().unwrap();
+            serving.closed = true;
+            for stream in serving.streams.values() {
+                stream.close();
+            }
+        }
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .unwrap_or_else(|_| debug!("Metrics thread panicked"));
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn serve(listener: &StreamListener, serving: &Arc<Mutex<Serving>>) {
+    let mut incoming = match listener.incoming(MetricsServer::MAX_CONNECTIONS) {
+        Ok(incoming) => incoming,
+        Err(err) => {
+            debug!("Metrics failed to accept: {:?}", err);
+            return;
+        }
+    };
+    let mut threads = HashMap::new();
+    // Connection threads send their id once done so their handles can be joined
+    let (finished_sender, finished) = std_mpsc::channel();
+    let mut next_id = 0u64;
+    while let Some(res) = block_on(incoming.next()) {
+        let stream = match res {
+            Ok(stream) => Arc::new(stream),
+            // The client gave up before the connection was accepted
+            Err(ref err)
+                if err.errno() == Some(NngErrno::ECONNABORTED)
+                    || err.errno() == Some(NngErrno::ECONNRESET) =>
+            {
+                debug!("Metrics accept failed: {:?}", err);
+                continue;
+            }
+            Err(ref err) if err.is_closed() || err.errno() == Some(NngErrno::EINVAL) => {
+                debug!("Metrics stopped accepting: {:?}", err);
+                break;
+            }
+            Err(err) => {
+                debug!("Metrics accept failed: {:?}", err);
+                thread::sleep(ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+        let id = next_id;
+        next_id += 1;
+        {
+            let mut serving = serving.lock().unwrap();
+            if serving.closed {
+                stream.close();
+                break;
+            }
+            serving.streams.insert(id, stream.clone());
+        }
+        let thread = {
+            let serving = serving.clone();
+            let finished_sender = finished_sender.clone();
+            thread::Builder::new()
+                .name("runng-metrics-connection".to_owned())
+                .spawn(move || {
+                    if let Err(err) = respond(&stream) {
+                        debug!("Metrics request failed: {:?}", err);
+                    }
+                    stream.close();
+                    serving.lock().unwrap().streams.remove(&id);
+                    let _ = finished_sender.send(id);
+                })
+        };
+        match thread {
+            Ok(thread) => {
+                threads.insert(id, thread);
+            }
+            Err(err) => {
+                debug!("Metrics failed to spawn thread: {:?}", err);
+                serving.lock().unwrap().streams.remove(&id);
+            }
+        }
+        // Don't accumulate handles of finished connections
+        while let Ok(id) = finished.try_recv() {
+            if let Some(thread) = threads.remove(&id) {
+                let _ = thread.join();
+            }
+        }
+    }
+    for (_, thread) in threads {
+        let _ = thread.join();
+    }
+}
+
+fn respond(stream: &NngStream) -> Result<()> {
+    let mut queue = SimpleAioWorkQueue::new()?;
+    queue.set_timeout(MetricsServer::REQUEST_TIMEOUT);
+    let request = read_request(stream, &mut queue)?;
+    let request_line = request.lines().next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => match render_current() {
+            Ok(body) => response("200 OK", &body),
+            Err(err) => response("500 Internal Server Error", &format!("{}\n", err)),
+        },
+        (Some("GET"), _) => response("404 Not Found", "Not found\n"),
+        _ => response("405 Method Not Allowed", "Method not allowed\n"),
+    };
+    write_all(stream, &mut queue, response.into_bytes())
+}
+
+fn response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.0 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Read up to the end of the request headers.
+fn read_request(stream: &NngStream, queue: &mut SimpleAioWorkQueue) -> Result<String> {
+    let mut request = Vec::new();
+    let mut buffer = vec![0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST {
+            return Err(Error::Errno(NngErrno::EMSGSIZE));
+        }
+        let (filled, res) = block_on(stream.recv_buffers(queue, buffer))?;
+        let received = res?;
+        if received == 0 {
+            return Err(Error::Errno(NngErrno::ECONNSHUT));
+        }
+        request.extend_from_slice(&filled[..received]);
+        buffer = filled;
+    }
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+fn write_all(stream: &NngStream, queue: &mut SimpleAioWorkQueue, mut data: Vec<u8>) -> Result<()> {
+    while !data.is_empty() {
+        let (mut remaining, res) = block_on(stream.send_buffers(queue, data))?;
+        let sent = res?;
+        remaining.drain(..sent);
+        data = remaining;
+    }
+    Ok(())
+}
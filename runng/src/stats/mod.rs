@@ -36,6 +36,15 @@ fn stats_example() -> Result<()> {
 ```
 */
 
+pub mod export;
+mod sampler;
+mod scoped;
+mod tree;
+
+pub use self::sampler::*;
+pub use self::scoped::*;
+pub use self::tree::*;
+
 use crate::*;
 use log::trace;
 use runng_sys::*;
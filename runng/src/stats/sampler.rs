@@ -0,0 +1,212 @@
+//! Periodic statistics snapshots and counter rates.
+
+use super::*;
+use futures::{
+    channel::mpsc,
+    executor::block_on,
+    sink::SinkExt,
+    stream::Stream,
+    task::{Context, Poll},
+};
+use std::{pin::Pin, thread, time::Duration};
+
+/// Change of a counter between two snapshots.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CounterDelta {
+    /// Names from the root separated by `/`, e.g. `socket3/tx_msgs`
+    pub path: String,
+    pub unit: StatUnit,
+    /// Value in the later snapshot
+    pub value: u64,
+    /// Increase since the earlier snapshot
+    pub delta: u64,
+    /// `delta` per second
+    pub rate: f64,
+}
+
+/// Changes of all counters between two snapshots.
+///
+/// # Examples
+/// ```
+/// use runng::stats::*;
+///
+/// fn test() -> runng::Result<()> {
+///     let before = NngStatRoot::new()?.to_tree()?;
+///     // ...
+///     let after = NngStatRoot::new()?.to_tree()?;
+///     let delta = StatsDelta::between(&before, &after);
+///     if let Some(tx) = delta.get("socket/1/tx_bytes") {
+///         println!("{} bytes/s", tx.rate);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsDelta {
+    /// Time between the snapshots
+    pub elapsed: Duration,
+    /// Counters in the later snapshot
+    pub counters: Vec<CounterDelta>,
+    /// The later snapshot
+    pub current: StatNode,
+}
+
+impl StatsDelta {
+    /// Compare counters of `current` with those of an earlier snapshot.
+    ///
+    /// Counters missing from `previous` (e.g. of a newly opened socket) count from zero.  If a
+    /// counter went down it was reset, and counts from zero too.
+    pub fn between(previous: &StatNode, current: &StatNode) -> StatsDelta {
+        let elapsed = current.timestamp.saturating_sub(previous.timestamp);
+        let mut counters = Vec::new();
+        collect_counters(current, Some(previous), "", elapsed, &mut counters);
+        StatsDelta {
+            elapsed: Duration::from_millis(elapsed),
+            counters,
+            current: current.clone(),
+        }
+    }
+
+    /// Returns change of counter at `path`, with the same syntax as `StatNode::get()`.
+    pub fn get(&self, path: &str) -> Option<&CounterDelta> {
+        let found = self.counters.iter().find(|counter| counter.path == path);
+        found.or_else(|| {
+            let path = join_ids(path);
+            self.counters.iter().find(|counter| counter.path == path)
+        })
+    }
+
+    /// Returns changes of counters in scope `scope` (e.g. `socket3`) and its descendants.
+    pub fn scope<'a>(&'a self, scope: &str) -> impl Iterator<Item = &'a CounterDelta> {
+        let prefix = format!("{}/", join_ids(scope).trim_end_matches('/'));
+        self.counters
+            .iter()
+            .filter(move |counter| counter.path.starts_with(&prefix))
+    }
+}
+
+fn collect_counters(
+    node: &StatNode,
+    previous: Option<&StatNode>,
+    path: &str,
+    default_elapsed: u64,
+    counters: &mut Vec<CounterDelta>,
+) {
+    for child in node.children.iter() {
+        let previous = previous.and_then(|previous| previous.child(&child.name));
+        let path = if path.is_empty() {
+            child.name.clone()
+        } else {
+            format!("{}/{}", path, child.name)
+        };
+        match (child.kind, &child.value) {
+            (StatKind::Scope, _) => {
+                collect_counters(child, previous, &path, default_elapsed, counters)
+            }
+            (StatKind::Counter, StatValue::Number(value)) => {
+                let before = previous.and_then(|stat| Some((stat.value.as_u64()?, stat.timestamp)));
+                let (before, elapsed) = match before {
+                    Some((before, timestamp)) if before <= *value => {
+                        (before, child.timestamp.saturating_sub(timestamp))
+                    }
+                    _ => (0, default_elapsed),
+                };
+                let delta = value - before;
+                let rate = if elapsed > 0 {
+                    delta as f64 * 1000.0 / elapsed as f64
+                } else {
+                    0.0
+                };
+                counters.push(CounterDelta {
+                    path,
+                    unit: child.unit,
+                    value: *value,
+                    delta,
+                    rate,
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+/// "socket/3/tx_msgs" -> "socket3/tx_msgs"
+fn join_ids(path: &str) -> String {
+    let mut joined = String::new();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        let is_id = segment.parse::<u32>().is_ok();
+        if !joined.is_empty() && !is_id {
+            joined.push('/');
+        }
+        joined.push_str(segment);
+    }
+    joined
+}
+
+/// Stream of `StatsDelta` from snapshots taken periodically on a background thread.
+///
+/// Snapshots stop once the sampler is dropped.  If the stream isn't polled, sampling waits rather
+/// than buffering deltas, so each delta covers the time since the previous one.
+///
+/// # Examples
+/// ```
+/// use futures::{executor::block_on, stream::StreamExt};
+/// use runng::stats::*;
+/// use std::time::Duration;
+///
+/// fn test() -> runng::Result<()> {
+///     let mut sampler = StatsSampler::new(Duration::from_secs(1))?;
+///     while let Some(delta) = block_on(sampler.next()) {
+///         for counter in delta?.scope("socket/1") {
+///             println!("{}: {}/s", counter.path, counter.rate);
+///         }
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct StatsSampler {
+    receiver: mpsc::Receiver<Result<StatsDelta>>,
+}
+
+impl StatsSampler {
+    /// Take a snapshot now and then every `interval`, producing a delta for each.
+    pub fn new(interval: Duration) -> Result<Self> {
+        let previous = NngStatRoot::new()?.to_tree()?;
+        let (sender, receiver) = mpsc::channel(0);
+        thread::Builder::new()
+            .name("runng-stats".to_owned())
+            .spawn(move || sample(interval, previous, sender))
+            .map_err(|_| Error::Errno(NngErrno::ENOMEM))?;
+        Ok(Self { receiver })
+    }
+}
+
+impl Stream for StatsSampler {
+    type Item = Result<StatsDelta>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+fn sample(
+    interval: Duration,
+    mut previous: StatNode,
+    mut sender: mpsc::Sender<Result<StatsDelta>>,
+) {
+    loop {
+        thread::sleep(interval);
+        let res = NngStatRoot::new().and_then(|root| root.to_tree());
+        let item = res.map(|current| {
+            let delta = StatsDelta::between(&previous, &current);
+            previous = current;
+            delta
+        });
+        // Fails once the sampler is dropped
+        if block_on(sender.send(item)).is_err() {
+            break;
+        }
+    }
+    trace!("Stats sampler exiting");
+}
@@ -0,0 +1,231 @@
+//! Statistics of a single socket, dialer or listener.
+
+use super::*;
+
+/// Returns value of number statistic `name` of `scope`.
+fn number(scope: &StatNode, name: &str) -> Option<u64> {
+    scope.child(name)?.value.as_u64()
+}
+
+fn string<'a>(scope: &'a StatNode, name: &str) -> Option<&'a str> {
+    scope.child(name)?.value.as_str()
+}
+
+/// Scopes of the tree like `socket3` whose `id` (or `id_stat`) is `id`.
+fn scopes<'a>(
+    tree: &'a StatNode,
+    prefix: &'a str,
+    id_stat: &'a str,
+    id: i32,
+) -> impl Iterator<Item = &'a StatNode> {
+    tree.children.iter().filter(move |scope| {
+        scope.kind == StatKind::Scope
+            && scope.name.starts_with(prefix)
+            && number(scope, id_stat) == Some(id as u64)
+    })
+}
+
+/// Statistics of a socket, its dialers and its listeners.  See `GetSocket::stats()`.
+///
+/// # Examples
+/// ```
+/// use runng::{factory::latest::ProtocolFactory, *};
+///
+/// fn test() -> runng::Result<()> {
+///     let factory = ProtocolFactory::default();
+///     let mut req = factory.requester_open()?;
+///     req.dial("tcp://127.0.0.1:5555")?;
+///     let stats = req.stats()?;
+///     println!("Sent {:?} messages", stats.tx_msgs());
+///     for dialer in stats.dialers.iter() {
+///         println!("{:?} connected {:?} times", dialer.url(), dialer.connect());
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct SocketStats {
+    /// Socket scope, e.g. `socket3`
+    pub socket: StatNode,
+    pub dialers: Vec<DialerStats>,
+    pub listeners: Vec<ListenerStats>,
+}
+
+impl SocketStats {
+    /// Take a snapshot and find statistics of socket with id `id`.
+    pub fn for_id(id: i32) -> Result<Self> {
+        let tree = NngStatRoot::new()?.to_tree()?;
+        Self::from_tree(&tree, id).ok_or(Error::Errno(NngErrno::ENOENT))
+    }
+
+    /// Find statistics of socket with id `id` in `tree` (e.g. from `NngStatRoot::to_tree()`).
+    pub fn from_tree(tree: &StatNode, id: i32) -> Option<Self> {
+        let socket = scopes(tree, "socket", "id", id).next()?.clone();
+        let dialers = scopes(tree, "dialer", "socket", id)
+            .map(|node| DialerStats { node: node.clone() })
+            .collect();
+        let listeners = scopes(tree, "listener", "socket", id)
+            .map(|node| ListenerStats { node: node.clone() })
+            .collect();
+        Some(Self {
+            socket,
+            dialers,
+            listeners,
+        })
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        number(&self.socket, "id")
+    }
+    pub fn name(&self) -> Option<&str> {
+        string(&self.socket, "name")
+    }
+    pub fn protocol(&self) -> Option<&str> {
+        string(&self.socket, "protocol")
+    }
+    /// Open pipes
+    pub fn pipes(&self) -> Option<u64> {
+        number(&self.socket, "pipes")
+    }
+    /// Pipes rejected by the protocol
+    pub fn reject(&self) -> Option<u64> {
+        number(&self.socket, "reject")
+    }
+    pub fn tx_msgs(&self) -> Option<u64> {
+        number(&self.socket, "tx_msgs")
+    }
+    pub fn rx_msgs(&self) -> Option<u64> {
+        number(&self.socket, "rx_msgs")
+    }
+    pub fn tx_bytes(&self) -> Option<u64> {
+        number(&self.socket, "tx_bytes")
+    }
+    pub fn rx_bytes(&self) -> Option<u64> {
+        number(&self.socket, "rx_bytes")
+    }
+}
+
+/// Statistics of a dialer.  See `StartedDialer::stats()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DialerStats {
+    /// Dialer scope, e.g. `dialer5`
+    pub node: StatNode,
+}
+
+impl DialerStats {
+    /// Take a snapshot and find statistics of dialer with id `id`.
+    pub fn for_id(id: i32) -> Result<Self> {
+        let tree = NngStatRoot::new()?.to_tree()?;
+        Self::from_tree(&tree, id).ok_or(Error::Errno(NngErrno::ENOENT))
+    }
+
+    /// Find statistics of dialer with id `id` in `tree`.
+    pub fn from_tree(tree: &StatNode, id: i32) -> Option<Self> {
+        let node = scopes(tree, "dialer", "id", id).next()?.clone();
+        Some(Self { node })
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        number(&self.node, "id")
+    }
+    /// Id of socket the dialer belongs to
+    pub fn socket(&self) -> Option<u64> {
+        number(&self.node, "socket")
+    }
+    pub fn url(&self) -> Option<&str> {
+        string(&self.node, "url")
+    }
+    /// Open pipes
+    pub fn pipes(&self) -> Option<u64> {
+        number(&self.node, "pipes")
+    }
+    /// Successful connections
+    pub fn connect(&self) -> Option<u64> {
+        number(&self.node, "connect")
+    }
+    /// Connections refused by the peer
+    pub fn refused(&self) -> Option<u64> {
+        number(&self.node, "refused")
+    }
+    pub fn disconnect(&self) -> Option<u64> {
+        number(&self.node, "disconnect")
+    }
+    pub fn timedout(&self) -> Option<u64> {
+        number(&self.node, "timedout")
+    }
+    /// Pipes rejected by the protocol
+    pub fn reject(&self) -> Option<u64> {
+        number(&self.node, "reject")
+    }
+    pub fn tx_msgs(&self) -> Option<u64> {
+        number(&self.node, "tx_msgs")
+    }
+    pub fn rx_msgs(&self) -> Option<u64> {
+        number(&self.node, "rx_msgs")
+    }
+    pub fn tx_bytes(&self) -> Option<u64> {
+        number(&self.node, "tx_bytes")
+    }
+    pub fn rx_bytes(&self) -> Option<u64> {
+        number(&self.node, "rx_bytes")
+    }
+}
+
+/// Statistics of a listener.  See `StartedListener::stats()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListenerStats {
+    /// Listener scope, e.g. `listener2`
+    pub node: StatNode,
+}
+
+impl ListenerStats {
+    /// Take a snapshot and find statistics of listener with id `id`.
+    pub fn for_id(id: i32) -> Result<Self> {
+        let tree = NngStatRoot::new()?.to_tree()?;
+        Self::from_tree(&tree, id).ok_or(Error::Errno(NngErrno::ENOENT))
+    }
+
+    /// Find statistics of listener with id `id` in `tree`.
+    pub fn from_tree(tree: &StatNode, id: i32) -> Option<Self> {
+        let node = scopes(tree, "listener", "id", id).next()?.clone();
+        Some(Self { node })
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        number(&self.node, "id")
+    }
+    /// Id of socket the listener belongs to
+    pub fn socket(&self) -> Option<u64> {
+        number(&self.node, "socket")
+    }
+    pub fn url(&self) -> Option<&str> {
+        string(&self.node, "url")
+    }
+    /// Open pipes
+    pub fn pipes(&self) -> Option<u64> {
+        number(&self.node, "pipes")
+    }
+    /// Accepted connections
+    pub fn accept(&self) -> Option<u64> {
+        number(&self.node, "accept")
+    }
+    pub fn disconnect(&self) -> Option<u64> {
+        number(&self.node, "disconnect")
+    }
+    /// Pipes rejected by the protocol
+    pub fn reject(&self) -> Option<u64> {
+        number(&self.node, "reject")
+    }
+    pub fn tx_msgs(&self) -> Option<u64> {
+        number(&self.node, "tx_msgs")
+    }
+    pub fn rx_msgs(&self) -> Option<u64> {
+        number(&self.node, "rx_msgs")
+    }
+    pub fn tx_bytes(&self) -> Option<u64> {
+        number(&self.node, "tx_bytes")
+    }
+    pub fn rx_bytes(&self) -> Option<u64> {
+        number(&self.node, "rx_bytes")
+    }
+}
@@ -0,0 +1,217 @@
+//! Owned copy of a statistics snapshot.
+
+use super::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Kind of statistic.  See [nng_stat_type](https://nng.nanomsg.org/man/v1.2.2/nng_stat_type.3).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde")
+)]
+pub enum StatKind {
+    /// Groups child statistics, has no value
+    Scope,
+    /// Value that can go up and down
+    Level,
+    /// Value that only goes up
+    Counter,
+    String,
+    Boolean,
+    /// Identifier such as a socket id
+    Id,
+}
+
+impl From<nng_stat_type_enum> for StatKind {
+    fn from(kind: nng_stat_type_enum) -> Self {
+        match kind {
+            nng_stat_type_enum::NNG_STAT_SCOPE => StatKind::Scope,
+            nng_stat_type_enum::NNG_STAT_LEVEL => StatKind::Level,
+            nng_stat_type_enum::NNG_STAT_COUNTER => StatKind::Counter,
+            nng_stat_type_enum::NNG_STAT_STRING => StatKind::String,
+            nng_stat_type_enum::NNG_STAT_BOOLEAN => StatKind::Boolean,
+            nng_stat_type_enum::NNG_STAT_ID => StatKind::Id,
+        }
+    }
+}
+
+/// Unit of statistic value.  See [nng_stat_unit](https://nng.nanomsg.org/man/v1.2.2/nng_stat_unit.3).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde")
+)]
+pub enum StatUnit {
+    None,
+    Bytes,
+    Messages,
+    Millis,
+    Events,
+}
+
+impl From<nng_unit_enum> for StatUnit {
+    fn from(unit: nng_unit_enum) -> Self {
+        match unit {
+            nng_unit_enum::NNG_UNIT_NONE => StatUnit::None,
+            nng_unit_enum::NNG_UNIT_BYTES => StatUnit::Bytes,
+            nng_unit_enum::NNG_UNIT_MESSAGES => StatUnit::Messages,
+            nng_unit_enum::NNG_UNIT_MILLIS => StatUnit::Millis,
+            nng_unit_enum::NNG_UNIT_EVENTS => StatUnit::Events,
+        }
+    }
+}
+
+/// Value of statistic, typed according to its `StatKind`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde")
+)]
+pub enum StatValue {
+    /// Scopes have no value
+    None,
+    /// Value of levels, counters and ids
+    Number(u64),
+    String(String),
+    Bool(bool),
+}
+
+impl StatValue {
+    /// Returns value of levels, counters and ids.
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            StatValue::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns value of string statistics.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            StatValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns value of boolean statistics.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            StatValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+/** Statistic and its children copied out of a snapshot.
+
+Unlike `NngStatChild`, it doesn't borrow the `NngStatRoot` it came from so it can be kept, compared with
+later snapshots, or serialized (with the `serde` feature).
+
+## Examples
+```rust
+use runng::stats::*;
+fn test() -> runng::Result<()> {
+    let tree = NngStatRoot::new()?.to_tree()?;
+    for socket in tree.children.iter().filter(|node| node.name.starts_with("socket")) {
+        let tx_msgs = socket.get("tx_msgs").and_then(|stat| stat.value.as_u64());
+        println!("{}: {:?}", socket.name, tx_msgs);
+    }
+    // Same as "socket3/rx_bytes"
+    let rx_bytes = tree.get("socket/3/rx_bytes");
+    Ok(())
+}
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde")
+)]
+pub struct StatNode {
+    pub name: String,
+    pub desc: String,
+    pub kind: StatKind,
+    pub unit: StatUnit,
+    pub value: StatValue,
+    /// Milliseconds since an arbitrary point in time
+    pub timestamp: u64,
+    pub children: Vec<StatNode>,
+}
+
+impl StatNode {
+    /// Copy `stat` and all of its children.
+    fn from_stat(stat: &NngStatChild) -> Result<StatNode> {
+        let kind = StatKind::from(stat.stat_type()?);
+        let value = match kind {
+            StatKind::Scope => StatValue::None,
+            StatKind::Level | StatKind::Counter | StatKind::Id => StatValue::Number(stat.value()),
+            StatKind::String => StatValue::String(stat.string().unwrap_or_default().to_owned()),
+            StatKind::Boolean => StatValue::Bool(stat.value() != 0),
+        };
+        let mut children = Vec::new();
+        if let Some(child) = stat.child() {
+            for child in child.iter() {
+                children.push(StatNode::from_stat(&child)?);
+            }
+        }
+        Ok(StatNode {
+            name: stat.name().unwrap_or_default().to_owned(),
+            desc: stat.desc().unwrap_or_default().to_owned(),
+            kind,
+            unit: StatUnit::from(stat.unit()?),
+            value,
+            timestamp: stat.timestamp(),
+            children,
+        })
+    }
+
+    /// Returns the immediate child named `name`.
+    pub fn child(&self, name: &str) -> Option<&StatNode> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    /// Returns the descendant at `path`, child names separated by `/`.
+    ///
+    /// Scopes named after an id, like `socket3`, may also be given as two segments, `socket/3`.
+    pub fn get(&self, path: &str) -> Option<&StatNode> {
+        let segments: Vec<&str> = path.split('/').filter(|seg| !seg.is_empty()).collect();
+        self.get_segments(&segments)
+    }
+
+    fn get_segments(&self, segments: &[&str]) -> Option<&StatNode> {
+        let (first, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => return Some(self),
+        };
+        if let Some(found) = self.child(first).and_then(|child| child.get_segments(rest)) {
+            return Some(found);
+        }
+        // Try "socket" + "3" as "socket3"
+        let (id, rest) = rest.split_first()?;
+        if id.parse::<u32>().is_err() {
+            return None;
+        }
+        self.child(&format!("{}{}", first, id))?.get_segments(rest)
+    }
+}
+
+impl NngStatRoot {
+    /// Copy the snapshot into an owned tree.
+    pub fn to_tree(&self) -> Result<StatNode> {
+        // Root isn't a child, but the accessors are the same
+        let root = NngStatChild::new(self.node).ok_or(Error::Errno(NngErrno::EINVAL))?;
+        StatNode::from_stat(&root)
+    }
+}
+
+impl NngStatChild<'_> {
+    /// Copy this statistic and its children into an owned tree.
+    pub fn to_tree(&self) -> Result<StatNode> {
+        StatNode::from_stat(self)
+    }
+}
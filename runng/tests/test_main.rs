@@ -5,17 +5,27 @@ mod tests {
 
     mod broker_tests;
     mod bus_tests;
+    mod codec_tests;
+    mod envelope_tests;
+    mod error_tests;
     mod future_tests;
     mod mem_tests;
+    mod metrics_tests;
     mod msg_tests;
+    mod mux_tests;
     mod options_tests;
     mod pair_tests;
     mod pipe_tests;
+    mod proxy_tests;
     mod pubsub_tests;
     mod pushpull_tests;
     mod reqrep_tests;
+    mod rpc_tests;
+    mod shutdown_tests;
+    mod socket_tests;
     mod stats_tests;
     mod stream_tests;
+    mod tracing_tests;
 
     use crate::common::*;
     use futures::{executor::block_on, future};
@@ -29,36 +39,36 @@ mod tests {
 
         let replier = factory.replier_open()?;
         {
+            let listener = replier.listener_create(&url)?.start()?;
             {
-                let listener = replier.listener_create(&url)?;
-                listener.start()?;
                 let requester = factory.requester_open()?;
-                {
-                    let req_dialer = requester.dialer_create(&url)?;
-                    assert_eq!(
-                        url,
-                        req_dialer
-                            .get_string(NngOption::URL)
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                    );
-                    req_dialer.start()?;
-                    requester.sendmsg(msg::NngMsg::new()?)?;
-                    let _request = replier.recvmsg()?;
-                    // Drop the dialer
-                }
-                // requester still works
+                let req_dialer = requester.dialer_create(&url)?;
+                assert_eq!(
+                    url,
+                    req_dialer
+                        .get_string(NngOption::URL)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                );
+                let _req_dialer = req_dialer.start()?;
+                assert_eq!(requester.socket().dialers().len(), 1);
                 requester.sendmsg(msg::NngMsg::new()?)?;
                 let _request = replier.recvmsg()?;
-                // Drop the listener
+                // Drop the dialer, which closes it
             }
-            // Replier still works
+            // Listener still works
             let mut requester = factory.requester_open()?;
             requester.dial(&url)?;
             requester.sendmsg(msg::NngMsg::new()?)?;
             let _request = replier.recvmsg()?;
+            assert_eq!(replier.socket().listeners()[0].id(), listener.id());
+            listener.close()?;
         }
+        // Nothing to dial once the listener is closed
+        assert!(replier.socket().listeners().is_empty());
+        let mut requester = factory.requester_open()?;
+        assert!(requester.dial(&url).is_err());
 
         Ok(())
     }
@@ -0,0 +1,132 @@
+#![cfg(feature = "serde")]
+
+use crate::common::*;
+use runng::{
+    asyncio::*,
+    codec::*,
+    factory::latest::ProtocolFactory,
+    msg::{Envelope, TraceContext},
+    protocol::*,
+    *,
+};
+use std::collections::HashMap;
+
+type Value = (u32, String, Vec<u8>, Option<bool>);
+
+fn value() -> Value {
+    (42, "runng".to_owned(), vec![1, 2, 3], Some(true))
+}
+
+fn roundtrip<C: Codec>() -> runng::Result<()> {
+    let value = value();
+    let msg = C::encode_msg(&value)?;
+    assert_eq!(value, C::decode_msg::<Value>(&msg)?);
+
+    let mut map = HashMap::new();
+    map.insert("key".to_owned(), 1.5f64);
+    let bytes = C::encode(&map)?;
+    assert_eq!(map, C::decode::<HashMap<String, f64>>(&bytes)?);
+    Ok(())
+}
+
+#[test]
+fn codecs() -> runng::Result<()> {
+    roundtrip::<Bincode>()?;
+    roundtrip::<Json>()?;
+    roundtrip::<MessagePack>()?;
+    roundtrip::<Cbor>()?;
+    Ok(())
+}
+
+#[test]
+fn decode_error() -> runng::Result<()> {
+    let res = Json::decode::<Value>(b"not json");
+    match res {
+        Err(Error::Decode(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    let msg = Bincode::encode_msg(&1u8)?;
+    match Bincode::decode_msg::<Value>(&msg) {
+        Err(Error::Decode(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn typed_socket() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut pull = factory.puller_open()?;
+    pull.listen(&url)?;
+    let mut push = factory.pusher_open()?;
+    push.dial(&url)?;
+
+    let pull = TypedSocket::<_, Value, MessagePack>::new(pull);
+    let push = TypedSocket::<_, Value, MessagePack>::new(push);
+    push.send(&value())?;
+    assert_eq!(value(), pull.recv()?);
+
+    // Message encoded with a different codec fails to decode
+    push.get_ref().sendmsg(Json::encode_msg(&"nonsense")?)?;
+    assert!(pull.recv().is_err());
+    Ok(())
+}
+
+#[test]
+fn typed_pushpull() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut pull = factory.puller_open()?;
+    pull.listen(&url)?;
+    let mut push = factory.pusher_open()?;
+    push.dial(&url)?;
+
+    let mut pull: TypedPull<Value> = TypedPull::new(pull.create_async()?);
+    let mut push: TypedPush<Value> = TypedPush::new(push.create_async()?);
+    for _ in 0..4 {
+        block_on(push.send(&value()))?;
+        assert_eq!(value(), block_on(pull.receive())?);
+    }
+    Ok(())
+}
+
+#[test]
+fn typed_reqrep() -> runng::Result<()> {
+    let url = get_url();
+    let mut rep = Rep0::open()?;
+    rep.listen(&url)?;
+    let mut req = Req0::open()?;
+    req.dial(&url)?;
+
+    let mut rep: TypedReply<u32, String, Cbor> = TypedReply::new(rep.create_async()?);
+    let mut req: TypedRequest<u32, String, Cbor> = TypedRequest::new(req.create_async()?);
+    let reply = req.send(&7);
+    let request = block_on(rep.receive())?;
+    block_on(rep.reply(&request.to_string()))?;
+    assert_eq!("7", block_on(reply)?);
+    Ok(())
+}
+
+#[test]
+fn typed_envelope() -> runng::Result<()> {
+    let url = get_url();
+    let mut rep = Rep0::open()?;
+    rep.listen(&url)?;
+    let mut req = Req0::open()?;
+    req.dial(&url)?;
+
+    let mut rep: TypedReply<u32, String, Json> = TypedReply::new(rep.create_async()?);
+    let mut req: TypedRequest<u32, String, Json> = TypedRequest::new(req.create_async()?);
+    let trace = TraceContext::new_root(true);
+    let reply = req.send_envelope(&Envelope::with_trace_context(&trace), &7);
+    let (envelope, request) = block_on(rep.receive_envelope())?;
+    let received = envelope.trace_context().unwrap();
+    assert_eq!(received, trace);
+    let child = received.child();
+    block_on(rep.reply_envelope(&Envelope::with_trace_context(&child), &request.to_string()))?;
+    let (envelope, reply) = block_on(reply)?;
+    assert_eq!("7", reply);
+    assert_eq!(envelope.trace_context(), Some(child));
+    Ok(())
+}
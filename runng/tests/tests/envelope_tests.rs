@@ -0,0 +1,159 @@
+use crate::common::*;
+use runng::{
+    asyncio::*,
+    factory::latest::ProtocolFactory,
+    msg::{Envelope, NngMsg, TraceContext, ENVELOPE_MAGIC, TRACEPARENT},
+    *,
+};
+
+const TRACEPARENT_EXAMPLE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+#[test]
+fn envelope() -> runng::Result<()> {
+    let mut envelope = Envelope::new();
+    envelope.insert("key", "value");
+    envelope.insert("empty", "");
+    let mut msg = NngMsg::new()?;
+    msg.append_u32(42)?;
+    envelope.inject(&mut msg)?;
+    assert!(msg.body().starts_with(ENVELOPE_MAGIC));
+
+    let extracted = Envelope::extract(&mut msg)?.unwrap();
+    assert_eq!(extracted, envelope);
+    assert_eq!(extracted.get("key"), Some("value"));
+    assert_eq!(
+        extracted.iter().collect::<Vec<_>>(),
+        vec![("empty", ""), ("key", "value")]
+    );
+    assert_eq!(msg.body(), &[0, 0, 0, 42]);
+
+    // No envelope
+    assert_eq!(Envelope::extract(&mut msg)?, None);
+    let (envelope, msg) = Envelope::open(msg)?;
+    assert!(envelope.is_empty());
+    assert_eq!(msg.len(), 4);
+
+    // Empty envelope is just the prefix
+    let mut msg = NngMsg::new()?;
+    Envelope::new().inject(&mut msg)?;
+    assert_eq!(msg.len(), 7);
+    assert_eq!(Envelope::extract(&mut msg)?, Some(Envelope::new()));
+    assert!(msg.is_empty());
+    Ok(())
+}
+
+#[test]
+fn envelope_errors() -> runng::Result<()> {
+    let mut envelope = Envelope::new();
+    envelope.insert("key", "value");
+    let mut msg = NngMsg::new()?;
+    envelope.inject(&mut msg)?;
+    let len = msg.len();
+    msg.chop(1)?;
+    match Envelope::extract(&mut msg) {
+        Err(Error::Decode(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    // Failed extraction leaves message alone
+    assert_eq!(msg.len(), len - 1);
+
+    let mut msg = NngMsg::new()?;
+    msg.append_slice(ENVELOPE_MAGIC)?;
+    msg.append_slice(&[2, 0, 0])?;
+    match Envelope::extract(&mut msg) {
+        Err(Error::Decode(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+
+    let mut envelope = Envelope::new();
+    envelope.insert("key", "x".repeat(usize::from(u16::max_value()) + 1));
+    match envelope.inject(&mut NngMsg::new()?) {
+        Err(Error::Encode(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn traceparent() -> runng::Result<()> {
+    let trace: TraceContext = TRACEPARENT_EXAMPLE.parse()?;
+    assert_eq!(trace.trace_id[0], 0x4b);
+    assert_eq!(trace.parent_id[7], 0xb7);
+    assert!(trace.is_sampled());
+    assert_eq!(trace.to_string(), TRACEPARENT_EXAMPLE);
+
+    let mut envelope = Envelope::new();
+    envelope.insert(TRACEPARENT, TRACEPARENT_EXAMPLE);
+    assert_eq!(envelope.trace_context(), Some(trace));
+
+    // Later versions may add fields
+    let future = "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra";
+    assert!(!future.parse::<TraceContext>()?.is_sampled());
+
+    let invalid = [
+        "",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+        "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+    ];
+    for traceparent in invalid.iter() {
+        match traceparent.parse::<TraceContext>() {
+            Err(Error::Decode(_)) => {}
+            other => panic!("Unexpected {:?} for {}", other, traceparent),
+        }
+    }
+
+    let root = TraceContext::new_root(false);
+    assert!(!root.is_sampled());
+    let child = root.child();
+    assert_eq!(child.trace_id, root.trace_id);
+    assert_ne!(child.parent_id, root.parent_id);
+    assert_eq!(child.to_string().parse::<TraceContext>()?, child);
+    Ok(())
+}
+
+#[test]
+fn reqrep() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut rep_ctx = factory.replier_open()?.listen(&url)?.create_async()?;
+    let mut req_ctx = factory.requester_open()?.dial(&url)?.create_async()?;
+
+    let trace = TraceContext::new_root(true);
+    let mut msg = NngMsg::new()?;
+    msg.append_u32(1)?;
+    let reply = req_ctx.send_envelope(&Envelope::with_trace_context(&trace), msg);
+    let (envelope, request) = block_on(rep_ctx.receive_envelope())?;
+    assert_eq!(envelope.trace_context(), Some(trace));
+    assert_eq!(request.body(), &[0, 0, 0, 1]);
+
+    let child = trace.child();
+    block_on(rep_ctx.reply_envelope(&Envelope::with_trace_context(&child), NngMsg::new()?))??;
+    let (envelope, reply) = block_on(reply)?;
+    assert_eq!(envelope.trace_context(), Some(child));
+    assert!(reply.is_empty());
+    Ok(())
+}
+
+#[test]
+fn pushpull() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut pull_ctx = factory.puller_open()?.listen(&url)?.create_async()?;
+    let mut push_ctx = factory.pusher_open()?.dial(&url)?.create_async()?;
+
+    let trace = TraceContext::new_root(true);
+    block_on(push_ctx.send_envelope(&Envelope::with_trace_context(&trace), NngMsg::new()?))?;
+    let (envelope, _) = block_on(pull_ctx.receive_envelope())?;
+    assert_eq!(envelope.trace_context(), Some(trace));
+
+    // Messages without an envelope have an empty one
+    block_on(push_ctx.send(NngMsg::new()?))?;
+    let (envelope, _) = block_on(pull_ctx.receive_envelope())?;
+    assert!(envelope.is_empty());
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use crate::common::*;
+use runng::{factory::latest::ProtocolFactory, *};
+use std::{error::Error as _, io};
+
+#[test]
+fn context() {
+    let err = Error::Errno(NngErrno::ETIMEDOUT)
+        .context("recv")
+        .context("call echo");
+    assert_eq!(err.to_string(), "call echo failed: recv failed: ETIMEDOUT");
+    assert_eq!(err.root(), &Error::Errno(NngErrno::ETIMEDOUT));
+    assert_eq!(err.errno(), Some(NngErrno::ETIMEDOUT));
+    assert_eq!(err.source().unwrap().to_string(), "recv failed: ETIMEDOUT");
+
+    let res: runng::Result<()> = Ok(());
+    assert_eq!(res.with_context(|| -> String { unreachable!() }), Ok(()));
+}
+
+#[test]
+fn dial() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut req = factory.requester_open()?;
+    // Socket operations return the nng error itself so it can be matched
+    let err = req.dial(&url).unwrap_err();
+    assert_eq!(err, Error::Errno(NngErrno::ECONNREFUSED));
+    assert!(err.is_retryable());
+    assert!(!err.is_closed());
+
+    let mut rep = factory.replier_open()?;
+    rep.listen(&url)?;
+    let mut other = factory.replier_open()?;
+    assert_eq!(
+        other.listen(&url).unwrap_err(),
+        Error::Errno(NngErrno::EADDRINUSE)
+    );
+    assert_eq!(
+        other.dialer_create("bogus://localhost").unwrap_err(),
+        Error::Errno(NngErrno::ENOTSUP)
+    );
+    Ok(())
+}
+
+#[test]
+fn classify() {
+    let retryable = [
+        NngErrno::ETIMEDOUT,
+        NngErrno::ECONNREFUSED,
+        NngErrno::ECONNRESET,
+        NngErrno::ECONNSHUT,
+        NngErrno::EAGAIN,
+        NngErrno::EUNREACHABLE,
+    ];
+    for errno in retryable.iter() {
+        assert!(Error::Errno(*errno).is_retryable(), "{:?}", errno);
+    }
+    let fatal = [
+        NngErrno::ECLOSED,
+        NngErrno::EINVAL,
+        NngErrno::EADDRINVAL,
+        NngErrno::ENOTSUP,
+        NngErrno::ECANCELED,
+    ];
+    for errno in fatal.iter() {
+        assert!(!Error::Errno(*errno).is_retryable(), "{:?}", errno);
+    }
+    assert!(Error::Errno(NngErrno::ECLOSED).context("send").is_closed());
+    assert!(!Error::Decode(String::new()).is_closed());
+}
+
+#[test]
+fn io_error() {
+    let err: io::Error = Error::Errno(NngErrno::ECONNREFUSED).context("dial").into();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+    assert_eq!(err.to_string(), "dial failed: ECONNREFUSED");
+
+    let err: io::Error = Error::Decode("bad".to_owned()).into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // System errors become the operating system error (2 is ENOENT/ERROR_FILE_NOT_FOUND)
+    let sys = Error::SysErr(2);
+    assert_eq!(sys.sys_error().unwrap().raw_os_error(), Some(2));
+    assert_eq!(sys.kind(), io::ErrorKind::NotFound);
+    let err: io::Error = sys.context("open").into();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    let err: io::Error = Error::SysErr(2).into();
+    assert_eq!(err.raw_os_error(), Some(2));
+}
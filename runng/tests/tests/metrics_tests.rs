@@ -0,0 +1,152 @@
+use crate::common::*;
+use runng::{
+    asyncio::*,
+    factory::latest::ProtocolFactory,
+    options::{NngOption, SetOpts},
+    stats::export,
+    *,
+};
+use std::time::Duration;
+
+#[test]
+fn reqrep() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let rep_metrics = AsyncMetrics::new();
+    let mut rep_ctx = factory
+        .replier_open()?
+        .listen(&url)?
+        .create_async_with_metrics(rep_metrics.clone())?;
+    let req_metrics = AsyncMetrics::new();
+    let mut req_ctx = factory
+        .requester_open()?
+        .dial(&url)?
+        .create_async_with_metrics(req_metrics.clone())?;
+
+    for _ in 0..3 {
+        let reply = req_ctx.send(NngMsg::new()?);
+        block_on(rep_ctx.receive())?;
+        block_on(rep_ctx.reply(NngMsg::new()?))??;
+        block_on(reply)??;
+    }
+
+    let req = req_metrics.snapshot();
+    assert_eq!((req.sends, req.receives, req.error_count()), (3, 3, 0));
+    assert_eq!(req.latency.count(), 3);
+    assert!(req.latency.mean().unwrap() > Duration::from_millis(0));
+    let rep = rep_metrics.snapshot();
+    assert_eq!((rep.sends, rep.receives), (3, 3));
+    assert_eq!(rep.latency.count(), 0);
+    Ok(())
+}
+
+#[test]
+fn pull_queue() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let metrics = AsyncMetrics::new();
+    let mut pull_ctx = factory
+        .puller_open()?
+        .listen(&url)?
+        .create_async_with_metrics(metrics.clone())?;
+    let mut push_ctx = factory.pusher_open()?.dial(&url)?.create_async()?;
+
+    for _ in 0..2 {
+        block_on(push_ctx.send(NngMsg::new()?))?;
+    }
+    sleep_brief();
+    assert_eq!(metrics.snapshot().queue_depth, 2);
+    block_on(pull_ctx.receive())?;
+    block_on(pull_ctx.receive())?;
+
+    // Nothing waits for the result
+    drop(pull_ctx.receive());
+    block_on(push_ctx.send(NngMsg::new()?))?;
+    sleep_brief();
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.receives, 3);
+    assert_eq!(snapshot.queue_depth, 0);
+    assert_eq!(snapshot.max_queue_depth, 2);
+    assert_eq!(snapshot.dropped, 1);
+    Ok(())
+}
+
+#[test]
+fn errors() -> runng::Result<()> {
+    let url = get_url();
+    let metrics = AsyncMetrics::new();
+    let mut pull = protocol::Pull0::open()?;
+    pull.set_duration(NngOption::RECVTIMEO, DURATION_FAST)?;
+    let mut pull_ctx = pull
+        .listen(&url)?
+        .create_async_with_metrics(metrics.clone())?;
+
+    // Nothing to receive, so times out
+    match block_on(pull_ctx.receive()) {
+        Err(Error::Errno(NngErrno::ETIMEDOUT)) => {}
+        res => panic!("Unexpected: {:?}", res),
+    }
+    let snapshot = metrics.snapshot();
+    assert!(snapshot.error_count() >= 1);
+    assert!(snapshot.errors[&NngErrno::ETIMEDOUT] >= 1);
+    assert_eq!(snapshot.receives, 0);
+    Ok(())
+}
+
+#[test]
+fn disabled() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let metrics = AsyncMetrics::disabled();
+    let mut pull_ctx = factory
+        .puller_open()?
+        .listen(&url)?
+        .create_async_with_metrics(metrics.clone())?;
+    let mut push_ctx = factory.pusher_open()?.dial(&url)?.create_async()?;
+    block_on(push_ctx.send(NngMsg::new()?))?;
+    block_on(pull_ctx.receive())?;
+    assert!(!metrics.is_enabled());
+    assert_eq!(metrics.snapshot(), Default::default());
+    Ok(())
+}
+
+#[test]
+fn histogram() {
+    let mut histogram =
+        LatencyHistogram::new(&[Duration::from_millis(1), Duration::from_millis(10)]);
+    assert_eq!(histogram.mean(), None);
+    for ms in [1, 5, 5, 20].iter() {
+        histogram.record(Duration::from_millis(*ms));
+    }
+    assert_eq!(histogram.count(), 4);
+    assert_eq!(histogram.sum(), Duration::from_millis(31));
+    assert_eq!(
+        histogram.buckets(),
+        vec![
+            (Some(Duration::from_millis(1)), 1),
+            (Some(Duration::from_millis(10)), 3),
+            (None, 4)
+        ]
+    );
+    assert_eq!(histogram.quantile(0.5), Some(Duration::from_millis(10)));
+    assert_eq!(histogram.quantile(1.0), None);
+}
+
+#[test]
+fn export() {
+    let mut snapshot = MetricsSnapshot::default();
+    snapshot.sends = 2;
+    snapshot.errors.insert(NngErrno::ETIMEDOUT, 1);
+    snapshot.latency = LatencyHistogram::new(&[Duration::from_millis(500)]);
+    snapshot.latency.record(Duration::from_millis(250));
+    let text = export::render_metrics(&[("rpc", snapshot)]);
+    assert!(text.contains("# TYPE nng_async_sends_total counter\n"));
+    assert!(text.contains("nng_async_sends_total{handle=\"rpc\"} 2\n"));
+    assert!(text.contains("nng_async_errors_total{errno=\"ETIMEDOUT\",handle=\"rpc\"} 1\n"));
+    assert!(text.contains("# TYPE nng_async_latency_seconds histogram\n"));
+    assert!(text.contains("nng_async_latency_seconds_bucket{handle=\"rpc\",le=\"0.5\"} 1\n"));
+    assert!(text.contains("nng_async_latency_seconds_bucket{handle=\"rpc\",le=\"+Inf\"} 1\n"));
+    assert!(text.contains("nng_async_latency_seconds_sum{handle=\"rpc\"} 0.25\n"));
+    assert!(text.contains("nng_async_latency_seconds_count{handle=\"rpc\"} 1\n"));
+}
@@ -1,5 +1,6 @@
 use crate::common::*;
 use rand::Rng;
+use runng::msg::{NngDecode, NngEncode, NngMsgBuilder, NngMsgPool, PoolStats};
 
 #[test]
 fn equality() -> runng::Result<()> {
@@ -37,3 +38,331 @@ fn equality() -> runng::Result<()> {
     }
     Ok(())
 }
+
+#[derive(Debug, PartialEq, NngEncode, NngDecode)]
+struct Point(i32, i32);
+
+#[derive(Debug, PartialEq, NngEncode, NngDecode)]
+enum Shape {
+    Empty,
+    Circle { center: Point, radius: f32 },
+    Polygon(Vec<Point>),
+}
+
+#[derive(Debug, PartialEq, NngEncode, NngDecode)]
+struct Envelope<T> {
+    #[nng(header)]
+    id: u64,
+    #[nng(header)]
+    flags: u8,
+    name: String,
+    data: Vec<u8>,
+    reply: Option<bool>,
+    value: T,
+}
+
+#[test]
+fn encode_decode() -> runng::Result<()> {
+    let envelope = Envelope {
+        id: 0x0102_0304_0506_0708,
+        flags: 3,
+        name: "runng".to_owned(),
+        data: vec![1, 2, 3],
+        reply: Some(false),
+        value: vec![
+            Shape::Empty,
+            Shape::Circle {
+                center: Point(-1, 2),
+                radius: 1.5,
+            },
+            Shape::Polygon(vec![Point(0, 0), Point(1, 1)]),
+        ],
+    };
+    let mut msg = NngMsg::new()?;
+    envelope.encode(&mut msg)?;
+
+    // Header fields in network byte order
+    assert_eq!(msg.header(), &[1, 2, 3, 4, 5, 6, 7, 8, 3]);
+    // Strings are length-prefixed
+    assert_eq!(
+        &msg.body()[..9],
+        &[0, 0, 0, 5, b'r', b'u', b'n', b'n', b'g']
+    );
+
+    let decoded = Envelope::decode(&mut msg)?;
+    assert_eq!(envelope, decoded);
+    assert!(msg.is_empty());
+    assert_eq!(msg.header_len(), 0);
+
+    // Whole struct in header
+    let point = Point(7, 8);
+    point.encode_header(&mut msg)?;
+    assert_eq!(msg.header_len(), 8);
+    assert_eq!(Point::decode_header(&mut msg)?, point);
+    Ok(())
+}
+
+#[test]
+fn header_over_socket() -> runng::Result<()> {
+    use runng::{Dial, Listen, RecvSocket, SendSocket};
+
+    let url = get_url();
+    let mut receiver = protocol::Pair0::open()?;
+    receiver.listen(&url)?;
+    let mut sender = protocol::Pair0::open()?;
+    sender.dial(&url)?;
+
+    let envelope = Envelope {
+        id: 1,
+        flags: 2,
+        name: String::new(),
+        data: vec![],
+        reply: None,
+        value: 3u8,
+    };
+    let mut msg = NngMsg::new()?;
+    envelope.encode(&mut msg)?;
+    sender.sendmsg(msg)?;
+
+    // Header isn't delivered as a header, its bytes arrive in front of the body
+    let mut msg = receiver.recvmsg()?;
+    assert_eq!(msg.header_len(), 0);
+    assert_eq!(&msg.body()[..9], &[0, 0, 0, 0, 0, 0, 0, 1, 2]);
+    assert!(Envelope::<u8>::decode(&mut msg).is_err());
+    Ok(())
+}
+
+#[test]
+fn decode_errors() -> runng::Result<()> {
+    // Not enough bytes
+    let mut msg = NngMsg::new()?;
+    msg.append_u16(1)?;
+    match Point::decode(&mut msg) {
+        Err(runng::Error::Decode(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+
+    // Unknown variant
+    let mut msg = NngMsg::new()?;
+    msg.append_u32(3)?;
+    match Shape::decode(&mut msg) {
+        Err(runng::Error::Decode(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+
+    // Invalid UTF-8
+    let mut msg = NngMsg::new()?;
+    vec![0xffu8, 0xfe].encode(&mut msg)?;
+    match String::decode(&mut msg) {
+        Err(runng::Error::Decode(_)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn io() -> runng::Result<()> {
+    use std::io::{BufRead, Read, Write};
+
+    let mut msg = NngMsg::new()?;
+    msg.write_all(b"line 1\n").unwrap();
+    writeln!(msg, "line {}", 2).unwrap();
+    assert_eq!(msg.body(), b"line 1\nline 2\n");
+
+    let lines: Vec<String> = msg.reader().lines().map(|line| line.unwrap()).collect();
+    assert_eq!(lines, vec!["line 1", "line 2"]);
+
+    let mut reader = msg.reader();
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, b"line");
+    assert_eq!(reader.position(), 4);
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, b" 1\nline 2\n");
+    assert_eq!(reader.read(&mut buffer).unwrap(), 0);
+    Ok(())
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn bytes() -> runng::Result<()> {
+    use bytes::{Buf, BufMut};
+
+    let mut msg = NngMsg::new()?;
+    let large = vec![7u8; 1000];
+    {
+        let mut writer = msg.writer();
+        writer.put_u32(0x0102_0304);
+        writer.put_slice(b"abc");
+        // Large enough to reserve more than once
+        writer.put_slice(&large);
+        assert_eq!(writer.len(), 4 + 3 + 1000);
+    }
+    // Bytes reserved but not written are removed
+    assert_eq!(msg.len(), 4 + 3 + 1000);
+    assert_eq!(&msg.body()[..7], &[1, 2, 3, 4, b'a', b'b', b'c']);
+
+    let mut reader = msg.reader();
+    assert_eq!(reader.get_u32(), 0x0102_0304);
+    assert_eq!(reader.get_u8(), b'a');
+    reader.advance(2);
+    assert_eq!(reader.remaining(), 1000);
+    assert_eq!(reader.to_bytes().as_ref(), large.as_slice());
+
+    // Writing appends to the existing body
+    let mut writer = msg.writer();
+    writer.reserve(2)?;
+    writer.put_u16(0x0506);
+    drop(writer);
+    assert_eq!(&msg.body()[msg.len() - 3..], &[7, 5, 6]);
+    Ok(())
+}
+
+#[test]
+fn capacity() -> runng::Result<()> {
+    let msg = NngMsg::with_reserved(128)?;
+    assert!(msg.is_empty());
+    let msg = NngMsg::with_len(128)?;
+    assert_eq!(msg.len(), 128);
+    let msg = NngMsg::with_capacity(128)?;
+    assert_eq!(msg.len(), 128);
+
+    let mut msg = NngMsg::new()?;
+    msg.append_slice(b"abc")?;
+    msg.reserve(1024)?;
+    assert_eq!(msg.body(), b"abc");
+    msg.resize(6, b'x')?;
+    assert_eq!(msg.body(), b"abcxxx");
+    msg.resize(2, b'y')?;
+    assert_eq!(msg.body(), b"ab");
+    msg.truncate(8)?;
+    assert_eq!(msg.body(), b"ab");
+    msg.truncate(1)?;
+    assert_eq!(msg.body(), b"a");
+    unsafe {
+        msg.set_len(3)?;
+    }
+    msg.as_mut_slice()[1..].copy_from_slice(b"bc");
+    assert_eq!(msg.body(), b"abc");
+    Ok(())
+}
+
+#[test]
+fn builder() -> runng::Result<()> {
+    let mut builder = NngMsgBuilder::with_capacity(32)?;
+    builder
+        .put_u32(0)?
+        .put_u16(0x0102)?
+        .put_slice(b"abc")?
+        .encode("de")?
+        .encode_header(&7u32)?;
+    builder.put(2)?.copy_from_slice(b"fg");
+    let len = builder.len() as u32;
+    builder.set_u32(0, len);
+
+    let mut msg = builder.build();
+    assert_eq!(msg.header(), &[0, 0, 0, 7]);
+    assert_eq!(msg.trim_u32()?, 4 + 2 + 3 + 4 + 2 + 2);
+    assert_eq!(msg.trim_u16()?, 0x0102);
+    assert_eq!(&msg.body()[..3], b"abc");
+    msg.trim(3)?;
+    assert_eq!(String::decode(&mut msg)?, "de");
+    assert_eq!(msg.body(), b"fg");
+    Ok(())
+}
+
+#[test]
+fn pool() -> runng::Result<()> {
+    let pool = NngMsgPool::with_classes(&[64, 256], 1);
+
+    // Empty pool allocates
+    let mut msg = pool.get(10)?;
+    assert!(msg.is_empty());
+    msg.append_slice(&[1; 100])?;
+    msg.header_append_u32(1)?;
+    // Returned to the 256 class and cleared
+    let ptr = unsafe { msg.msg() };
+    pool.recycle(msg);
+    let stats = pool.stats();
+    assert_eq!(stats.misses, 1);
+    assert_eq!(stats.pooled, 1);
+
+    // Same message comes back
+    let msg = pool.get(200)?;
+    assert_eq!(unsafe { msg.msg() }, ptr);
+    assert!(msg.is_empty());
+    assert_eq!(msg.header_len(), 0);
+    assert_eq!(pool.stats().hits, 1);
+    assert_eq!(pool.stats().pooled, 0);
+
+    // Larger than any class
+    let mut big = pool.get(1000)?;
+    big.append_slice(&[0; 1000])?;
+    pool.recycle(big);
+    pool.recycle(msg);
+    // Each class is bounded
+    let mut msg = pool.get(100)?;
+    msg.append_slice(&[0; 200])?;
+    let mut full = pool.get(200)?;
+    full.append_slice(&[0; 200])?;
+    pool.recycle(msg);
+    pool.recycle(full);
+    assert_eq!(
+        pool.stats(),
+        PoolStats {
+            hits: 1,
+            misses: 4,
+            recycled: 5,
+            discarded: 2,
+            pooled: 2,
+        }
+    );
+
+    pool.clear();
+    assert_eq!(pool.stats().pooled, 0);
+
+    // Small messages go to the smallest class
+    let mut small = NngMsg::with_capacity(10)?;
+    small.trim(10)?;
+    let ptr = unsafe { small.msg() };
+    pool.recycle(small);
+    let msg = pool.get(64)?;
+    assert_eq!(unsafe { msg.msg() }, ptr);
+    assert!(msg.is_empty());
+    Ok(())
+}
+
+#[test]
+fn pool_send() -> runng::Result<()> {
+    use runng::{asyncio::*, Dial, Listen, RecvSocket, SendSocket};
+
+    let url = get_url();
+    let pool = NngMsgPool::new();
+    let mut puller = protocol::Pull0::open()?;
+    puller.listen(&url)?;
+    let mut pusher = protocol::Push0::open()?;
+    pusher.dial(&url)?;
+
+    // Synchronous
+    let mut msg = pool.get(4)?;
+    msg.append_u32(1)?;
+    pusher.sendmsg(msg)?;
+    let msg = puller.recvmsg()?;
+    assert_eq!(msg.body(), &[0, 0, 0, 1]);
+    pool.recycle(msg);
+
+    // Asynchronous, reusing the received message
+    let mut push_ctx = pusher.create_async()?;
+    let mut pull_ctx = puller.create_async()?;
+    let mut msg = pool.get(4)?;
+    msg.append_u32(2)?;
+    block_on(push_ctx.send(msg))?;
+    let msg = block_on(pull_ctx.receive())?;
+    assert_eq!(msg.body(), &[0, 0, 0, 2]);
+    pool.recycle(msg);
+
+    let stats = pool.stats();
+    assert_eq!((stats.hits, stats.misses, stats.pooled), (1, 1, 1));
+    Ok(())
+}
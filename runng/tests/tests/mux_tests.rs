@@ -0,0 +1,144 @@
+use crate::common::*;
+use futures::{
+    sink::SinkExt,
+    task::{noop_waker, Context},
+    Sink,
+};
+use runng::{mux::*, protocol::*, *};
+use std::{pin::Pin, thread};
+
+fn connect(window: u32) -> runng::Result<(Mux, Mux, Pair1, Pair1)> {
+    let url = get_url();
+    let mut server_socket = Pair1::open()?;
+    server_socket.listen(&url)?;
+    let mut client_socket = Pair1::open()?;
+    client_socket.dial(&url)?;
+
+    let (client, driver) = Mux::new(&client_socket, MuxConfig::client().window(window))?;
+    thread::spawn(move || block_on(driver));
+    let (server, driver) = Mux::new(&server_socket, MuxConfig::server().window(window))?;
+    thread::spawn(move || block_on(driver));
+    Ok((client, server, client_socket, server_socket))
+}
+
+fn msg_u32(value: u32) -> runng::Result<NngMsg> {
+    let mut msg = NngMsg::new()?;
+    msg.append_u32(value)?;
+    Ok(msg)
+}
+
+#[test]
+fn bidirectional() -> runng::Result<()> {
+    let (client, mut server, _client_socket, _server_socket) = connect(4)?;
+    const NUM_MESSAGES: u32 = 32;
+
+    // Echo every message of every stream
+    thread::spawn(move || {
+        while let Some(stream) = block_on(server.next()) {
+            thread::spawn(move || {
+                assert_eq!(stream.name(), "echo");
+                let (mut sink, stream) = stream.split();
+                block_on(stream.forward(&mut sink)).unwrap();
+                block_on(sink.close()).unwrap();
+            });
+        }
+    });
+
+    let streams: Vec<_> = (0..2)
+        .map(|_| client.open("echo"))
+        .collect::<runng::Result<_>>()?;
+    // Client streams have odd ids
+    assert_eq!(streams[0].id() % 2, 1);
+    assert_ne!(streams[0].id(), streams[1].id());
+
+    for stream in streams {
+        // More messages than the window, so must receive while sending
+        let (mut sink, mut stream) = stream.split();
+        let sender = thread::spawn(move || -> runng::Result<()> {
+            for i in 0..NUM_MESSAGES {
+                block_on(sink.send(msg_u32(i)?))?;
+            }
+            block_on(sink.close())
+        });
+        let mut expected = 0;
+        while let Some(msg) = block_on(stream.next()) {
+            let mut msg = msg?;
+            assert_eq!(msg.trim_u32()?, expected);
+            expected += 1;
+        }
+        assert_eq!(expected, NUM_MESSAGES);
+        sender.join().unwrap()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn flow_control() -> runng::Result<()> {
+    let (client, mut server, _client_socket, _server_socket) = connect(2)?;
+    let mut stream = client.open("")?;
+    block_on(stream.send(msg_u32(0)?))?;
+    block_on(stream.send(msg_u32(1)?))?;
+
+    // Window is full until server consumes messages
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    sleep_fast();
+    assert!(Pin::new(&mut stream).poll_ready(&mut cx).is_pending());
+    // Sending without waiting for credit fails instead of exceeding the window
+    let res = Pin::new(&mut stream).start_send(msg_u32(9)?);
+    assert_eq!(res.unwrap_err().errno(), Some(NngErrno::EAGAIN));
+
+    let mut accepted = block_on(server.next()).unwrap();
+    let mut msg = block_on(accepted.next()).unwrap()?;
+    assert_eq!(msg.trim_u32()?, 0);
+    block_on(stream.send(msg_u32(2)?))?;
+    Ok(())
+}
+
+#[test]
+fn reset() -> runng::Result<()> {
+    let (client, mut server, _client_socket, _server_socket) = connect(4)?;
+    let mut stream = client.open("")?;
+    block_on(stream.send(msg_u32(0)?))?;
+    let mut accepted = block_on(server.next()).unwrap();
+
+    // Dropping an open stream resets it
+    drop(stream);
+    let _msg = block_on(accepted.next()).unwrap()?;
+    match block_on(accepted.next()) {
+        Some(Err(Error::Errno(NngErrno::ECONNRESET))) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    assert!(block_on(accepted.next()).is_none());
+    assert_eq!(accepted.reset_code(), Some(RESET_CANCEL));
+
+    // Explicit reset with code
+    let stream = client.open("")?;
+    let mut accepted = block_on(server.next()).unwrap();
+    stream.reset(42);
+    match block_on(accepted.next()) {
+        Some(Err(Error::Errno(NngErrno::ECONNRESET))) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    assert_eq!(accepted.reset_code(), Some(42));
+    assert!(block_on(accepted.send(msg_u32(0)?)).is_err());
+    Ok(())
+}
+
+#[test]
+fn driver_completes() -> runng::Result<()> {
+    let url = get_url();
+    let mut server_socket = Pair1::open()?;
+    server_socket.listen(&url)?;
+    let mut client_socket = Pair1::open()?;
+    client_socket.dial(&url)?;
+    let (client, driver) = Mux::new(&client_socket, MuxConfig::client())?;
+    let driver = thread::spawn(move || block_on(driver));
+    let _stream = client.open("")?;
+
+    // Ends even though the mux and a stream are still alive
+    client_socket.socket().close()?;
+    driver.join().unwrap();
+    assert!(client.open("").unwrap_err().is_closed());
+    Ok(())
+}
@@ -29,8 +29,7 @@ fn sockaddr() -> runng::Result<()> {
     for url in get_urls() {
         let factory = ProtocolFactory::default();
         let sock = factory.pair_open()?;
-        let listener = sock.listener_create(&url)?;
-        listener.start()?;
+        let listener = sock.listener_create(&url)?.start()?;
         let sockaddr = listener.get_sockaddr(NngOption::LOCADDR)?;
         use SockAddr::*;
         match sockaddr {
@@ -0,0 +1,332 @@
+use crate::common::*;
+use futures::channel::mpsc;
+use runng::{
+    factory::latest::ProtocolFactory,
+    options::{GetOpts, NngOption, SetOpts},
+    proxy::*,
+    *,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static IPC_ID: AtomicUsize = AtomicUsize::new(1);
+fn get_ipc_url() -> String {
+    let val = IPC_ID.fetch_add(1, Ordering::Relaxed);
+    format!("ipc:///tmp/runng_proxy_{}_{}", std::process::id(), val)
+}
+
+// Listen on `url` and return the URL to dial
+fn listen(socket: &mut protocol::Pair1, url: &str) -> runng::Result<String> {
+    socket.listen(url)?;
+    if url.starts_with("tcp://") {
+        let port = socket.socket().listeners()[0].get_int(NngOption::TCP_BOUND_PORT)?;
+        Ok(format!("tcp://127.0.0.1:{}", port))
+    } else {
+        Ok(url.to_owned())
+    }
+}
+
+fn open() -> runng::Result<protocol::Pair1> {
+    let mut socket = ProtocolFactory::default().pair_open()?;
+    socket
+        .set_duration(NngOption::RECVTIMEO, DURATION_TEST)?
+        .set_duration(NngOption::SENDTIMEO, DURATION_TEST)?;
+    Ok(socket)
+}
+
+fn next_event(events: &mut mpsc::UnboundedReceiver<ProxyEvent>) -> ProxyEvent {
+    block_on(events.next()).unwrap()
+}
+
+fn forward(proxy_url: &str, target_url: &str) -> runng::Result<()> {
+    let mut target = open()?;
+    let target_url = listen(&mut target, target_url)?;
+    let mut proxy = Proxy::start(proxy_url, &target_url, ProxyConfig::default())?;
+    let mut events = proxy.take_events().unwrap();
+    let proxy_url = if proxy_url.starts_with("tcp://") {
+        let port = proxy.listener().get_int(NngOption::TCP_BOUND_PORT)?;
+        format!("tcp://127.0.0.1:{}", port)
+    } else {
+        proxy_url.to_owned()
+    };
+
+    let mut client = open()?;
+    client.dial(&proxy_url)?;
+    assert_eq!(next_event(&mut events), ProxyEvent::Connected { id: 1 });
+
+    // Both directions
+    client.send(&[1, 2, 3])?;
+    assert_eq!(target.recvmsg()?.body(), &[1, 2, 3]);
+    target.send(&[4, 5])?;
+    assert_eq!(client.recvmsg()?.body(), &[4, 5]);
+    let stats = proxy.stats();
+    assert_eq!(stats.accepted, 1);
+    assert_eq!(stats.active, 1);
+    assert!(stats.bytes_to_target >= 3);
+    assert!(stats.bytes_from_target >= 2);
+
+    // Closing one side closes the connection
+    drop(client);
+    match next_event(&mut events) {
+        ProxyEvent::Closed {
+            id,
+            to_target,
+            from_target,
+        } => {
+            assert_eq!(id, 1);
+            assert_eq!(to_target, stats.bytes_to_target);
+            assert_eq!(from_target, stats.bytes_from_target);
+        }
+        other => panic!("Unexpected {:?}", other),
+    }
+    assert_eq!(proxy.stats().active, 0);
+    proxy.close();
+    Ok(())
+}
+
+#[test]
+fn ipc_to_tcp() -> runng::Result<()> {
+    init_logging();
+    forward(&get_ipc_url(), "tcp://127.0.0.1:0")
+}
+
+#[test]
+fn tcp_to_ipc() -> runng::Result<()> {
+    init_logging();
+    forward("tcp://127.0.0.1:0", &get_ipc_url())
+}
+
+#[test]
+fn connect_failed() -> runng::Result<()> {
+    init_logging();
+    let proxy_url = get_ipc_url();
+    // Nothing listening on target
+    let mut proxy = Proxy::start(&proxy_url, &get_ipc_url(), ProxyConfig::default())?;
+    let mut events = proxy.take_events().unwrap();
+    let mut client = open()?;
+    client.dial(&proxy_url)?;
+    match next_event(&mut events) {
+        ProxyEvent::ConnectFailed { id, .. } => assert_eq!(id, 1),
+        other => panic!("Unexpected {:?}", other),
+    }
+    assert_eq!(proxy.stats().failed, 1);
+    Ok(())
+}
+
+#[test]
+fn connect_timeout() -> runng::Result<()> {
+    init_logging();
+    let proxy_url = get_ipc_url();
+    // TEST-NET-1 address that nothing answers
+    let config = ProxyConfig::default().connect_timeout(100);
+    let mut proxy = Proxy::start(&proxy_url, "tcp://192.0.2.1:5555", config)?;
+    let mut events = proxy.take_events().unwrap();
+    let mut client = open()?;
+    client.dial(&proxy_url)?;
+    match next_event(&mut events) {
+        ProxyEvent::ConnectFailed { id, error } => {
+            assert_eq!(id, 1);
+            // Fails fast where there's no route at all
+            assert!(
+                error.errno() == Some(NngErrno::ETIMEDOUT)
+                    || error.errno() == Some(NngErrno::EUNREACHABLE)
+            );
+        }
+        other => panic!("Unexpected {:?}", other),
+    }
+    proxy.close();
+    Ok(())
+}
+
+#[test]
+fn close() -> runng::Result<()> {
+    init_logging();
+    let mut target = open()?;
+    let target_url = listen(&mut target, &get_ipc_url())?;
+    let proxy_url = get_ipc_url();
+    let mut proxy = Proxy::start(&proxy_url, &target_url, ProxyConfig::default())?;
+    let mut events = proxy.take_events().unwrap();
+    let mut client = open()?;
+    client.dial(&proxy_url)?;
+    assert_eq!(next_event(&mut events), ProxyEvent::Connected { id: 1 });
+
+    // Closing proxy ends open connections
+    proxy.close();
+    match next_event(&mut events) {
+        ProxyEvent::Closed { id, .. } => assert_eq!(id, 1),
+        other => panic!("Unexpected {:?}", other),
+    }
+    assert!(block_on(events.next()).is_none());
+    Ok(())
+}
+
+struct FaultSetup {
+    proxy: Proxy,
+    faults: Faults,
+    events: mpsc::UnboundedReceiver<ProxyEvent>,
+    client: protocol::Pair1,
+    target: protocol::Pair1,
+}
+
+// Client connected to target through a proxy injecting `faults`
+fn fault_setup(faults: Faults) -> runng::Result<FaultSetup> {
+    let mut target = open()?;
+    let target_url = listen(&mut target, &get_ipc_url())?;
+    let proxy_url = get_ipc_url();
+    let config = ProxyConfig::default().faults(faults.clone());
+    let mut proxy = Proxy::start(&proxy_url, &target_url, config)?;
+    let events = proxy.take_events().unwrap();
+    let mut client = open()?;
+    client
+        .set_duration(NngOption::RECONNMINT, DURATION_FAST)?
+        .set_duration(NngOption::RECONNMAXT, DURATION_FAST)?;
+    client.dial_flags(&proxy_url, SocketFlags::NONBLOCK)?;
+    Ok(FaultSetup {
+        proxy,
+        faults,
+        events,
+        client,
+        target,
+    })
+}
+
+// Connection `id` closes and the client connects again
+fn expect_reconnect(events: &mut mpsc::UnboundedReceiver<ProxyEvent>, id: ConnectionId) {
+    // Reconnecting can race with the proxy reporting the close
+    let mut closed = false;
+    let mut connected = false;
+    while !(closed && connected) {
+        match next_event(events) {
+            ProxyEvent::Closed { id: closed_id, .. } if closed_id == id => closed = true,
+            ProxyEvent::Connected { id: connected_id } if connected_id == id + 1 => {
+                connected = true
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn delay() -> runng::Result<()> {
+    init_logging();
+    let mut setup = fault_setup(Faults::new())?;
+    assert_eq!(
+        next_event(&mut setup.events),
+        ProxyEvent::Connected { id: 1 }
+    );
+    setup.faults.set_delay(DURATION_LONG);
+
+    let start = std::time::Instant::now();
+    setup.client.send(&[1])?;
+    setup.target.recvmsg()?;
+    assert!(start.elapsed() >= DURATION_LONG);
+
+    // Closing doesn't wait out a pending delay
+    setup.faults.set_delay(std::time::Duration::from_secs(60));
+    setup.client.send(&[2])?;
+    std::thread::sleep(DURATION_BRIEF);
+    let start = std::time::Instant::now();
+    setup.proxy.close();
+    assert!(start.elapsed() < DURATION_TEST);
+    Ok(())
+}
+
+#[test]
+fn bandwidth() -> runng::Result<()> {
+    init_logging();
+    let mut setup = fault_setup(Faults::new())?;
+    assert_eq!(
+        next_event(&mut setup.events),
+        ProxyEvent::Connected { id: 1 }
+    );
+    setup.faults.set_bandwidth(Some(20_000));
+
+    // At least 0.2 seconds at 20KB/s
+    let start = std::time::Instant::now();
+    setup.client.send(&[0; 4000])?;
+    assert_eq!(setup.target.recvmsg()?.len(), 4000);
+    assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+    setup.proxy.close();
+    Ok(())
+}
+
+#[test]
+fn stall() -> runng::Result<()> {
+    init_logging();
+    let mut setup = fault_setup(Faults::new())?;
+    assert_eq!(
+        next_event(&mut setup.events),
+        ProxyEvent::Connected { id: 1 }
+    );
+    setup
+        .target
+        .set_duration(NngOption::RECVTIMEO, DURATION_LONG)?;
+
+    setup.faults.stall();
+    setup.client.send(&[1])?;
+    match setup.target.recvmsg() {
+        Err(Error::Errno(NngErrno::ETIMEDOUT)) => {}
+        other => panic!("Unexpected {:?}", other),
+    }
+    setup.faults.resume();
+    assert_eq!(setup.target.recvmsg()?.body(), &[1]);
+
+    // Closing proxy doesn't wait for stalled connections
+    setup.faults.stall();
+    setup.client.send(&[2])?;
+    sleep_brief();
+    setup.proxy.close();
+    Ok(())
+}
+
+#[test]
+fn disconnect() -> runng::Result<()> {
+    init_logging();
+    let mut setup = fault_setup(Faults::with_seed(1))?;
+    assert_eq!(
+        next_event(&mut setup.events),
+        ProxyEvent::Connected { id: 1 }
+    );
+
+    // Client reconnects after an injected disconnect
+    setup.faults.set_disconnect_probability(1.0);
+    setup.client.send(&[1])?;
+    expect_reconnect(&mut setup.events, 1);
+    setup.faults.set_disconnect_probability(0.0);
+
+    // And after connections are closed through the proxy
+    setup.proxy.disconnect_all();
+    expect_reconnect(&mut setup.events, 2);
+    setup.client.send(&[2])?;
+    assert_eq!(setup.target.recvmsg()?.body(), &[2]);
+    setup.proxy.close();
+    Ok(())
+}
+
+#[test]
+fn refuse() -> runng::Result<()> {
+    init_logging();
+    let faults = Faults::new();
+    faults.set_refuse(true);
+    let start = std::time::Instant::now();
+    let mut setup = fault_setup(faults)?;
+
+    // Client retries no faster than RECONNMINT
+    for id in 1..=3 {
+        assert_eq!(next_event(&mut setup.events), ProxyEvent::Refused { id });
+    }
+    assert!(start.elapsed() >= DURATION_FAST * 2);
+    assert_eq!(setup.proxy.stats().refused, 3);
+
+    setup.faults.clear();
+    loop {
+        match next_event(&mut setup.events) {
+            ProxyEvent::Refused { .. } => continue,
+            ProxyEvent::Connected { .. } => break,
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+    setup.client.send(&[1])?;
+    assert_eq!(setup.target.recvmsg()?.body(), &[1]);
+    setup.proxy.close();
+    Ok(())
+}
@@ -226,3 +226,46 @@ fn contexts() -> runng::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn topic_router() -> runng::Result<()> {
+    let url = get_url();
+
+    let mut publisher = protocol::Pub0::open()?;
+    publisher.listen(&url)?;
+    let mut subscriber = protocol::Sub0::open()?;
+    subscriber.dial(&url)?;
+
+    let router = subscriber.create_topic_router(8)?;
+    let prices = router.topic(b"prices.")?;
+    let trades = router.topic(b"trades.")?;
+    // Subscription stays while other streams for the topic remain
+    drop(router.topic(b"trades.")?);
+    // Dropping the last stream for a topic unsubscribes from it
+    drop(router.topic(b"orders.")?);
+    sleep_brief();
+
+    for topic in &["prices.1", "trades.1", "orders.1", "prices.2"] {
+        publisher.send(topic.as_bytes())?;
+        sleep_fast();
+    }
+    let orders = router.topic(b"orders.")?;
+
+    let prices: Vec<_> = block_on(prices.take(2).collect());
+    for (msg, expected) in prices.into_iter().zip(&["prices.1", "prices.2"]) {
+        assert_eq!(msg?.body(), expected.as_bytes());
+    }
+
+    // Streams end once the socket is closed
+    subscriber.socket().close()?;
+    let trades: Vec<_> = block_on(trades.collect());
+    assert_eq!(trades.len(), 2);
+    assert_eq!(trades[0].as_ref().unwrap().body(), b"trades.1");
+    assert!(trades[1].as_ref().unwrap_err().is_closed());
+    // Nothing was delivered for the topic while unsubscribed
+    let orders: Vec<_> = block_on(orders.collect());
+    assert_eq!(orders.len(), 1);
+    assert!(orders[0].as_ref().unwrap_err().is_closed());
+    assert!(router.topic(b"prices.").unwrap_err().is_closed());
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use crate::common::*;
+use futures::future;
+use runng::{
+    asyncio::{AsyncRequest, AsyncSocket},
+    msg::{NngDecode, NngEncode},
+    protocol::*,
+    rpc::*,
+    *,
+};
+use std::thread;
+
+#[derive(Debug, PartialEq, NngEncode, NngDecode)]
+struct Add {
+    a: i32,
+    b: i32,
+}
+
+fn calculator() -> Router {
+    Router::new()
+        .method("add", |req: Add| future::ok(req.a + req.b))
+        .method("echo", |req: String| future::ok(req))
+        .method("divide", |req: Add| {
+            if req.b == 0 {
+                future::err(RpcError::application(22, "Divide by zero").into())
+            } else {
+                future::ok(req.a / req.b)
+            }
+        })
+}
+
+fn start(url: &str, workers: usize) -> runng::Result<(Rep0, Req0)> {
+    let mut rep = Rep0::open()?;
+    rep.listen(url)?;
+    let mut req = Req0::open()?;
+    req.dial(url)?;
+    let server = RpcServer::new(&rep, calculator());
+    let run = server.run(workers)?;
+    thread::spawn(move || block_on(run));
+    Ok((rep, req))
+}
+
+#[test]
+fn method_ids() {
+    // FNV-1a test vectors
+    assert_eq!(method_id(""), 0x811c_9dc5);
+    assert_eq!(method_id("a"), 0xe40c_292c);
+    assert_ne!(method_id("add"), method_id("echo"));
+}
+
+#[test]
+fn calls() -> runng::Result<()> {
+    let url = get_url();
+    let (_rep, req) = start(&url, 2)?;
+    let client = RpcClient::new(&req);
+
+    let sum: i32 = block_on(client.call("add", &Add { a: 1, b: 2 }))?;
+    assert_eq!(sum, 3);
+    let echo: String = block_on(client.call("echo", "hello"))?;
+    assert_eq!(echo, "hello");
+
+    // Concurrent calls
+    let calls: Vec<_> = (0..8)
+        .map(|i| client.call::<_, i32>("add", &Add { a: i, b: i }))
+        .collect();
+    let results = block_on(future::join_all(calls));
+    for (i, res) in results.into_iter().enumerate() {
+        assert_eq!(res?, 2 * i as i32);
+    }
+    Ok(())
+}
+
+#[test]
+fn errors() -> runng::Result<()> {
+    let url = get_url();
+    let (_rep, req) = start(&url, 1)?;
+    let client = RpcClient::new(&req);
+
+    // Application error
+    let res: runng::Result<i32> = block_on(client.call("divide", &Add { a: 1, b: 0 }));
+    assert_eq!(
+        res,
+        Err(Error::Rpc(RpcError::application(22, "Divide by zero")))
+    );
+
+    // Unknown method
+    let res: runng::Result<i32> = block_on(client.call("subtract", &Add { a: 1, b: 0 }));
+    match res {
+        Err(Error::Rpc(err)) => assert_eq!(err.status, RpcStatus::UnknownMethod),
+        other => panic!("Unexpected {:?}", other),
+    }
+
+    // Request that doesn't decode
+    let res: runng::Result<i32> = block_on(client.call("add", &1u8));
+    match res {
+        Err(Error::Rpc(err)) => assert_eq!(err.status, RpcStatus::BadRequest),
+        other => panic!("Unexpected {:?}", other),
+    }
+
+    // Envelope that doesn't decode
+    let mut ctx = req.create_async()?;
+    let mut request = NngMsg::new()?;
+    request.append_u32(method_id("add"))?;
+    request.append_u32(99)?;
+    let mut reply = block_on(ctx.send(request))??;
+    let envelope = Envelope::trim(&mut reply)?;
+    assert_eq!(envelope.method, method_id("add"));
+    assert_eq!(envelope.status, RpcStatus::BadRequest);
+
+    // Server still works
+    let sum: i32 = block_on(client.call("add", &Add { a: 2, b: 2 }))?;
+    assert_eq!(sum, 4);
+    Ok(())
+}
+
+#[service]
+trait Calculator {
+    async fn add(&self, a: i32, b: i32) -> i32;
+    async fn divide(&self, a: i32, b: i32) -> i32;
+    async fn greet(&self, name: String) -> String;
+    async fn reset(&self);
+}
+
+struct LocalCalculator;
+
+impl Calculator for LocalCalculator {
+    fn add(&self, a: i32, b: i32) -> RpcFuture<i32> {
+        Box::pin(future::ok(a + b))
+    }
+    fn divide(&self, a: i32, b: i32) -> RpcFuture<i32> {
+        if b == 0 {
+            Box::pin(future::err(
+                RpcError::application(22, "Divide by zero").into(),
+            ))
+        } else {
+            Box::pin(future::ok(a / b))
+        }
+    }
+    fn greet(&self, name: String) -> RpcFuture<String> {
+        Box::pin(future::ok(format!("Hello, {}", name)))
+    }
+    fn reset(&self) -> RpcFuture<()> {
+        Box::pin(future::ok(()))
+    }
+}
+
+#[test]
+fn service() -> runng::Result<()> {
+    assert_eq!(CalculatorClient::ADD, "Calculator.add");
+    assert_eq!(CalculatorClient::GREET, "Calculator.greet");
+
+    let url = get_url();
+    let mut rep = Rep0::open()?;
+    rep.listen(&url)?;
+    let mut req = Req0::open()?;
+    req.dial(&url)?;
+    let server = RpcServer::new(&rep, CalculatorServer::new(LocalCalculator));
+    let run = server.run(2)?;
+    thread::spawn(move || block_on(run));
+
+    let client = CalculatorClient::new(&req);
+    assert_eq!(block_on(client.add(1, 2))?, 3);
+    assert_eq!(block_on(client.greet("runng".to_owned()))?, "Hello, runng");
+    block_on(client.reset())?;
+    assert_eq!(
+        block_on(client.divide(1, 0)),
+        Err(Error::Rpc(RpcError::application(22, "Divide by zero")))
+    );
+
+    // Generated client and `Router` use the same envelope
+    let client = RpcClient::new(&req);
+    let sum: i32 = block_on(client.call("Calculator.add", &(2i32, 3i32)))?;
+    assert_eq!(sum, 5);
+    Ok(())
+}
+
+struct Colliding;
+
+impl Service for Colliding {
+    fn methods(&self) -> Vec<&str> {
+        // FNV-1a collision
+        vec!["costarring", "liquid"]
+    }
+    fn call(&self, method: MethodId, _request: NngMsg) -> RpcFuture<NngMsg> {
+        unknown_method(method)
+    }
+}
+
+#[test]
+#[should_panic(expected = "collides")]
+fn colliding_methods() {
+    let rep = Rep0::open().unwrap();
+    RpcServer::new(&rep, Colliding);
+}
@@ -0,0 +1,101 @@
+use crate::common::*;
+use runng::{asyncio::*, factory::latest::ProtocolFactory, shutdown::*, *};
+
+#[test]
+fn drain() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut pull = factory.puller_open()?;
+    pull.listen(&url)?;
+    let mut push = factory.pusher_open()?;
+    push.dial(&url)?;
+    let mut push_ctx = push.create_async()?;
+
+    let sent = push_ctx.send(NngMsg::new()?);
+    push.sendmsg(NngMsg::new()?)?;
+    let report = ShutdownCoordinator::new()
+        .socket(&push)
+        .socket(&pull)
+        .shutdown(DURATION_TEST);
+    assert!(report.is_clean(), "{}", report);
+    assert_eq!(report.sockets.len(), 2);
+    block_on(sent)?;
+
+    // No new work accepted
+    assert!(block_on(push_ctx.send(NngMsg::new()?))
+        .unwrap_err()
+        .is_closed());
+    assert!(push.sendmsg(NngMsg::new()?).unwrap_err().is_closed());
+    assert!(push.socket().is_closed() && pull.socket().is_closed());
+    Ok(())
+}
+
+#[test]
+fn publisher_without_subscribers() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut publisher = factory.publisher_open()?;
+    publisher.listen(&url)?;
+    // Dropped by nng, so never counted as sent
+    for _ in 0..4 {
+        publisher.sendmsg(NngMsg::new()?)?;
+    }
+
+    let report = ShutdownCoordinator::new()
+        .socket(&publisher)
+        .shutdown(DURATION_TEST);
+    assert!(report.is_clean(), "{}", report);
+    assert!(report.elapsed < DURATION_TEST);
+    Ok(())
+}
+
+#[test]
+fn abandoned() -> runng::Result<()> {
+    let factory = ProtocolFactory::default();
+    // No peer, so send never completes
+    let push = factory.pusher_open()?;
+    let mut push_ctx = push.create_async()?;
+    let sent = push_ctx.send(NngMsg::new()?);
+
+    let report = ShutdownCoordinator::new()
+        .socket(&push)
+        .shutdown(DURATION_FAST);
+    assert!(!report.is_clean());
+    assert_eq!(report.abandoned_sends(), 1);
+    assert!(report.elapsed >= DURATION_FAST);
+    assert!(block_on(sent).unwrap_err().is_closed());
+    Ok(())
+}
+
+#[test]
+fn contexts() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut rep = factory.replier_open()?;
+    rep.listen(&url)?;
+    let mut req = factory.requester_open()?;
+    req.dial(&url)?;
+    let mut rep_ctx = rep.create_async()?;
+    let _other_rep_ctx = rep.create_async()?;
+    let mut req_ctx = req.create_async()?;
+
+    let reply = req_ctx.send(NngMsg::new()?);
+    block_on(rep_ctx.receive())?;
+    block_on(rep_ctx.reply(NngMsg::new()?))??;
+    block_on(reply)??;
+
+    let report = ShutdownCoordinator::new()
+        .socket(&req)
+        .socket(&rep)
+        .shutdown(DURATION_TEST);
+    assert!(report.is_clean(), "{}", report);
+    let contexts: Vec<_> = report
+        .sockets
+        .iter()
+        .map(|socket| socket.contexts)
+        .collect();
+    assert_eq!(contexts, vec![1, 2]);
+    // Contexts were closed
+    assert!(block_on(rep_ctx.receive()).unwrap_err().is_closed());
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::common::*;
+use runng::{asyncio::*, factory::latest::ProtocolFactory, *};
+use std::time::{Duration, Instant};
+
+#[test]
+fn close() -> runng::Result<()> {
+    let factory = ProtocolFactory::default();
+    let pull = factory.puller_open()?;
+    let socket = pull.socket().clone();
+    let mut pull_ctx = pull.create_async()?;
+    let receive = pull_ctx.receive();
+
+    socket.close()?;
+    assert!(socket.is_closed());
+    assert!(pull.socket().is_closed());
+    // Idempotent through clones
+    pull.socket().close()?;
+    socket.close()?;
+
+    assert!(block_on(receive).unwrap_err().is_closed());
+    assert!(pull.recvmsg().unwrap_err().is_closed());
+    Ok(())
+}
+
+#[test]
+fn shutdown() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut pull = factory.puller_open()?;
+    pull.listen(&url)?;
+    let mut push = factory.pusher_open()?;
+    push.dial(&url)?;
+    let mut push_ctx = push.create_async()?;
+
+    block_on(push_ctx.send(NngMsg::new()?))?;
+    push.socket().shutdown(Duration::from_secs(1))?;
+    assert!(push.socket().is_closed());
+    pull.recvmsg()?;
+    Ok(())
+}
+
+#[test]
+fn shutdown_timeout() -> runng::Result<()> {
+    let factory = ProtocolFactory::default();
+    // No peer, so send never completes
+    let push = factory.pusher_open()?;
+    let mut push_ctx = push.create_async()?;
+    let send = push_ctx.send(NngMsg::new()?);
+
+    let start = Instant::now();
+    let err = push.socket().shutdown(DURATION_FAST).unwrap_err();
+    assert!(start.elapsed() >= DURATION_FAST);
+    assert_eq!(err.errno(), Some(NngErrno::ETIMEDOUT));
+    assert!(block_on(send).unwrap_err().is_closed());
+    Ok(())
+}
+
+#[test]
+fn dialer_lifecycle() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut rep = factory.replier_open()?;
+    rep.listen(&url)?;
+    let req = factory.requester_open()?;
+
+    let mut builder = req.dialer_create(&url)?;
+    builder.set_ms(NngOption::RECONNMINT, 50)?;
+    let id = builder.id();
+    let dialers: Vec<_> = req.socket().dialers().iter().map(|d| d.id()).collect();
+    assert_eq!(dialers, vec![id]);
+
+    let dialer = builder.start()?;
+    assert_eq!(dialer.id(), id);
+    assert_eq!(dialer.get_ms(NngOption::RECONNMINT)?, 50);
+    dialer.close()?;
+    assert!(req.socket().dialers().is_empty());
+
+    // Dropping closes too
+    let builder = req.dialer_create(&url)?;
+    drop(builder);
+    assert!(req.socket().dialers().is_empty());
+    Ok(())
+}
+
+#[test]
+fn listener_lifecycle() -> runng::Result<()> {
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut rep = factory.replier_open()?;
+    rep.listen(&url)?;
+    let other = rep.listener_create(&get_url())?.start()?;
+    assert_eq!(rep.socket().listeners().len(), 2);
+
+    other.close()?;
+    assert_eq!(rep.socket().listeners().len(), 1);
+    // Same url can't be started twice, and the failed listener is closed
+    assert!(rep.listener_create(&url)?.start().is_err());
+    assert_eq!(rep.socket().listeners().len(), 1);
+
+    // Closing the socket closes its listeners
+    let listener = rep.listener_create(&get_url())?.start()?;
+    rep.socket().close()?;
+    assert!(rep.socket().listeners().is_empty());
+    listener.close()?;
+    Ok(())
+}
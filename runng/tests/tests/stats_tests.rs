@@ -3,6 +3,7 @@
 use crate::common::*;
 use log::debug;
 use runng::{factory::compat::ProtocolFactory, socket::*, stats::*};
+use std::time::Duration;
 
 fn init_stats() -> runng::Result<(protocol::pair0::Pair0, protocol::pair0::Pair0)> {
     init_logging();
@@ -27,3 +28,236 @@ fn stats_example() -> runng::Result<()> {
     }
     Ok(())
 }
+
+#[test]
+fn tree() -> runng::Result<()> {
+    let (p0, p1) = init_stats()?;
+    sleep_brief();
+    p1.sendmsg(NngMsg::new()?)?;
+    p0.recvmsg()?;
+    let tree = NngStatRoot::new()?.to_tree()?;
+
+    let id = unsafe { UnsafeSocket::new(p1.nng_socket()).id() };
+    let socket = tree.get(&format!("socket{}", id)).unwrap();
+    assert_eq!(socket.kind, StatKind::Scope);
+    assert_eq!(
+        socket.get("id").unwrap().value,
+        StatValue::Number(id as u64)
+    );
+    let tx_msgs = tree.get(&format!("socket/{}/tx_msgs", id)).unwrap();
+    assert_eq!(tx_msgs.kind, StatKind::Counter);
+    assert_eq!(tx_msgs.unit, StatUnit::Messages);
+    assert!(tx_msgs.value.as_u64().unwrap() >= 1);
+    assert!(tree.get("socket/not_a_stat").is_none());
+
+    // Owned tree outlives the snapshot
+    let child = NngStatRoot::new()?.child().unwrap().to_tree()?;
+    debug!("{:?}", child);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn tree_serde() -> runng::Result<()> {
+    let (_p0, _p1) = init_stats()?;
+    sleep_brief();
+    let tree = NngStatRoot::new()?.to_tree()?;
+    let json = serde_json::to_string(&tree).unwrap();
+    let copy: StatNode = serde_json::from_str(&json).unwrap();
+    assert_eq!(copy, tree);
+    Ok(())
+}
+
+fn stat(name: &str, kind: StatKind, value: StatValue, children: Vec<StatNode>) -> StatNode {
+    StatNode {
+        name: name.to_owned(),
+        desc: format!("{} desc", name),
+        kind,
+        unit: StatUnit::None,
+        value,
+        timestamp: 0,
+        children,
+    }
+}
+
+#[test]
+fn export_render() -> runng::Result<()> {
+    let dialer = stat(
+        "dialer2",
+        StatKind::Scope,
+        StatValue::None,
+        vec![
+            stat("id", StatKind::Id, StatValue::Number(2), vec![]),
+            stat("socket", StatKind::Id, StatValue::Number(1), vec![]),
+            stat(
+                "url",
+                StatKind::String,
+                StatValue::String("tcp://\"quoted\"".to_owned()),
+                vec![],
+            ),
+            stat("connect", StatKind::Counter, StatValue::Number(3), vec![]),
+        ],
+    );
+    let socket = stat(
+        "socket1",
+        StatKind::Scope,
+        StatValue::None,
+        vec![
+            stat("id", StatKind::Id, StatValue::Number(1), vec![]),
+            stat("pipes", StatKind::Level, StatValue::Number(4), vec![]),
+            stat("raw", StatKind::Boolean, StatValue::Bool(true), vec![]),
+            // Keeps the socket's id
+            dialer,
+        ],
+    );
+    let root = stat("", StatKind::Scope, StatValue::None, vec![socket]);
+    let text = export::render(&root);
+    let expected = r#"# HELP nng_socket_dialer_connect_total connect desc
+# TYPE nng_socket_dialer_connect_total counter
+nng_socket_dialer_connect_total{dialer_id="2",socket="1",socket_id="1",url="tcp://\"quoted\""} 3
+# HELP nng_socket_pipes pipes desc
+# TYPE nng_socket_pipes gauge
+nng_socket_pipes{socket_id="1"} 4
+# HELP nng_socket_raw raw desc
+# TYPE nng_socket_raw gauge
+nng_socket_raw{socket_id="1"} 1
+"#;
+    assert_eq!(text, expected);
+    Ok(())
+}
+
+#[test]
+fn export_server() -> runng::Result<()> {
+    use runng::options::{GetOpts, NngOption};
+    use std::io::{Read, Write};
+
+    let (_p0, p1) = init_stats()?;
+    sleep_brief();
+    let server = export::MetricsServer::start("tcp://127.0.0.1:0")?;
+    let port = server.listener().get_int(NngOption::TCP_BOUND_PORT)?;
+    let get = |path: &str| {
+        let mut client = std::net::TcpStream::connect(("127.0.0.1", port as u16)).unwrap();
+        write!(client, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    };
+
+    // A client that never sends a request doesn't hold up others
+    let _idle = std::net::TcpStream::connect(("127.0.0.1", port as u16)).unwrap();
+    let id = unsafe { UnsafeSocket::new(p1.nng_socket()).id() };
+    let response = get("/metrics");
+    assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(response.contains("# TYPE nng_socket_tx_msgs_total counter"));
+    let socket_id = format!("socket_id=\"{}\"", id);
+    assert!(response
+        .lines()
+        .any(|line| line.starts_with("nng_socket_tx_msgs_total{") && line.contains(&socket_id)));
+    assert!(get("/other").starts_with("HTTP/1.0 404"));
+    server.close();
+    Ok(())
+}
+
+fn counter_tree(timestamp: u64, tx_msgs: u64, rx_msgs: Option<u64>) -> StatNode {
+    let mut counters = vec![stat(
+        "tx_msgs",
+        StatKind::Counter,
+        StatValue::Number(tx_msgs),
+        vec![],
+    )];
+    if let Some(rx_msgs) = rx_msgs {
+        counters.push(stat(
+            "rx_msgs",
+            StatKind::Counter,
+            StatValue::Number(rx_msgs),
+            vec![],
+        ));
+    }
+    let mut root = stat(
+        "",
+        StatKind::Scope,
+        StatValue::None,
+        vec![stat("socket1", StatKind::Scope, StatValue::None, counters)],
+    );
+    root.timestamp = timestamp;
+    for child in root.children[0].children.iter_mut() {
+        child.timestamp = timestamp;
+    }
+    root
+}
+
+#[test]
+fn delta() -> runng::Result<()> {
+    let before = counter_tree(1000, 10, None);
+    let after = counter_tree(3000, 30, Some(4));
+    let delta = StatsDelta::between(&before, &after);
+    assert_eq!(delta.elapsed, Duration::from_secs(2));
+    let tx = delta.get("socket/1/tx_msgs").unwrap();
+    assert_eq!(tx.path, "socket1/tx_msgs");
+    assert_eq!((tx.value, tx.delta), (30, 20));
+    assert_eq!(tx.rate, 10.0);
+    // New counter counts from zero
+    let rx = delta.get("socket1/rx_msgs").unwrap();
+    assert_eq!((rx.delta, rx.rate), (4, 2.0));
+    assert_eq!(delta.scope("socket1").count(), 2);
+    assert_eq!(delta.scope("socket2").count(), 0);
+
+    // Counter that went down was reset
+    let delta = StatsDelta::between(&after, &counter_tree(4000, 5, Some(4)));
+    assert_eq!(delta.get("socket1/tx_msgs").unwrap().delta, 5);
+    assert_eq!(delta.get("socket1/rx_msgs").unwrap().delta, 0);
+    Ok(())
+}
+
+#[test]
+fn sampler() -> runng::Result<()> {
+    let (p0, p1) = init_stats()?;
+    sleep_brief();
+    let id = unsafe { UnsafeSocket::new(p1.nng_socket()).id() };
+    let mut sampler = StatsSampler::new(DURATION_LONG)?;
+    for _ in 0..3 {
+        p1.sendmsg(NngMsg::new()?)?;
+        p0.recvmsg()?;
+    }
+    let delta = block_on(sampler.next()).unwrap()?;
+    let tx = delta.get(&format!("socket/{}/tx_msgs", id)).unwrap();
+    assert_eq!(tx.delta, 3);
+    assert!(tx.rate > 0.0);
+    assert!(delta.elapsed > Duration::from_millis(0));
+    Ok(())
+}
+
+#[test]
+fn socket_stats() -> runng::Result<()> {
+    init_logging();
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let p0 = factory.pair_open()?;
+    let listener = p0.listener_create(&url)?.start()?;
+    let p1 = factory.pair_open()?;
+    let dialer = p1.dialer_create(&url)?.start()?;
+    sleep_brief();
+    p1.sendmsg(NngMsg::with_len(16)?)?;
+    p0.recvmsg()?;
+
+    let stats = p1.stats()?;
+    let id = unsafe { UnsafeSocket::new(p1.nng_socket()).id() };
+    assert_eq!(stats.id(), Some(id as u64));
+    assert_eq!(stats.tx_msgs(), Some(1));
+    assert!(stats.tx_bytes().unwrap() >= 16);
+    assert_eq!(stats.listeners.len(), 0);
+    assert_eq!(stats.dialers.len(), 1);
+    assert_eq!(stats.dialers[0].id(), Some(dialer.id() as u64));
+
+    let dialer_stats = dialer.stats()?;
+    assert_eq!(dialer_stats, stats.dialers[0]);
+    assert_eq!(dialer_stats.socket(), Some(id as u64));
+    assert_eq!(dialer_stats.url(), Some(url.as_str()));
+    assert_eq!(dialer_stats.connect(), Some(1));
+
+    let listener_stats = listener.stats()?;
+    assert_eq!(listener_stats.accept(), Some(1));
+    assert_eq!(p0.stats()?.listeners, vec![listener_stats]);
+    assert_eq!(p0.stats()?.rx_msgs(), Some(1));
+    Ok(())
+}
@@ -1,9 +1,14 @@
 use crate::common::*;
+use futures::{
+    task::{noop_waker, Context},
+    Stream,
+};
 use rand::Rng;
 use runng::{
     asyncio::*,
     options::{GetOpts, NngOption},
 };
+use std::pin::Pin;
 
 fn create_listener_dialer_tcp() -> runng::Result<(StreamListener, StreamDialer)> {
     let url = "tcp://localhost:0";
@@ -57,3 +62,112 @@ fn iov() -> runng::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn buffers() -> runng::Result<()> {
+    init_logging();
+
+    let (mut listener, mut dialer) = create_listener_dialer_tcp()?;
+    let mut listener_aio = SimpleAioWorkQueue::new()?;
+    let mut dialer_aio = SimpleAioWorkQueue::new()?;
+    let accept_future = listener.accept(&mut listener_aio);
+    let dial_future = dialer.dial(&mut dialer_aio);
+    let listen_stream = block_on(accept_future)??;
+    let dial_stream = block_on(dial_future)??;
+
+    let mut data = vec![0u8; 1024];
+    rand::thread_rng().fill(data.as_mut_slice());
+    let (data, sent) = block_on(dial_stream.send_buffers(&mut dialer_aio, data))?;
+    let sent = sent?;
+
+    // Receive into message body until everything arrives
+    let mut msg = NngMsg::with_len(sent)?;
+    let mut received = 0;
+    while received < sent {
+        let (buffer, count) = block_on(listen_stream.recv_buffers(&mut listener_aio, msg))?;
+        msg = buffer;
+        let count = count?;
+        assert_eq!(&msg.body()[..count], &data[received..received + count]);
+        received += count;
+        msg.trim(count)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn pooled_queue() -> runng::Result<()> {
+    init_logging();
+
+    assert_eq!(
+        PooledAioWorkQueue::new(0).unwrap_err().errno(),
+        Some(NngErrno::EINVAL)
+    );
+
+    // Everything runs on one queue
+    let (mut listener, mut dialer) = create_listener_dialer_tcp()?;
+    let mut queue = PooledAioWorkQueue::new(2)?;
+    let accept_futures = vec![listener.accept(&mut queue), listener.accept(&mut queue)];
+    let dial_futures = vec![dialer.dial(&mut queue), dialer.dial(&mut queue)];
+    let mut dial_streams = Vec::new();
+    for dial_future in dial_futures {
+        dial_streams.push(block_on(dial_future)??);
+    }
+    let mut listen_streams = Vec::new();
+    for accept_future in accept_futures {
+        listen_streams.push(block_on(accept_future)??);
+    }
+
+    // Pending receives don't block sends
+    let recv_futures: Vec<_> = listen_streams
+        .iter()
+        .map(|stream| stream.recv_buffers(&mut queue, vec![0u8; 4]))
+        .collect();
+    for (i, stream) in dial_streams.iter().enumerate() {
+        let (_, sent) = block_on(stream.send_buffers(&mut queue, vec![i as u8; 4]))?;
+        assert_eq!(sent?, 4);
+    }
+    let mut received = Vec::new();
+    for recv_future in recv_futures {
+        let (buffer, count) = block_on(recv_future)?;
+        assert_eq!(count?, 4);
+        received.push(buffer[0]);
+    }
+    received.sort();
+    assert_eq!(received, vec![0, 1]);
+    Ok(())
+}
+
+#[test]
+fn incoming() -> runng::Result<()> {
+    init_logging();
+
+    let (listener, mut dialer) = create_listener_dialer_tcp()?;
+    let mut dialer_aio = PooledAioWorkQueue::new(3)?;
+    assert_eq!(
+        listener.incoming(0).unwrap_err().errno(),
+        Some(NngErrno::EINVAL)
+    );
+    let mut incoming = listener.incoming(2)?;
+    let dial_futures: Vec<_> = (0..3).map(|_| dialer.dial(&mut dialer_aio)).collect();
+
+    let mut accepted = Vec::new();
+    for _ in 0..2 {
+        accepted.push(block_on(incoming.next()).unwrap()?);
+    }
+    // Waits while `limit` connections are alive
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    sleep_fast();
+    assert!(Pin::new(&mut incoming).poll_next(&mut cx).is_pending());
+    accepted.pop();
+    accepted.push(block_on(incoming.next()).unwrap()?);
+    for dial_future in dial_futures {
+        block_on(dial_future)??;
+    }
+
+    // Ends once listener is closed
+    listener.close();
+    assert!(block_on(incoming.next()).is_none());
+    assert!(block_on(incoming.next()).is_none());
+    Ok(())
+}
@@ -0,0 +1,122 @@
+#![cfg(feature = "tracing")]
+
+use crate::common::*;
+use runng::{asyncio::*, factory::latest::ProtocolFactory, *};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, Once,
+};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Metadata, Subscriber,
+};
+
+/// Name and `socket_id` of a span
+type SpanInfo = (&'static str, Option<i64>);
+
+static INIT: Once = Once::new();
+static SPANS: Mutex<Vec<SpanInfo>> = Mutex::new(Vec::new());
+
+/// Records spans created on any thread (nng callbacks included)
+struct Recorder {
+    next_id: AtomicU64,
+}
+
+struct SocketId(Option<i64>);
+
+impl Visit for SocketId {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == "socket_id" {
+            self.0 = Some(value);
+        }
+    }
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record_i64(field, value as i64)
+    }
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let mut socket_id = SocketId(None);
+        attrs.record(&mut socket_id);
+        SPANS
+            .lock()
+            .unwrap()
+            .push((attrs.metadata().name(), socket_id.0));
+        span::Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+    fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+    fn event(&self, _event: &Event<'_>) {}
+    fn enter(&self, _span: &span::Id) {}
+    fn exit(&self, _span: &span::Id) {}
+}
+
+fn spans_of(socket_id: i32) -> Vec<&'static str> {
+    INIT.call_once(|| {
+        let recorder = Recorder {
+            next_id: AtomicU64::new(1),
+        };
+        tracing::subscriber::set_global_default(recorder).unwrap();
+    });
+    SPANS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, id)| *id == Some(i64::from(socket_id)))
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+#[test]
+fn reqrep() -> runng::Result<()> {
+    // Install subscriber before creating any spans
+    spans_of(0);
+
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut rep = factory.replier_open()?;
+    rep.listen(&url)?;
+    let rep_id = unsafe { runng_sys::nng_socket_id(rep.nng_socket()) };
+    let mut rep_ctx = rep.create_async()?;
+    let mut req = factory.requester_open()?;
+    req.dial(&url)?;
+    let req_id = unsafe { runng_sys::nng_socket_id(req.nng_socket()) };
+    let mut req_ctx = req.create_async()?;
+
+    let reply = req_ctx.send(NngMsg::new()?);
+    block_on(rep_ctx.receive())?;
+    block_on(rep_ctx.reply(NngMsg::new()?))??;
+    block_on(reply)??;
+
+    assert_eq!(spans_of(req_id), vec!["nng.request"]);
+    assert_eq!(spans_of(rep_id), vec!["nng.reply"]);
+    Ok(())
+}
+
+#[test]
+fn pushpull() -> runng::Result<()> {
+    spans_of(0);
+
+    let url = get_url();
+    let factory = ProtocolFactory::default();
+    let mut pull = factory.puller_open()?;
+    pull.listen(&url)?;
+    let pull_id = unsafe { runng_sys::nng_socket_id(pull.nng_socket()) };
+    let mut pull_ctx = pull.create_async()?;
+    let mut push = factory.pusher_open()?;
+    push.dial(&url)?;
+    let push_id = unsafe { runng_sys::nng_socket_id(push.nng_socket()) };
+    let mut push_ctx = push.create_async()?;
+
+    block_on(push_ctx.send(NngMsg::new()?))?;
+    block_on(pull_ctx.receive())?;
+
+    assert_eq!(spans_of(push_id), vec!["nng.push"]);
+    assert_eq!(spans_of(pull_id), vec!["nng.pull"]);
+    Ok(())
+}
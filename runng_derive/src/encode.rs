@@ -0,0 +1,213 @@
+//! `NngEncode` and `NngDecode` derives.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Field, Fields, Ident, Index, Meta, NestedMeta, Variant};
+
+pub fn gen_encode_impl(mut ast: DeriveInput) -> TokenStream {
+    add_bounds(&mut ast, parse_quote!(::runng::msg::NngEncode));
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let (body, header) = match &ast.data {
+        Data::Struct(data) => {
+            let accessors = field_accessors(&data.fields, quote!(self.));
+            let body = encode_fields(&data.fields, &accessors, false);
+            let header = encode_fields(&data.fields, &accessors, true);
+            (body, header)
+        }
+        Data::Enum(data) => {
+            let variants: Vec<_> = data.variants.iter().collect();
+            let body = encode_variants(name, &variants, false);
+            let header = encode_variants(name, &variants, true);
+            (body, header)
+        }
+        Data::Union(_) => panic!("NngEncode doesn't support unions"),
+    };
+
+    let gen = quote! {
+        impl #impl_generics ::runng::msg::NngEncode for #name #ty_generics #where_clause {
+            fn encode(&self, msg: &mut ::runng::msg::NngMsg) -> ::runng::Result<()> {
+                #body
+                Ok(())
+            }
+            fn encode_header(&self, msg: &mut ::runng::msg::NngMsg) -> ::runng::Result<()> {
+                #header
+                Ok(())
+            }
+        }
+    };
+    gen.into()
+}
+
+pub fn gen_decode_impl(mut ast: DeriveInput) -> TokenStream {
+    add_bounds(&mut ast, parse_quote!(::runng::msg::NngDecode));
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let (body, header) = match &ast.data {
+        Data::Struct(data) => {
+            let body = decode_fields(quote!(#name), &data.fields, false);
+            let header = decode_fields(quote!(#name), &data.fields, true);
+            (quote!(Ok(#body)), quote!(Ok(#header)))
+        }
+        Data::Enum(data) => {
+            let variants: Vec<_> = data.variants.iter().collect();
+            let body = decode_variants(name, &variants, false);
+            let header = decode_variants(name, &variants, true);
+            (body, header)
+        }
+        Data::Union(_) => panic!("NngDecode doesn't support unions"),
+    };
+
+    let gen = quote! {
+        impl #impl_generics ::runng::msg::NngDecode for #name #ty_generics #where_clause {
+            fn decode(msg: &mut ::runng::msg::NngMsg) -> ::runng::Result<Self> {
+                #body
+            }
+            fn decode_header(msg: &mut ::runng::msg::NngMsg) -> ::runng::Result<Self> {
+                #header
+            }
+        }
+    };
+    gen.into()
+}
+
+// Require every type parameter to implement the derived trait
+fn add_bounds(ast: &mut DeriveInput, bound: syn::TypeParamBound) {
+    for param in ast.generics.type_params_mut() {
+        param.bounds.push(bound.clone());
+    }
+}
+
+// Whether field has `#[nng(header)]` attribute
+fn is_header(field: &Field) -> bool {
+    let mut header = false;
+    for attr in field.attrs.iter() {
+        match attr.parse_meta() {
+            Ok(Meta::List(list)) if list.ident == "nng" => {
+                for nested in list.nested.iter() {
+                    match nested {
+                        NestedMeta::Meta(Meta::Word(word)) if word == "header" => header = true,
+                        _ => panic!("Unsupported nng attribute"),
+                    }
+                }
+            }
+            _ => {} // Documentation comments, etc.
+        }
+    }
+    header
+}
+
+// Expressions to access each field: `self.name`/`self.0` for structs, bindings for enum variants
+fn field_accessors(fields: &Fields, prefix: TokenStream2) -> Vec<TokenStream2> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => quote!(#prefix #ident),
+            None => {
+                let index = Index::from(i);
+                quote!(#prefix #index)
+            }
+        })
+        .collect()
+}
+
+fn variant_bindings(fields: &Fields) -> Vec<Ident> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => ident.clone(),
+            None => Ident::new(&format!("__field{}", i), syn::export::Span::call_site()),
+        })
+        .collect()
+}
+
+// `(a, b)` or `{ a, b }` pattern to destructure a variant
+fn variant_pattern(fields: &Fields, bindings: &[Ident]) -> TokenStream2 {
+    match fields {
+        Fields::Named(_) => quote!({ #(#bindings),* }),
+        Fields::Unnamed(_) => quote!(( #(#bindings),* )),
+        Fields::Unit => quote!(),
+    }
+}
+
+fn encode_fields(fields: &Fields, accessors: &[TokenStream2], all_header: bool) -> TokenStream2 {
+    let statements = fields.iter().zip(accessors).map(|(field, accessor)| {
+        if all_header || is_header(field) {
+            quote!(::runng::msg::NngEncode::encode_header(&#accessor, msg)?;)
+        } else {
+            quote!(::runng::msg::NngEncode::encode(&#accessor, msg)?;)
+        }
+    });
+    quote!(#(#statements)*)
+}
+
+fn encode_variants(name: &Ident, variants: &[&Variant], header: bool) -> TokenStream2 {
+    let arms = variants.iter().enumerate().map(|(i, variant)| {
+        let ident = &variant.ident;
+        let index = i as u32;
+        let bindings = variant_bindings(&variant.fields);
+        let pattern = variant_pattern(&variant.fields, &bindings);
+        // Bindings are already references
+        let accessors: Vec<_> = bindings.iter().map(|binding| quote!(*#binding)).collect();
+        let fields = encode_fields(&variant.fields, &accessors, header);
+        let tag = if header {
+            quote!(msg.header_append_u32(#index)?;)
+        } else {
+            quote!(msg.append_u32(#index)?;)
+        };
+        quote! {
+            #name::#ident #pattern => {
+                #tag
+                #fields
+            }
+        }
+    });
+    quote! {
+        match self {
+            #(#arms)*
+        }
+    }
+}
+
+fn decode_fields(path: TokenStream2, fields: &Fields, all_header: bool) -> TokenStream2 {
+    let values = fields.iter().map(|field| {
+        let value = if all_header || is_header(field) {
+            quote!(::runng::msg::NngDecode::decode_header(msg)?)
+        } else {
+            quote!(::runng::msg::NngDecode::decode(msg)?)
+        };
+        match &field.ident {
+            Some(ident) => quote!(#ident: #value),
+            None => value,
+        }
+    });
+    match fields {
+        Fields::Named(_) => quote!(#path { #(#values),* }),
+        Fields::Unnamed(_) => quote!(#path ( #(#values),* )),
+        Fields::Unit => path,
+    }
+}
+
+fn decode_variants(name: &Ident, variants: &[&Variant], header: bool) -> TokenStream2 {
+    let arms = variants.iter().enumerate().map(|(i, variant)| {
+        let ident = &variant.ident;
+        let index = i as u32;
+        let value = decode_fields(quote!(#name::#ident), &variant.fields, header);
+        quote!(#index => Ok(#value),)
+    });
+    let tag = if header {
+        quote!(<u32 as ::runng::msg::NngDecode>::decode_header(msg)?)
+    } else {
+        quote!(<u32 as ::runng::msg::NngDecode>::decode(msg)?)
+    };
+    let name_str = name.to_string();
+    quote! {
+        match #tag {
+            #(#arms)*
+            index => Err(::runng::Error::Decode(format!("Invalid variant {} for {}", index, #name_str))),
+        }
+    }
+}
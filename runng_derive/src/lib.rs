@@ -2,6 +2,9 @@
 
 extern crate proc_macro;
 
+mod encode;
+mod service;
+
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::{Ident, Lit, Meta, MetaNameValue};
@@ -22,6 +25,32 @@ pub fn derive_nng_msg(_tokens: TokenStream) -> TokenStream {
     _derive_nng_msg()
 }
 
+/// Adds `impl runng::msg::NngEncode`.  Fields marked `#[nng(header)]` are written to the message header,
+/// which doesn't survive being sent through a socket (see `runng::msg::NngEncode`).
+#[proc_macro_derive(NngEncode, attributes(nng))]
+pub fn derive_nng_encode(tokens: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(tokens).unwrap();
+    encode::gen_encode_impl(ast)
+}
+
+/// Adds `impl runng::msg::NngDecode`.  Fields marked `#[nng(header)]` are read from the message header.
+#[proc_macro_derive(NngDecode, attributes(nng))]
+pub fn derive_nng_decode(tokens: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(tokens).unwrap();
+    encode::gen_decode_impl(ast)
+}
+
+/// Generates an RPC client and server for a trait.  See `runng::rpc`.
+///
+/// For `trait Calculator` this rewrites each method to return `runng::rpc::RpcFuture` and adds:
+/// - `CalculatorClient` that implements `Calculator` by calling a remote service
+/// - `CalculatorServer<T: Calculator>` that implements `runng::rpc::Service`
+#[proc_macro_attribute]
+pub fn service(_args: TokenStream, tokens: TokenStream) -> TokenStream {
+    let item: syn::ItemTrait = syn::parse(tokens).unwrap();
+    service::gen_service(item)
+}
+
 fn derive_nng_opts<F>(tokens: TokenStream, gen_impl: F) -> TokenStream
 where
     F: Fn(&syn::Ident, &str) -> TokenStream,
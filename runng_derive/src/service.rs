@@ -0,0 +1,184 @@
+//! `service` attribute for RPC traits.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_quote, FnArg, Ident, ItemTrait, ReturnType, TraitItem, Type};
+
+struct Method {
+    ident: Ident,
+    /// Name used to compute the method id: `Trait.method`
+    name: String,
+    const_ident: Ident,
+    args: Vec<Type>,
+    output: Type,
+}
+
+pub fn gen_service(mut item: ItemTrait) -> TokenStream {
+    let trait_ident = item.ident.clone();
+    let mut methods: Vec<Method> = Vec::new();
+
+    // Rewrite every method to return `RpcFuture` and collect its signature
+    for trait_item in item.items.iter_mut() {
+        let method = match trait_item {
+            TraitItem::Method(method) => method,
+            _ => panic!("service traits may only contain methods"),
+        };
+        let sig = &mut method.sig;
+        let ident = sig.ident.clone();
+        if method.default.is_some() {
+            panic!(
+                "service method `{}` can't have a default implementation",
+                ident
+            );
+        }
+        let mut inputs = sig.decl.inputs.iter();
+        match inputs.next() {
+            Some(FnArg::SelfRef(receiver)) if receiver.mutability.is_none() => {}
+            _ => panic!("service method `{}` must take `&self`", ident),
+        }
+        let args = inputs
+            .map(|arg| match arg {
+                FnArg::Captured(arg) => arg.ty.clone(),
+                _ => panic!("service method `{}` has unsupported argument", ident),
+            })
+            .collect();
+        let output: Type = match &sig.decl.output {
+            ReturnType::Default => parse_quote!(()),
+            ReturnType::Type(_, ty) => (**ty).clone(),
+        };
+        sig.asyncness = None;
+        sig.decl.output = parse_quote!(-> ::runng::rpc::RpcFuture<#output>);
+
+        let name = format!("{}.{}", trait_ident, ident);
+        let const_ident = Ident::new(&ident.to_string().to_uppercase(), Span::call_site());
+        methods.push(Method {
+            ident,
+            name,
+            const_ident,
+            args,
+            output,
+        });
+    }
+
+    let vis = &item.vis;
+    let client = Ident::new(&format!("{}Client", trait_ident), Span::call_site());
+    let server = Ident::new(&format!("{}Server", trait_ident), Span::call_site());
+
+    let consts = methods.iter().map(|method| {
+        let const_ident = &method.const_ident;
+        let name = &method.name;
+        let doc = format!(
+            "Name of method `{}`.  See `runng::rpc::method_id()` for its id.",
+            method.name
+        );
+        quote! {
+            #[doc = #doc]
+            pub const #const_ident: &'static str = #name;
+        }
+    });
+
+    let client_methods = methods.iter().map(|method| {
+        let ident = &method.ident;
+        let const_ident = &method.const_ident;
+        let output = &method.output;
+        let types = &method.args;
+        let names = arg_names(method);
+        let names2 = names.clone();
+        quote! {
+            fn #ident(&self, #(#names: #types),*) -> ::runng::rpc::RpcFuture<#output> {
+                self.client.call(#client::#const_ident, &(#(#names2,)*))
+            }
+        }
+    });
+
+    let server_arms = methods.iter().map(|method| {
+        let ident = &method.ident;
+        let const_ident = &method.const_ident;
+        let types = &method.args;
+        let names = arg_names(method);
+        let names2 = names.clone();
+        // Ids are computed by runng so they can't differ from `method_id()`
+        quote! {
+            if method == ::runng::rpc::method_id(#client::#const_ident) {
+                match <(#(#types,)*) as ::runng::msg::NngDecode>::decode(&mut request) {
+                    Ok((#(#names,)*)) => ::runng::rpc::encode_reply(self.service.#ident(#(#names2),*)),
+                    Err(err) => ::runng::rpc::bad_request(err),
+                }
+            } else
+        }
+    });
+
+    let method_names = methods.iter().map(|method| &method.name);
+    let client_doc = format!("Calls `{}` methods of a remote `{}`.", trait_ident, server);
+    let server_doc = format!(
+        "`Service` that serves requests from `{}` using a `{}` implementation.",
+        client, trait_ident
+    );
+
+    let gen = quote! {
+        #item
+
+        #[doc = #client_doc]
+        #[derive(Clone, Debug)]
+        #vis struct #client {
+            client: ::runng::rpc::RpcClient,
+        }
+
+        impl #client {
+            #(#consts)*
+
+            pub fn new<S: ::runng::GetSocket>(socket: &S) -> Self {
+                Self {
+                    client: ::runng::rpc::RpcClient::new(socket),
+                }
+            }
+        }
+
+        impl From<::runng::rpc::RpcClient> for #client {
+            fn from(client: ::runng::rpc::RpcClient) -> Self {
+                Self { client }
+            }
+        }
+
+        impl #trait_ident for #client {
+            #(#client_methods)*
+        }
+
+        #[doc = #server_doc]
+        #[derive(Debug)]
+        #vis struct #server<T> {
+            service: T,
+        }
+
+        impl<T> #server<T> {
+            pub fn new(service: T) -> Self {
+                Self { service }
+            }
+        }
+
+        impl<T: #trait_ident + Send + Sync + 'static> ::runng::rpc::Service for #server<T> {
+            fn methods(&self) -> Vec<&str> {
+                vec![#(#method_names),*]
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn call(
+                &self,
+                method: ::runng::rpc::MethodId,
+                mut request: ::runng::msg::NngMsg,
+            ) -> ::runng::rpc::RpcFuture<::runng::msg::NngMsg> {
+                #(#server_arms)* {
+                    ::runng::rpc::unknown_method(method)
+                }
+            }
+        }
+    };
+    gen.into()
+}
+
+fn arg_names(method: &Method) -> Vec<Ident> {
+    (0..method.args.len())
+        .map(|i| Ident::new(&format!("__arg{}", i), Span::call_site()))
+        .collect()
+}